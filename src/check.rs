@@ -8,8 +8,8 @@ fn nothing() {}
 
 #[inline]
 pub fn ensure_minimum_arity(
-    at_least: u8,
-    got: u8,
+    at_least: usize,
+    got: usize,
 ) -> Result<()> {
     (at_least <= got).then(nothing).ok_or(
         Error::MinimumArityMismatch {
@@ -19,8 +19,28 @@ pub fn ensure_minimum_arity(
     )
 }
 
+/// For built-ins with optional arguments. Getting too many is
+/// reported as not getting the full amount
 #[inline]
-pub fn ensure_exact_arity(want: u8, got: u8) -> Result<()> {
+pub fn ensure_arity_between(
+    at_least: usize,
+    at_most: usize,
+    got: usize,
+) -> Result<()> {
+    ensure_minimum_arity(at_least, got)?;
+    (got <= at_most).then(nothing).ok_or(
+        Error::ExactArityMismatch {
+            expected: at_most,
+            received: got,
+        },
+    )
+}
+
+#[inline]
+pub fn ensure_exact_arity(
+    want: usize,
+    got: usize,
+) -> Result<()> {
     (want == got).then(nothing).ok_or(
         Error::ExactArityMismatch {
             expected: want,
@@ -28,3 +48,21 @@ pub fn ensure_exact_arity(want: u8, got: u8) -> Result<()> {
         },
     )
 }
+
+/// For lambdas, which take their `required` arguments followed
+/// by up to `optional` ones, or by any amount if `variadic`
+#[inline]
+pub fn ensure_lambda_arity(
+    required: usize,
+    optional: usize,
+    variadic: bool,
+    got: usize,
+) -> Result<()> {
+    if variadic {
+        ensure_minimum_arity(required, got)
+    } else if optional == 0 {
+        ensure_exact_arity(required, got)
+    } else {
+        ensure_arity_between(required, required + optional, got)
+    }
+}
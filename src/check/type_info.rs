@@ -10,8 +10,18 @@ impl Typed for Expression {
     fn rough_type(&self) -> &'static str {
         match self {
             Expression::List(_) => "list",
+            Expression::Map(_) => "map",
+            Expression::Set(_) => "set",
+            Expression::Quote(_) => "quote",
+            Expression::Quasiquote(_) => "quasiquote",
+            Expression::Unquote(_) => "unquote",
+            Expression::UnquoteSplicing(_) => "unquote-splicing",
+            Expression::DefMacro(_) => "defmacro",
             Expression::Lambda(_) => "lambda",
+            Expression::Do(_) => "do",
+            Expression::Match(_) => "match",
             Expression::Binding(_) => "binding",
+            Expression::Let(_) => "let",
             Expression::Atom(atom) => atom.rough_type(),
             Expression::Application(_) => "application",
             Expression::If(_) | Expression::IfElse(_) => "if",
@@ -27,12 +37,57 @@ impl Typed for BuiltIn {
             BuiltIn::Times => "*",
             BuiltIn::Divide => "/",
             BuiltIn::Equal => "=",
+            BuiltIn::NotEqual => "not=",
+            BuiltIn::Less => "<",
+            BuiltIn::Greater => ">",
+            BuiltIn::LessEqual => "<=",
+            BuiltIn::GreaterEqual => ">=",
             BuiltIn::Not => "not",
             BuiltIn::And => "and",
             BuiltIn::Or => "or",
             BuiltIn::Remainder => "%",
+            BuiltIn::Quot => "quot",
+            BuiltIn::Rem => "rem",
+            BuiltIn::Str => "str",
+            BuiltIn::StringLength => "string-length",
+            BuiltIn::Substring => "substring",
+            BuiltIn::Split => "split",
+            BuiltIn::Join => "join",
+            BuiltIn::UpperCase => "upper-case",
+            BuiltIn::LowerCase => "lower-case",
+            BuiltIn::Trim => "trim",
+            BuiltIn::StartsWith => "starts-with?",
+            BuiltIn::Contains => "contains?",
+            BuiltIn::MacroExpand => "macroexpand",
+            BuiltIn::Get => "get",
+            BuiltIn::Assoc => "assoc",
+            BuiltIn::Dissoc => "dissoc",
+            BuiltIn::Keys => "keys",
+            BuiltIn::Vals => "vals",
+            BuiltIn::Merge => "merge",
+            BuiltIn::Conj => "conj",
+            BuiltIn::Replace => "replace",
+            BuiltIn::ExactToInexact => "exact->inexact",
+            BuiltIn::Rationalize => "rationalize",
             BuiltIn::Count => "count",
             BuiltIn::Cond => "cond",
+            BuiltIn::List => "list",
+            BuiltIn::Cons => "cons",
+            BuiltIn::First => "first",
+            BuiltIn::Rest => "rest",
+            BuiltIn::Nth => "nth",
+            BuiltIn::Length => "length",
+            BuiltIn::IsEmpty => "empty?",
+            BuiltIn::Append => "append",
+            BuiltIn::Reverse => "reverse",
+            BuiltIn::Last => "last",
+            BuiltIn::Map => "map",
+            BuiltIn::Filter => "filter",
+            BuiltIn::Reduce => "reduce",
+            BuiltIn::Apply => "apply",
+            BuiltIn::Any => "any?",
+            BuiltIn::Every => "every?",
+            BuiltIn::ForEach => "for-each",
         }
     }
 }
@@ -40,7 +95,9 @@ impl Typed for BuiltIn {
 impl Typed for Atom {
     fn rough_type(&self) -> &'static str {
         match self {
-            Atom::Number(_) => "number",
+            Atom::Integer(_) | Atom::BigInteger(_) => "integer",
+            Atom::Rational(_) => "rational",
+            Atom::Number(_) => "float",
             Atom::Identifier(_) => "identifier",
             Atom::Symbol(_) => "symbol",
             Atom::String(_) => "string",
@@ -52,6 +109,7 @@ impl Typed for Atom {
                 }
             }
             Atom::BuiltIn(built_in) => built_in.rough_type(),
+            Atom::NativeFn(_) => "native function",
             Atom::Nil => "nil",
         }
     }
@@ -1,4 +1,8 @@
-use crate::SmallString;
+mod syntax;
+
+pub use syntax::{Expected, SyntaxContext, SyntaxError};
+
+use crate::{Location, SmallString};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -14,13 +18,73 @@ pub enum Error {
     #[error(
         "Arity mismatch: expected {expected}, received {received}"
     )]
-    ExactArityMismatch { expected: u8, received: u8 },
+    ExactArityMismatch { expected: usize, received: usize },
     #[error(
         "Arity mismatch: expected at least {at_least}, received {received}"
     )]
-    MinimumArityMismatch { at_least: u8, received: u8 },
+    MinimumArityMismatch { at_least: usize, received: usize },
+    /// A list destructured with a pattern of a different
+    /// length
+    #[error(
+        "Destructuring error: expected a list of {expected} items, received {received}"
+    )]
+    ExactLengthMismatch { expected: usize, received: usize },
+    #[error(
+        "Destructuring error: expected a list of at least {at_least} items, received {received}"
+    )]
+    MinimumLengthMismatch { at_least: usize, received: usize },
+    /// A value other than the literal it was destructured
+    /// with, which is shown as written
+    #[error("Destructuring error: expected {0}")]
+    LiteralMismatch(SmallString),
+    #[error("Index {index} out of bounds for a list of length {length}")]
+    IndexOutOfBounds { index: i64, length: usize },
+    /// A character index before the start or past the end of a
+    /// string
+    #[error("Index {index} out of bounds for a string of length {length}")]
+    StringIndexOutOfBounds { index: i64, length: usize },
+    /// The end of a substring before its start
+    #[error("Substring end {end} is before its start {start}")]
+    ReversedRange { start: i64, end: i64 },
+    /// An integer too large for an `i64` was given where one
+    /// is expected
+    #[error("Integer overflow")]
+    IntegerOverflow,
+    #[error("Division by zero")]
+    DivisionByZero,
+    #[error("Unquote outside of a quasiquote")]
+    UnquoteOutsideQuasiquote,
+    /// `~@` was used where a single expression goes, e.g. as
+    /// the condition of an `if`
+    #[error(
+        "Unquote-splicing outside of a list or application"
+    )]
+    SpliceOutsideSequence,
+    /// Code built by a macro that doesn't have the shape of the
+    /// form it starts with, shown as it was built
+    #[error("Malformed {form}: {code}")]
+    MalformedCode {
+        form: &'static str,
+        code: SmallString,
+    },
     #[error("Unknown symbol {0}")]
     UnknownSymbol(SmallString),
-    #[error("Parsing error: {0}")]
-    ParsingError(String),
+    #[error("Parsing error at {0}")]
+    ParsingError(SyntaxError),
+    #[error("{location}: {error}")]
+    Located {
+        location: Location,
+        error: Box<Error>,
+    },
+}
+
+impl Error {
+    /// The error itself, stripped of the location it happened
+    /// at, if any
+    pub fn inner(&self) -> &Error {
+        match self {
+            Error::Located { error, .. } => error.inner(),
+            other => other,
+        }
+    }
 }
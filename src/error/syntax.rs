@@ -0,0 +1,357 @@
+use std::fmt::{self, Write};
+
+use crate::{
+    parser::{ParseError, ParseErrorKind},
+    Span,
+};
+
+/// Why the parser rejected its input.
+///
+/// Use [`SyntaxError::render`] to show it along with the source
+/// line it points to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+    /// Where parsing failed
+    pub span: Span,
+    /// What would have been accepted at that point
+    pub expected: Vec<Expected>,
+    /// What was being parsed when it failed, innermost first
+    pub context: Vec<SyntaxContext>,
+}
+
+/// Something the parser would have accepted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expected {
+    /// A specific character, e.g. the `[` opening `let`
+    /// bindings
+    Char(char),
+    /// `)`, closing an application or special form
+    ClosingParen,
+    /// `]`, closing a list or parameters
+    ClosingBracket,
+    /// `}`, closing a map or set
+    ClosingBrace,
+    /// The `"` closing a string
+    ClosingQuote,
+    /// The `|#` closing a block comment
+    EndOfComment,
+    /// The end of the input, after a complete expression
+    EndOfInput,
+    /// Any expression, e.g. the value given to `def`
+    Expression,
+    /// A name, e.g. that of a macro or symbol
+    Identifier,
+    /// What a value gets bound to: an identifier, `_`, or a
+    /// list or map pattern
+    Pattern,
+    /// A list or map pattern binding each of its identifiers
+    /// once, unlike `[x x]`
+    DistinctIdentifiers,
+    /// The `[...]` parameters of a lambda or macro
+    Parameters,
+    /// What follows a backslash within a string, e.g. `n`
+    EscapeSequence,
+    /// Digits other than zero under the `/` of a rational
+    NonZeroDenominator,
+}
+
+/// A construct being parsed, such as `"let bindings"`, and
+/// where it starts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxContext {
+    pub label: &'static str,
+    pub span: Span,
+}
+
+impl SyntaxError {
+    /// Gathers what the parser reported about a failure to parse
+    /// `source`
+    pub(crate) fn from_parse_error(
+        source: &str,
+        error: ParseError<'_>,
+        source_id: usize,
+    ) -> Self {
+        // The first entry is where parsing failed, while the
+        // ones after it were added as the error bubbled up
+        let failed_at = error.errors.first().map(|(at, _)| *at);
+        let mut expected = vec![];
+        let mut context = vec![];
+
+        for (remaining, kind) in error.errors {
+            match kind {
+                ParseErrorKind::Context(label) => {
+                    context.push(SyntaxContext {
+                        label,
+                        span: Span::at(
+                            source, remaining, source_id,
+                        ),
+                    });
+                }
+                // Only what failed right at the error's position
+                // could have been expected there
+                ParseErrorKind::Expected(item)
+                    if Some(remaining) == failed_at
+                        && !expected.contains(&item) =>
+                {
+                    expected.push(item)
+                }
+                ParseErrorKind::Expected(_)
+                | ParseErrorKind::Nom(_) => {}
+            }
+        }
+
+        Self {
+            span: Span::at(
+                source,
+                failed_at.unwrap_or(""),
+                source_id,
+            ),
+            expected,
+            context,
+        }
+    }
+
+    /// A human-readable message pointing at the offending part
+    /// of `source`, which must be what was being parsed, e.g.:
+    ///
+    /// ```text
+    /// 1:7: expected ')' while parsing application
+    /// (+ 1 2
+    ///       ^
+    /// ```
+    pub fn render(&self, source: &str) -> String {
+        let mut rendered = self.to_string();
+
+        let line_start = source[..self.span.start]
+            .rfind('\n')
+            .map_or(0, |idx| idx + 1);
+        let line = source[line_start..]
+            .lines()
+            .next()
+            .unwrap_or_default();
+        // Underline the whole offending span, or at least one
+        // character
+        let width = source[self.span.start..self.span.end]
+            .chars()
+            .count()
+            .max(1);
+
+        // Writing into a String can't fail
+        let _ = write!(
+            rendered,
+            "\n{line}\n{:>indent$}",
+            "^".repeat(width),
+            indent = self.span.column - 1 + width,
+        );
+
+        for context in self.context.iter().skip(1) {
+            let _ = write!(
+                rendered,
+                "\nwithin {} starting at {}:{}",
+                context.label,
+                context.span.line,
+                context.span.column
+            );
+        }
+
+        rendered
+    }
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: ", self.span.line, self.span.column)?;
+
+        if self.expected.is_empty() {
+            f.write_str("unexpected input")?;
+        } else {
+            f.write_str("expected ")?;
+            for (idx, expected) in
+                self.expected.iter().enumerate()
+            {
+                if idx > 0 {
+                    f.write_str(" or ")?;
+                }
+                write!(f, "{expected}")?;
+            }
+        }
+
+        if let Some(context) = self.context.first() {
+            write!(f, " while parsing {}", context.label)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expected::Char(ch) => write!(f, "'{ch}'"),
+            Expected::ClosingParen => f.write_str("')'"),
+            Expected::ClosingBracket => f.write_str("']'"),
+            Expected::ClosingBrace => f.write_str("'}'"),
+            Expected::ClosingQuote => f.write_str("'\"'"),
+            Expected::EndOfComment => f.write_str("'|#'"),
+            Expected::EndOfInput => f.write_str("end of input"),
+            Expected::Expression => f.write_str("an expression"),
+            Expected::Identifier => f.write_str("an identifier"),
+            Expected::Pattern => f.write_str("a pattern"),
+            Expected::DistinctIdentifiers => f.write_str(
+                "a pattern binding each identifier once",
+            ),
+            Expected::Parameters => {
+                f.write_str("parameters in brackets")
+            }
+            Expected::EscapeSequence => {
+                f.write_str("an escape sequence")
+            }
+            Expected::NonZeroDenominator => {
+                f.write_str("a nonzero denominator")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Expected, SyntaxError};
+    use crate::{Error, Interpreter};
+
+    fn syntax_error(source: &str) -> SyntaxError {
+        match Interpreter::parse_program(source) {
+            Err(Error::ParsingError(error)) => error,
+            other => {
+                panic!("expected a syntax error, got {other:?}")
+            }
+        }
+    }
+
+    #[test]
+    fn reports_where_parsing_failed() {
+        let error = syntax_error("(def x\n  (+ 1 2)");
+
+        assert_eq!(
+            (
+                error.span.start,
+                error.span.line,
+                error.span.column
+            ),
+            (16, 2, 10)
+        );
+        assert_eq!(error.expected, [Expected::ClosingParen]);
+        assert_eq!(
+            error
+                .context
+                .iter()
+                .map(|context| context.label)
+                .collect::<Vec<_>>(),
+            ["definition"]
+        );
+
+        let error = syntax_error("(let [x 1 y] y)");
+        assert_eq!(error.span.column, 12);
+        assert_eq!(error.expected, [Expected::Expression]);
+        assert_eq!(
+            error
+                .context
+                .iter()
+                .map(|context| (
+                    context.label,
+                    context.span.column
+                ))
+                .collect::<Vec<_>>(),
+            [("let bindings", 6), ("let", 1)]
+        );
+
+        let error = syntax_error("(+ 1 2))");
+        assert_eq!(error.span.column, 8);
+        assert_eq!(error.expected, [Expected::EndOfInput]);
+        assert!(error.context.is_empty());
+
+        // Rather than dividing 1 by 0
+        let error = syntax_error("(+ 1/0 2)");
+        assert_eq!(error.span.column, 6);
+        assert_eq!(error.context[0].label, "rational");
+
+        // Malformed escape sequences point past the backslash
+        for source in [
+            r#"(str "a\q")"#,
+            r#"(str "a\u{zz}")"#,
+            r#"(str "a\u{12")"#,
+        ] {
+            let error = syntax_error(source);
+            assert_eq!(error.span.column, 9, "{source}");
+            assert_eq!(
+                error.expected,
+                [Expected::EscapeSequence]
+            );
+        }
+    }
+
+    #[test]
+    fn reports_what_special_forms_lack() {
+        for (source, column, expected) in [
+            ("(def)", 5, Expected::Pattern),
+            ("(def x)", 7, Expected::Expression),
+            ("(def [a &] 1)", 10, Expected::Pattern),
+            ("(defmacro)", 10, Expected::Identifier),
+            ("(defmacro m x)", 13, Expected::Parameters),
+            ("(fn x)", 5, Expected::Parameters),
+            ("(fn [x])", 8, Expected::Expression),
+            ("(if)", 4, Expected::Expression),
+            ("(if true)", 9, Expected::Expression),
+            ("(let [x 1])", 11, Expected::Expression),
+            ("(match 1 _)", 11, Expected::Expression),
+            (
+                "(match 1 [a [a]] 2)",
+                10,
+                Expected::DistinctIdentifiers,
+            ),
+            ("{:a 1 :b}", 9, Expected::Expression),
+            ("`", 2, Expected::Expression),
+            ("\"abc", 5, Expected::ClosingQuote),
+            ("#| open", 8, Expected::EndOfComment),
+        ] {
+            let error = syntax_error(source);
+            assert_eq!(
+                (error.span.line, error.span.column),
+                (1, column),
+                "{source}"
+            );
+            assert_eq!(error.expected, [expected], "{source}");
+        }
+    }
+
+    #[test]
+    fn renders_syntax_errors() {
+        let source = "(def x\n  (+ 1 2)";
+        assert_eq!(
+            syntax_error(source).render(source),
+            "2:10: expected ')' while parsing definition\n  (+ \
+             1 2)\n         ^"
+        );
+
+        let source = "(let [x 1 y] y)";
+        assert_eq!(
+            syntax_error(source).render(source),
+            "1:12: expected an expression while parsing let \
+             bindings\n(let [x 1 y] y)\n           ^\nwithin let \
+             starting at 1:1"
+        );
+
+        assert_eq!(
+            syntax_error("(+ 1 2))").to_string(),
+            "1:8: expected end of input"
+        );
+        assert_eq!(
+            syntax_error("(+ 1 2").to_string(),
+            "1:7: expected ')' while parsing application"
+        );
+        assert_eq!(
+            syntax_error("(+ 1 2) #_").to_string(),
+            "1:11: expected an expression while parsing \
+             discarded form"
+        );
+    }
+}
@@ -1,13 +1,20 @@
-use std::{fmt::Debug, hash::BuildHasherDefault};
+use std::{fmt::Debug, hash::BuildHasherDefault, mem, rc::Rc};
 
 use indexmap::IndexMap;
 use rustc_hash::FxHasher;
 
 use crate::{
-    expression::elements::{
-        Application, Atom, Binding, FnIdentifier, If, IfElse,
+    expression::{
+        builtin::call_symbol,
+        elements::{
+            Application, Atom, Binding, FnIdentifier, If,
+            IfElse, Lambda, Let, Match, Pattern,
+        },
+        BuiltIn, Map, Set,
     },
-    Error, Expression, Result, SmallString,
+    macros,
+    scope::Scope,
+    Error, Expression, Result, SmallString, Span, Typed,
 };
 
 #[derive(Debug, Default)]
@@ -18,24 +25,162 @@ pub struct Env {
         Expression,
         BuildHasherDefault<FxHasher>,
     >,
+    /// Macros, by name, along with the lambdas expanding them
+    macros: IndexMap<
+        SmallString,
+        Lambda,
+        BuildHasherDefault<FxHasher>,
+    >,
+    /// The innermost lexical scope we're currently evaluating
+    /// in, or `None` if at the top level
+    scope: Option<Rc<Scope>>,
+    /// An empty scope, captured by lambdas defined at the top
+    /// level
+    top_level: Rc<Scope>,
+    /// Span of the innermost application that failed while
+    /// evaluating, used to locate the error being propagated
+    error_span: Option<Span>,
 }
 
 impl Env {
+    /// Looks up what `identifier` refers to: a local binding,
+    /// a global one, or else the built-in of that name
     pub fn get(
         &self,
         identifier: SmallString,
     ) -> Result<Expression> {
-        self.get_ref(identifier).map(Clone::clone)
+        match self.get_ref(identifier) {
+            Err(Error::UnknownSymbol(identifier)) => {
+                Self::built_in(identifier)
+            }
+            found => found.cloned(),
+        }
     }
 
+    /// Looks up a local or global binding. Built-ins aren't
+    /// bindings, so they're only found by [`Env::get`]
     pub fn get_ref(
         &self,
         identifier: SmallString,
     ) -> Result<&Expression> {
-        self.bindings
-            .get(&identifier)
+        // Local bindings shadow global ones
+        let local = self
+            .scope
+            .as_ref()
+            .and_then(|scope| scope.resolve_symbol(&identifier));
+
+        match local {
+            Some(expression) => Ok(expression),
+            None => self
+                .bindings
+                .get(&identifier)
+                .ok_or(Error::UnknownSymbol(identifier)),
+        }
+    }
+
+    /// Looks up a global binding, or else the built-in of that
+    /// name, ignoring local scopes
+    #[cfg(feature = "bytecode")]
+    pub(crate) fn get_global(
+        &self,
+        identifier: &SmallString,
+    ) -> Result<Expression> {
+        match self.bindings.get(identifier) {
+            Some(expression) => Ok(expression.clone()),
+            None => Self::built_in(identifier.clone()),
+        }
+    }
+
+    /// The built-in named `identifier`, which is what it refers
+    /// to unless bound to something else
+    fn built_in(identifier: SmallString) -> Result<Expression> {
+        BuiltIn::from_name(&identifier)
+            .map(|built_in| {
+                Expression::Atom(Atom::BuiltIn(built_in))
+            })
             .ok_or(Error::UnknownSymbol(identifier))
     }
+
+    /// The global bindings, in the order they were first made
+    pub fn bindings(
+        &self,
+    ) -> impl Iterator<Item = (&SmallString, &Expression)> {
+        self.bindings.iter()
+    }
+
+    /// Binds `identifier` to `expression` in the global scope,
+    /// replacing any previous binding
+    pub fn insert(
+        &mut self,
+        identifier: SmallString,
+        expression: Expression,
+    ) {
+        let _ = self.bindings.insert(identifier, expression);
+    }
+
+    /// Binds `identifier` to a macro expanded by `lambda`,
+    /// replacing any previous one
+    pub(crate) fn insert_macro(
+        &mut self,
+        identifier: SmallString,
+        lambda: Lambda,
+    ) {
+        let _ = self.macros.insert(identifier, lambda);
+    }
+
+    pub(crate) fn get_macro(
+        &self,
+        identifier: &SmallString,
+    ) -> Option<&Lambda> {
+        self.macros.get(identifier)
+    }
+
+    /// The scope a lambda evaluated right now captures
+    fn closure_scope(&self) -> Rc<Scope> {
+        self.scope
+            .clone()
+            .unwrap_or_else(|| self.top_level_scope())
+    }
+
+    /// The empty scope captured by lambdas defined at the top
+    /// level
+    pub(crate) fn top_level_scope(&self) -> Rc<Scope> {
+        self.top_level.clone()
+    }
+
+    /// Takes the span of the innermost application that failed
+    /// since the last call to this function
+    pub fn take_error_span(&mut self) -> Option<Span> {
+        self.error_span.take()
+    }
+
+    /// Records `span` as the location of a failed evaluation.
+    /// Enclosing applications will fail as well, but only the
+    /// innermost one is useful for locating the error, so spans
+    /// recorded before are kept
+    pub(crate) fn blame<T>(
+        &mut self,
+        result: &Result<T>,
+        span: Option<Span>,
+    ) {
+        if result.is_err() && self.error_span.is_none() {
+            self.error_span = span;
+        }
+    }
+
+    /// Evaluates `expression` within the given scope, restoring
+    /// the current one afterwards
+    pub fn evaluate_in_scope(
+        &mut self,
+        scope: Option<Rc<Scope>>,
+        expression: Expression,
+    ) -> Result<Expression> {
+        let previous = mem::replace(&mut self.scope, scope);
+        let result = expression.evaluate(self);
+        self.scope = previous;
+
+        result
+    }
 }
 
 pub trait Evaluable {
@@ -56,104 +201,456 @@ impl Evaluable for Atom {
     }
 }
 
+/// What's left to do to evaluate an expression once whatever
+/// isn't in tail position has been evaluated
+pub(crate) enum Step {
+    /// The expression has been fully evaluated
+    Done(Expression),
+    /// `expression` is in tail position and must be evaluated
+    /// within `scope`
+    Continue {
+        scope: Option<Rc<Scope>>,
+        expression: Expression,
+    },
+}
+
+impl Step {
+    /// Evaluates what's left, if anything
+    pub(crate) fn finish(
+        self,
+        env: &mut Env,
+    ) -> Result<Expression> {
+        match self {
+            Step::Done(expression) => Ok(expression),
+            Step::Continue { scope, expression } => {
+                env.evaluate_in_scope(scope, expression)
+            }
+        }
+    }
+}
+
 impl Evaluable for If {
     fn evaluate(self, env: &mut Env) -> Result<Expression> {
+        self.step(env)?.finish(env)
+    }
+}
+
+impl If {
+    fn step(self, env: &mut Env) -> Result<Step> {
         let cond = matches!(
             self.condition.evaluate(env)?,
             Expression::Atom(Atom::Boolean(true))
         );
-        if cond {
-            self.do_this.evaluate(env)
+
+        Ok(if cond {
+            Step::Continue {
+                scope: env.scope.clone(),
+                expression: self.do_this,
+            }
         } else {
-            Ok(Expression::Atom(Atom::Nil))
-        }
+            Step::Done(Expression::Atom(Atom::Nil))
+        })
     }
 }
 
 impl Evaluable for IfElse {
     fn evaluate(self, env: &mut Env) -> Result<Expression> {
+        self.step(env)?.finish(env)
+    }
+}
+
+impl IfElse {
+    fn step(self, env: &mut Env) -> Result<Step> {
         let cond = matches!(
             self.condition.evaluate(env)?,
             Expression::Atom(Atom::Boolean(true))
         );
-        if cond {
-            self.if_true.evaluate(env)
-        } else {
-            self.if_false.evaluate(env)
-        }
+
+        Ok(Step::Continue {
+            scope: env.scope.clone(),
+            expression: if cond {
+                self.if_true
+            } else {
+                self.if_false
+            },
+        })
     }
 }
 
 impl Evaluable for Application {
     fn evaluate(self, env: &mut Env) -> Result<Expression> {
+        let span = self.span;
+        let result =
+            self.step(env).and_then(|step| step.finish(env));
+        env.blame(&result, Some(span));
+        result
+    }
+}
+
+impl Application {
+    /// Applies the function named by this application to its
+    /// arguments. Lambda bodies and `cond` branches are left
+    /// for the caller to evaluate
+    fn step(self, env: &mut Env) -> Result<Step> {
         match self.name {
-            FnIdentifier::BuiltIn(built_in) => {
-                built_in.apply(self.arguments, env)
+            FnIdentifier::BuiltIn(BuiltIn::Cond) => {
+                let step = match BuiltIn::cond_branch(
+                    self.arguments,
+                    env,
+                )? {
+                    Some(branch) => Step::Continue {
+                        scope: env.scope.clone(),
+                        expression: branch,
+                    },
+                    None => Step::Done(Expression::default()),
+                };
+
+                Ok(step)
+            }
+            FnIdentifier::BuiltIn(built_in) => built_in
+                .apply(self.arguments, env)
+                .map(Step::Done),
+            FnIdentifier::Symbol(symbol) => {
+                let arguments =
+                    evaluate_all(self.arguments, env)?;
+                call(
+                    &Expression::Atom(Atom::Symbol(symbol)),
+                    arguments,
+                    env,
+                )
             }
             FnIdentifier::Other(identifier) => {
-                let lambda = env.get(identifier)?.as_lambda()?;
-
-                lambda.apply(self.arguments, env)
+                let callee = env.get(identifier)?;
+                let arguments =
+                    evaluate_all(self.arguments, env)?;
+                call(&callee, arguments, env)
             }
+            FnIdentifier::Expression(callee) => {
+                let callee =
+                    Rc::unwrap_or_clone(callee).evaluate(env)?;
+                let arguments =
+                    evaluate_all(self.arguments, env)?;
+                call(&callee, arguments, env)
+            }
+        }
+    }
+}
+
+/// Calls `callee` with the given, already evaluated, arguments.
+/// This is how every value gets applied, whether by an
+/// application, a higher-order built-in or the bytecode VM.
+/// Lambda bodies are left for the caller to evaluate
+pub(crate) fn call(
+    callee: &Expression,
+    arguments: Vec<Expression>,
+    env: &mut Env,
+) -> Result<Step> {
+    match callee {
+        Expression::Lambda(lambda) => {
+            lambda.call(arguments, env)
+        }
+        Expression::Atom(Atom::NativeFn(native_fn)) => {
+            native_fn.call(arguments).map(Step::Done)
+        }
+        Expression::Atom(Atom::BuiltIn(built_in)) => {
+            built_in.call(arguments, env).map(Step::Done)
+        }
+        Expression::Atom(Atom::Symbol(symbol)) => {
+            call_symbol(symbol, arguments).map(Step::Done)
         }
+        other => Err(Error::TypeMismatch {
+            expected: "lambda",
+            received: other.rough_type(),
+        }),
     }
 }
 
+/// Evaluates each of the given expressions, in order
+pub(crate) fn evaluate_all(
+    expressions: Vec<Expression>,
+    env: &mut Env,
+) -> Result<Vec<Expression>> {
+    expressions
+        .into_iter()
+        .map(|expr| expr.evaluate(env))
+        .collect()
+}
+
+/// Evaluates every expression of a `do` block but the last,
+/// which is left to be evaluated in tail position
+fn step_do(
+    mut body: Vec<Expression>,
+    env: &mut Env,
+) -> Result<Step> {
+    let last = body.pop().unwrap_or_default();
+    for expression in body {
+        expression.evaluate(env)?;
+    }
+
+    Ok(Step::Continue {
+        scope: env.scope.clone(),
+        expression: last,
+    })
+}
+
 impl Evaluable for Binding {
     fn evaluate(self, env: &mut Env) -> Result<Expression> {
         let expression = self.expression.evaluate(env)?;
 
+        // Nothing gets bound unless the whole pattern matches
+        let mut bindings = vec![];
+        self.pattern.destructure(
+            expression.clone(),
+            &mut |identifier, value| {
+                bindings.push((identifier.clone(), value))
+            },
+        )?;
+
         // We'll allow binding shadowing so whether or not
         // this binding previously existed is not important
-        let _ = env
-            .bindings
-            .insert(self.identifier, expression.clone());
-
+        env.bindings.extend(bindings);
         Ok(expression)
     }
 }
 
-impl Evaluable for Expression {
+impl Evaluable for Let {
     fn evaluate(self, env: &mut Env) -> Result<Expression> {
-        match self {
-            Expression::Lambda(lambda) => {
-                Ok(Expression::Lambda(lambda))
-            }
-            Expression::Binding(binding) => {
-                binding.evaluate(env)
-            }
-            Expression::Atom(atom) => atom.evaluate(env),
-            Expression::Application(application) => {
-                application.evaluate(env)
-            }
-            Expression::If(if_expr) => if_expr.evaluate(env),
-            Expression::IfElse(if_else_expr) => {
-                if_else_expr.evaluate(env)
+        self.step(env)?.finish(env)
+    }
+}
+
+impl Let {
+    fn step(self, env: &mut Env) -> Result<Step> {
+        let mut scope = env.scope.clone();
+
+        // Each binding gets its own scope so that it's visible
+        // to the bindings that follow it, as well as to closures
+        // created while evaluating them
+        for (pattern, expression) in self.bindings {
+            let value = env
+                .evaluate_in_scope(scope.clone(), expression)?;
+
+            let (mut identifiers, mut values) = (vec![], vec![]);
+            pattern.destructure(
+                value,
+                &mut |identifier, value| {
+                    identifiers.push(identifier.clone());
+                    values.push(value);
+                },
+            )?;
+            scope = Some(Rc::new(Scope::new(
+                identifiers,
+                values,
+                scope,
+            )));
+        }
+
+        Ok(Step::Continue {
+            scope,
+            expression: self.body,
+        })
+    }
+}
+
+impl Evaluable for Match {
+    fn evaluate(self, env: &mut Env) -> Result<Expression> {
+        self.step(env)?.finish(env)
+    }
+}
+
+impl Match {
+    /// Picks the branch of the first clause whose pattern
+    /// matches and whose guard, if any, holds. Its bindings are
+    /// visible to both the guard and the branch
+    fn step(self, env: &mut Env) -> Result<Step> {
+        let value = self.expression.evaluate(env)?;
+
+        for clause in self.clauses {
+            if !clause.pattern.matches(&value) {
+                continue;
             }
-            Expression::List(list) => {
-                // Avoid processing the list until strictly
-                // necessary
-                Ok(Expression::List(list))
+            let (mut identifiers, mut values) = (vec![], vec![]);
+            clause.pattern.destructure(
+                value.clone(),
+                &mut |identifier, value| {
+                    identifiers.push(identifier.clone());
+                    values.push(value);
+                },
+            )?;
+
+            let scope = if identifiers.is_empty() {
+                env.scope.clone()
+            } else {
+                Some(Rc::new(Scope::new(
+                    identifiers,
+                    values,
+                    env.scope.clone(),
+                )))
+            };
+
+            if let Some(guard) = clause.guard {
+                let holds = matches!(
+                    env.evaluate_in_scope(scope.clone(), guard)?,
+                    Expression::Atom(Atom::Boolean(true))
+                );
+                if !holds {
+                    continue;
+                }
             }
+
+            return Ok(Step::Continue {
+                scope,
+                expression: clause.branch,
+            });
         }
+
+        Ok(Step::Done(Expression::Atom(Atom::Nil)))
     }
 }
 
-// TODO: transform this into a trait
-pub fn resolve_argument(
-    identifier: &SmallString,
-    fn_arguments: &[SmallString],
-    received_arguments: &[Expression],
-    env: &mut Env,
-) -> Result<Expression> {
-    let idx = fn_arguments
-        .iter()
-        .position(|arg| arg == identifier)
-        .ok_or_else(|| {
-            Error::UnknownSymbol(identifier.clone())
-        })?;
-
-    received_arguments[idx].clone().evaluate(env)
+impl Evaluable for Expression {
+    fn evaluate(self, env: &mut Env) -> Result<Expression> {
+        let scope = env.scope.clone();
+        // Span of the last form we've gone through, such as an
+        // application or an `if`, which is to blame for errors
+        // that happen in its tail position
+        let mut span = None;
+        let mut expression = self;
+
+        // Rather than recursing into expressions in tail
+        // position, we loop over them, switching to the scope
+        // they must be evaluated in. This way tail calls don't
+        // grow the stack, and loops can be written recursively
+        let result = loop {
+            let step = match expression {
+                Expression::Application(application) => {
+                    span = Some(application.span);
+                    application.step(env)
+                }
+                Expression::If(if_expr) => {
+                    span = Some(if_expr.span);
+                    if_expr.step(env)
+                }
+                Expression::IfElse(if_else_expr) => {
+                    span = Some(if_else_expr.span);
+                    if_else_expr.step(env)
+                }
+                Expression::Let(let_expr) => {
+                    span = Some(let_expr.span);
+                    let_expr.step(env)
+                }
+                Expression::Match(match_expr) => {
+                    span = Some(match_expr.span);
+                    match_expr.step(env)
+                }
+                Expression::Do(body) => step_do(body, env),
+                Expression::Lambda(lambda)
+                    if lambda.template.is_some() =>
+                {
+                    break Err(Error::UnquoteOutsideQuasiquote)
+                }
+                Expression::Lambda(mut lambda) => {
+                    // Closures capture the scope they're defined
+                    // in. Lambdas that were already evaluated
+                    // are values, which keep
+                    // the scope they've
+                    // captured before
+                    if lambda.captured.is_none() {
+                        lambda.captured =
+                            Some(env.closure_scope());
+                    }
+                    break Ok(Expression::Lambda(lambda));
+                }
+                Expression::Binding(binding) => {
+                    span = Some(binding.span);
+                    break binding.evaluate(env);
+                }
+                Expression::Atom(atom) => {
+                    break atom.evaluate(env)
+                }
+                Expression::List(list) => {
+                    // The elements of a list may refer to local
+                    // bindings, so they must be evaluated while
+                    // their scope is still around
+                    break evaluate_all(list, env)
+                        .map(Expression::List);
+                }
+                Expression::Map(map) => {
+                    let entries = map
+                        .iter()
+                        .map(|(key, value)| {
+                            Ok((
+                                key.clone().evaluate(env)?,
+                                value.clone().evaluate(env)?,
+                            ))
+                        })
+                        .collect::<Result<Map>>();
+                    break entries
+                        .map(Rc::new)
+                        .map(Expression::Map);
+                }
+                Expression::Quote(data) => {
+                    break Ok(Rc::unwrap_or_clone(data))
+                }
+                Expression::Quasiquote(template) => {
+                    break macros::fill(
+                        &template,
+                        &mut |form| form.clone().evaluate(env),
+                    );
+                }
+                Expression::Unquote(_)
+                | Expression::UnquoteSplicing(_) => {
+                    break Err(Error::UnquoteOutsideQuasiquote)
+                }
+                Expression::DefMacro(def_macro) => {
+                    let Binding {
+                        pattern: Pattern::Identifier(identifier),
+                        expression,
+                        ..
+                    } = *def_macro
+                    else {
+                        // Otherwise, it's unquoted outside of a
+                        // template
+                        break Err(
+                            Error::UnquoteOutsideQuasiquote,
+                        );
+                    };
+                    let lambda = expression
+                        .evaluate(env)
+                        .and_then(Expression::as_lambda);
+                    break lambda.map(|lambda| {
+                        env.insert_macro(identifier, lambda);
+                        Expression::default()
+                    });
+                }
+                Expression::Set(set) => {
+                    let items = set
+                        .iter()
+                        .map(|item| item.clone().evaluate(env))
+                        .collect::<Result<Set>>();
+                    break items
+                        .map(Rc::new)
+                        .map(Expression::Set);
+                }
+            };
+
+            match step {
+                Ok(Step::Done(value)) => break Ok(value),
+                Ok(Step::Continue {
+                    scope,
+                    expression: next,
+                }) => {
+                    env.scope = scope;
+                    expression = next;
+                }
+                Err(err) => break Err(err),
+            }
+        };
+
+        env.scope = scope;
+        env.blame(&result, span);
+        result
+    }
 }
 
 #[cfg(test)]
@@ -161,7 +658,7 @@ mod tests {
     use super::{Env, Evaluable};
     use crate::{
         expression::elements::Atom, parse_expression, Error,
-        Expression, Interpreter, Result,
+        Expression, Interpreter, Result, SmallString,
     };
 
     // TODO: finish converting test cases to use `parse_and_eval`
@@ -179,391 +676,2039 @@ mod tests {
 
     #[test]
     fn evaluates_bindings() {
-        let mut interp = Interpreter::new();
-        assert!(parse_expression("(def)").is_err());
-        assert!(parse_expression("(def x)").is_err());
-        assert!(parse_expression("(def 2 2)").is_err());
-
-        assert_eq!(
-            interp.parse_and_eval("(def five 5.0)").unwrap(),
-            Expression::Atom(Atom::Number(5.0))
-        );
-
-        assert_eq!(
-            // Ensure that we can retrieve previous bindings
-            // from our env
-            interp
-                .parse_and_eval("(def six (+ five 1.0))",)
-                .unwrap(),
-            Expression::Atom(Atom::Number(6.0))
-        );
+        for mut interp in Interpreter::all_backends() {
+            assert!(parse_expression("(def)").is_err());
+            assert!(parse_expression("(def x)").is_err());
+            assert!(parse_expression("(def 2 2)").is_err());
+
+            assert_eq!(
+                interp.parse_and_eval("(def five 5.0)").unwrap(),
+                Expression::Atom(Atom::Number(5.0))
+            );
+
+            assert_eq!(
+                // Ensure that we can retrieve previous bindings
+                // from our env
+                interp
+                    .parse_and_eval("(def six (+ five 1.0))",)
+                    .unwrap(),
+                Expression::Atom(Atom::Number(6.0))
+            );
+        }
     }
 
     #[test]
     fn evaluates_equality_correctly() {
-        // Must fail arity check
-        assert!(parse_and_eval("(=)").is_err());
-        assert!(parse_and_eval("(= 2)").is_err());
+        for mut interp in Interpreter::all_backends() {
+            // Must fail arity check
+            assert!(interp.parse_and_eval("(=)").is_err());
+            assert!(interp.parse_and_eval("(= 2)").is_err());
+
+            assert_eq!(
+                interp.parse_and_eval("(= 2 2)").unwrap(),
+                true.into()
+            );
+            assert_eq!(
+                interp.parse_and_eval("(= 2 3)").unwrap(),
+                false.into()
+            );
+            assert_eq!(
+                interp
+                    .parse_and_eval(
+                        "(= (+ 1 3) (+ 2 2) (- 6 2))"
+                    )
+                    .unwrap(),
+                true.into()
+            );
+        }
+    }
 
-        assert_eq!(
-            parse_and_eval("(= 2 2)").unwrap(),
-            true.into()
-        );
-        assert_eq!(
-            parse_and_eval("(= 2 3)").unwrap(),
-            false.into()
-        );
-        assert_eq!(
-            parse_and_eval("(= (+ 1 3) (+ 2 2) (- 6 2))")
-                .unwrap(),
-            true.into()
-        );
+    #[test]
+    fn evaluates_comparisons() {
+        for mut interp in Interpreter::all_backends() {
+            let mut eval = |input: &str| {
+                interp
+                    .parse_and_eval(input)
+                    .map(|expression| expression.to_string())
+            };
+
+            assert_eq!(eval("(< 1 2)").unwrap(), "true");
+            assert_eq!(eval("(< 2 2)").unwrap(), "false");
+            assert_eq!(eval("(<= 2 2)").unwrap(), "true");
+            assert_eq!(eval("(> 3 2)").unwrap(), "true");
+            assert_eq!(eval("(>= 1 2)").unwrap(), "false");
+
+            // Comparisons are chained
+            assert_eq!(eval("(< 1 2 3)").unwrap(), "true");
+            assert_eq!(eval("(< 1 3 2)").unwrap(), "false");
+            assert_eq!(eval("(<= 1 1 2 2)").unwrap(), "true");
+            assert_eq!(eval("(> 3 2 1)").unwrap(), "true");
+            assert_eq!(eval("(>= 3 3 4)").unwrap(), "false");
+
+            assert_eq!(eval("(not= 1 2)").unwrap(), "true");
+            assert_eq!(eval("(not= 1 1 1)").unwrap(), "false");
+            assert_eq!(eval("(not= 1 1 2)").unwrap(), "true");
+            assert_eq!(eval("(!= :a :a)").unwrap(), "false");
+
+            assert_eq!(
+                eval("(let [n 1] (if (< n 2) :base :step))")
+                    .unwrap(),
+                ":base"
+            );
+
+            assert_eq!(
+                eval("(< 1)").unwrap_err().inner(),
+                &Error::MinimumArityMismatch {
+                    at_least: 2,
+                    received: 1
+                }
+            );
+            assert_eq!(
+                eval("(< 2 1 :three)").unwrap_err().inner(),
+                &Error::TypeMismatch {
+                    expected: "number",
+                    received: "symbol"
+                }
+            );
+        }
     }
 
     #[test]
     fn evaluates_and_operator_correctly() {
-        // Must fail arity check
-        assert!(parse_and_eval("(and)").is_err());
-        assert!(parse_and_eval("(and 2)").is_err());
-
-        // Must fail type check
-        assert_eq!(
-            parse_and_eval("(and 2 2)").unwrap_err(),
-            Error::TypeMismatch {
-                expected: "boolean",
-                received: "number"
-            }
-        );
-
-        assert_eq!(
-            parse_and_eval("(and true true)").unwrap(),
-            true.into()
-        );
-        assert_eq!(
-            parse_and_eval("(and false true)").unwrap(),
-            false.into()
-        );
-        assert_eq!(parse_and_eval("(and true true true true true true true true false true)").unwrap(), false.into());
-        assert_eq!(
-            parse_and_eval(
-                "(and (= 2 2) (= 3 3) (= (= 2 5) (=7 8))))"
-            )
-            .unwrap(),
-            true.into()
-        );
-        assert_eq!(
-            parse_and_eval(
-                "(and (= 2 2) (= 2 3) (= (= 2 5) (=7 8))))"
-            )
-            .unwrap(),
-            false.into()
-        );
+        for mut interp in Interpreter::all_backends() {
+            // Must fail arity check
+            assert!(interp.parse_and_eval("(and)").is_err());
+            assert!(interp.parse_and_eval("(and 2)").is_err());
+
+            // Must fail type check
+            assert_eq!(
+                interp
+                    .parse_and_eval("(and 2 2)")
+                    .unwrap_err()
+                    .inner(),
+                &Error::TypeMismatch {
+                    expected: "boolean",
+                    received: "integer"
+                }
+            );
+
+            assert_eq!(
+                interp
+                    .parse_and_eval("(and true true)")
+                    .unwrap(),
+                true.into()
+            );
+            assert_eq!(
+                interp
+                    .parse_and_eval("(and false true)")
+                    .unwrap(),
+                false.into()
+            );
+            assert_eq!(interp.parse_and_eval("(and true true true true true true true true false true)").unwrap(), false.into());
+            assert_eq!(
+                interp
+                    .parse_and_eval(
+                        "(and (= 2 2) (= 3 3) (= (= 2 5) (=7 8)))"
+                    )
+                    .unwrap(),
+                true.into()
+            );
+            assert_eq!(
+                interp
+                    .parse_and_eval(
+                        "(and (= 2 2) (= 2 3) (= (= 2 5) (=7 8)))"
+                    )
+                    .unwrap(),
+                false.into()
+            );
+        }
     }
 
     #[test]
     fn evaluates_or_operator_correctly() {
-        // Must fail arity check
-        assert!(parse_and_eval("(or)").is_err());
-        assert!(parse_and_eval("(or 2)").is_err());
-
-        // Must fail type check
-        assert_eq!(
-            parse_and_eval("(or 2 true)").unwrap_err(),
-            Error::TypeMismatch {
-                expected: "boolean",
-                received: "number"
-            }
-        );
-
-        assert_eq!(
-            parse_and_eval("(or true true)").unwrap(),
-            true.into()
-        );
-        assert_eq!(
-            parse_and_eval("(or false true)").unwrap(),
-            true.into()
-        );
-        assert_eq!(
-            parse_and_eval(
-                "(or false false false false false true)"
-            )
-            .unwrap(),
-            true.into()
-        );
-        assert_eq!(
-            parse_and_eval(
-                "(or (= 2 2) (= 3 3) (= (= 2 5) (=7 8))))"
-            )
-            .unwrap(),
-            true.into()
-        );
-        assert_eq!(
-            parse_and_eval(
-                "(or (= 2 3) (= 3 3) (= (= 2 5) (=7 8))))"
-            )
-            .unwrap(),
-            true.into()
-        );
-        assert_eq!(
-            parse_and_eval(
-                "(or (= 2 3) (= 5 3) (= (= 2 5) (=7 8))))"
-            )
-            .unwrap(),
-            true.into()
-        );
+        for mut interp in Interpreter::all_backends() {
+            // Must fail arity check
+            assert!(interp.parse_and_eval("(or)").is_err());
+            assert!(interp.parse_and_eval("(or 2)").is_err());
+
+            // Must fail type check
+            assert_eq!(
+                interp
+                    .parse_and_eval("(or 2 true)")
+                    .unwrap_err()
+                    .inner(),
+                &Error::TypeMismatch {
+                    expected: "boolean",
+                    received: "integer"
+                }
+            );
+
+            assert_eq!(
+                interp.parse_and_eval("(or true true)").unwrap(),
+                true.into()
+            );
+            assert_eq!(
+                interp
+                    .parse_and_eval("(or false true)")
+                    .unwrap(),
+                true.into()
+            );
+            assert_eq!(
+                interp
+                    .parse_and_eval(
+                        "(or false false false false false true)"
+                    )
+                    .unwrap(),
+                true.into()
+            );
+            assert_eq!(
+                interp
+                    .parse_and_eval(
+                        "(or (= 2 2) (= 3 3) (= (= 2 5) (=7 8)))"
+                    )
+                    .unwrap(),
+                true.into()
+            );
+            assert_eq!(
+                interp
+                    .parse_and_eval(
+                        "(or (= 2 3) (= 3 3) (= (= 2 5) (=7 8)))"
+                    )
+                    .unwrap(),
+                true.into()
+            );
+            assert_eq!(
+                interp
+                    .parse_and_eval(
+                        "(or (= 2 3) (= 5 3) (= (= 2 5) (=7 8)))"
+                    )
+                    .unwrap(),
+                true.into()
+            );
+        }
     }
 
     #[test]
     fn evaluates_addition_correctly() {
-        assert_eq!(parse_and_eval("(+)").unwrap(), 0.0.into());
-
-        assert_eq!(parse_and_eval("(+ 3)").unwrap(), 3.0.into());
-
-        assert_eq!(parse_and_eval("(+ 5)").unwrap(), 5.0.into());
-
-        assert_eq!(
-            parse_and_eval("(+ (+ 3 5) (+ (if true 5 2) 2))")
-                .unwrap(),
-            15.0.into()
-        );
+        for mut interp in Interpreter::all_backends() {
+            assert_eq!(
+                interp.parse_and_eval("(+)").unwrap(),
+                0_i64.into()
+            );
+
+            assert_eq!(
+                interp.parse_and_eval("(+ 3)").unwrap(),
+                3_i64.into()
+            );
+
+            assert_eq!(
+                interp.parse_and_eval("(+ 5)").unwrap(),
+                5_i64.into()
+            );
+
+            assert_eq!(
+                interp
+                    .parse_and_eval(
+                        "(+ (+ 3 5) (+ (if true 5 2) 2))"
+                    )
+                    .unwrap(),
+                15_i64.into()
+            );
+        }
     }
 
     #[test]
     fn evaluates_remainder_operations() {
-        let mut interp = Interpreter::new();
-        assert_eq!(
-            interp.parse_and_eval("(% 5 2)").unwrap(),
-            1.0.into()
-        );
-        assert_eq!(
-            interp.parse_and_eval("(= (% 4 2) 0)").unwrap(),
-            true.into()
-        );
-        assert_eq!(
-            interp
-                .parse_and_eval("(= (% 4 2) (% 9 3) (% 33 11))")
-                .unwrap(),
-            true.into()
-        );
-
-        // Must fail arity check
-        interp.parse_and_eval("(%)").unwrap_err();
-        interp.parse_and_eval("(% 1)").unwrap_err();
-        interp.parse_and_eval("(% 1 4 5)").unwrap_err();
-
-        // Must fail type-check
-        interp.parse_and_eval("(% :ok :hey)").unwrap_err();
+        for mut interp in Interpreter::all_backends() {
+            assert_eq!(
+                interp.parse_and_eval("(% 5 2)").unwrap(),
+                1_i64.into()
+            );
+            assert_eq!(
+                interp.parse_and_eval("(= (% 4 2) 0)").unwrap(),
+                true.into()
+            );
+            assert_eq!(
+                interp
+                    .parse_and_eval(
+                        "(= (% 4 2) (% 9 3) (% 33 11))"
+                    )
+                    .unwrap(),
+                true.into()
+            );
+
+            // Must fail arity check
+            interp.parse_and_eval("(%)").unwrap_err();
+            interp.parse_and_eval("(% 1)").unwrap_err();
+            interp.parse_and_eval("(% 1 4 5)").unwrap_err();
+
+            // Must fail type-check
+            interp.parse_and_eval("(% :ok :hey)").unwrap_err();
+        }
     }
 
     #[test]
     fn evaluates_multiplication_correctly() {
-        let expr = parse_expression("(*)").unwrap().1;
-        assert_eq!(
-            expr.evaluate(&mut Env::default()).unwrap(),
-            Expression::Atom(Atom::Number(0.0))
-        );
+        for mut interp in Interpreter::all_backends() {
+            assert_eq!(
+                interp.parse_and_eval("(*)").unwrap(),
+                Expression::Atom(Atom::Integer(0))
+            );
+
+            assert_eq!(
+                interp.parse_and_eval("(* 3)").unwrap(),
+                Expression::Atom(Atom::Integer(3))
+            );
+
+            assert_eq!(
+                interp.parse_and_eval("(* 3 2)").unwrap(),
+                Expression::Atom(Atom::Integer(6))
+            );
+
+            assert_eq!(
+                interp.parse_and_eval("(* 3 2 1)").unwrap(),
+                Expression::Atom(Atom::Integer(6))
+            );
+
+            assert_eq!(
+                interp.parse_and_eval("(* 3 2 1 0)").unwrap(),
+                Expression::Atom(Atom::Integer(0))
+            );
+        }
+    }
 
-        let expr = parse_expression("(* 3)").unwrap().1;
-        assert_eq!(
-            expr.evaluate(&mut Env::default()).unwrap(),
-            Expression::Atom(Atom::Number(3.0))
-        );
+    #[test]
+    fn evaluates_subtraction_correctly() {
+        for mut interp in Interpreter::all_backends() {
+            assert_eq!(
+                interp.parse_and_eval("(-)").unwrap(),
+                Expression::Atom(Atom::Integer(0))
+            );
+
+            assert_eq!(
+                interp.parse_and_eval("(- 3)").unwrap(),
+                Expression::Atom(Atom::Integer(-3))
+            );
+
+            // FIXME: this is wrong
+            assert_eq!(
+                interp.parse_and_eval("(- 3 2)").unwrap(),
+                Expression::Atom(Atom::Integer(1))
+            );
+        }
+    }
 
-        let expr = parse_expression("(* 3 2)").unwrap().1;
-        assert_eq!(
-            expr.evaluate(&mut Env::default()).unwrap(),
-            Expression::Atom(Atom::Number(6.0))
-        );
+    #[test]
+    fn evaluates_integer_arithmetic() {
+        for mut interp in Interpreter::all_backends() {
+            let mut eval = |input: &str| {
+                interp
+                    .parse_and_eval(input)
+                    .map(|expression| expression.to_string())
+            };
+
+            // Integers stay exact past 2^53
+            assert_eq!(
+                eval("(+ 9007199254740992 1)").unwrap(),
+                "9007199254740993"
+            );
+            assert_eq!(
+                eval("(% 9007199254740993 2)").unwrap(),
+                "1"
+            );
+
+            // Mixing in a float gives a float
+            assert_eq!(eval("(+ 1 2.5)").unwrap(), "3.5");
+            assert_eq!(eval("(* 2 1.0)").unwrap(), "2.0");
+            assert_eq!(eval("(/ 6 3)").unwrap(), "2");
+            assert_eq!(eval("(/ 7 2)").unwrap(), "7/2");
+            assert_eq!(eval("(- 5)").unwrap(), "-5");
+
+            assert_eq!(eval("(quot 7 2)").unwrap(), "3");
+            assert_eq!(eval("(quot -7 2)").unwrap(), "-3");
+            assert_eq!(eval("(rem -7 2)").unwrap(), "-1");
+            assert_eq!(eval("(rem 7 -2)").unwrap(), "1");
+
+            // Numbers are compared by value
+            assert_eq!(eval("(= 1 1.0)").unwrap(), "true");
+            assert_eq!(
+                eval("(= 1 1.0000000000000002)").unwrap(),
+                "false"
+            );
+            assert_eq!(
+                eval("(= 9007199254740993 9007199254740992.0)")
+                    .unwrap(),
+                "false"
+            );
+            assert_eq!(
+                eval("(< 9007199254740992.0 9007199254740993)")
+                    .unwrap(),
+                "true"
+            );
+            assert_eq!(
+                eval("(= [1 [2]] [1.0 [2.0]])").unwrap(),
+                "true"
+            );
+            assert_eq!(eval("(< 1 1.5 2)").unwrap(), "true");
+
+            // Integers that overflow grow instead
+            assert_eq!(
+                eval("(+ 9223372036854775807 1)").unwrap(),
+                "9223372036854775808"
+            );
+            assert_eq!(
+                eval("(* -9223372036854775807 2)").unwrap(),
+                "-18446744073709551614"
+            );
+            assert_eq!(
+                eval("(quot -9223372036854775808 -1)").unwrap(),
+                "9223372036854775808"
+            );
+            assert_eq!(
+                eval("(/ 1 0)").unwrap_err().inner(),
+                &Error::DivisionByZero
+            );
+            assert_eq!(
+                eval("(% 1 0)").unwrap_err().inner(),
+                &Error::DivisionByZero
+            );
+            assert_eq!(
+                eval("(quot 1.5 1)").unwrap_err().inner(),
+                &Error::TypeMismatch {
+                    expected: "integer",
+                    received: "float"
+                }
+            );
+        }
+    }
 
-        let expr = parse_expression("(* 3 2 1)").unwrap().1;
-        assert_eq!(
-            expr.evaluate(&mut Env::default()).unwrap(),
-            Expression::Atom(Atom::Number(6.0))
-        );
+    #[test]
+    fn evaluates_big_integers_and_rationals() {
+        for mut interp in Interpreter::all_backends() {
+            let mut eval = |input: &str| {
+                interp
+                    .parse_and_eval(input)
+                    .map(|expression| expression.to_string())
+            };
+
+            assert_eq!(
+                eval("(* 4294967296 4294967296 4294967296)")
+                    .unwrap(),
+                "79228162514264337593543950336"
+            );
+            // Big integers shrink back once they fit
+            assert_eq!(
+                eval("(- 9223372036854775808 1)").unwrap(),
+                "9223372036854775807"
+            );
+            assert_eq!(
+                eval("(quot 18446744073709551616 4)").unwrap(),
+                "4611686018427387904"
+            );
+            assert_eq!(
+                eval("(rem 18446744073709551617 2)").unwrap(),
+                "1"
+            );
+
+            assert_eq!(eval("(/ 1 3)").unwrap(), "1/3");
+            assert_eq!(eval("(+ 1/3 1/6)").unwrap(), "1/2");
+            assert_eq!(eval("(+ 1/3 2/3)").unwrap(), "1");
+            assert_eq!(eval("(* 3 1/3)").unwrap(), "1");
+            assert_eq!(eval("(- 1/2)").unwrap(), "-1/2");
+            assert_eq!(eval("(/ 1/2)").unwrap(), "2");
+            assert_eq!(eval("(% 7/2 1)").unwrap(), "1/2");
+            assert_eq!(eval("(+ 1/2 0.25)").unwrap(), "0.75");
+            assert_eq!(
+                eval("(/ 1 18446744073709551616)").unwrap(),
+                "1/18446744073709551616"
+            );
+
+            assert_eq!(eval("(= 1/2 0.5)").unwrap(), "true");
+            assert_eq!(
+                eval("(= 1/3 0.3333333333333333)").unwrap(),
+                "false"
+            );
+            assert_eq!(
+                eval("(< 1/3 0.3333333333333333)").unwrap(),
+                "false"
+            );
+            assert_eq!(eval("(< 1/3 1/2 1)").unwrap(), "true");
+            assert_eq!(
+                eval("(> 18446744073709551616 1.8e19 1)")
+                    .unwrap(),
+                "true"
+            );
+
+            assert_eq!(
+                eval("(exact->inexact 1/4)").unwrap(),
+                "0.25"
+            );
+            assert_eq!(
+                eval("(exact->inexact 3)").unwrap(),
+                "3.0"
+            );
+            assert_eq!(
+                eval("(rationalize 0.1)").unwrap(),
+                "1/10"
+            );
+            assert_eq!(
+                eval("(rationalize -2.5)").unwrap(),
+                "-5/2"
+            );
+            assert_eq!(eval("(rationalize 3.0)").unwrap(), "3");
+            assert_eq!(
+                eval("(rationalize 2/4)").unwrap(),
+                "1/2"
+            );
+            assert_eq!(
+                eval("(rationalize 1e20)").unwrap(),
+                "100000000000000000000"
+            );
+
+            assert_eq!(
+                eval("(/ 1/2 0)").unwrap_err().inner(),
+                &Error::DivisionByZero
+            );
+            assert_eq!(
+                eval("(quot 1/2 1)").unwrap_err().inner(),
+                &Error::TypeMismatch {
+                    expected: "integer",
+                    received: "rational"
+                }
+            );
+            assert_eq!(
+                eval("(nth [1] 18446744073709551616)")
+                    .unwrap_err()
+                    .inner(),
+                &Error::IntegerOverflow
+            );
+            assert_eq!(
+                eval("(rationalize (/ 1.0 0))")
+                    .unwrap_err()
+                    .inner(),
+                &Error::TypeMismatch {
+                    expected: "finite number",
+                    received: "float"
+                }
+            );
+        }
+    }
 
-        let expr = parse_expression("(* 3 2 1 0)").unwrap().1;
-        assert_eq!(
-            expr.evaluate(&mut Env::default()).unwrap(),
-            Expression::Atom(Atom::Number(0.0))
-        );
+    #[test]
+    fn evaluates_if_expressions() {
+        for mut interp in Interpreter::all_backends() {
+            assert_eq!(
+                interp.parse_and_eval("(if true 2)").unwrap(),
+                Expression::Atom(Atom::Integer(2))
+            );
+
+            assert_eq!(
+                interp
+                    .parse_and_eval("(if false 2 (if true 5))")
+                    .unwrap(),
+                Expression::Atom(Atom::Integer(5))
+            );
+        }
     }
 
     #[test]
-    fn evaluates_subtraction_correctly() {
-        let expr = parse_expression("(-)").unwrap().1;
-        assert_eq!(
-            expr.evaluate(&mut Env::default()).unwrap(),
-            Expression::Atom(Atom::Number(0.0))
-        );
+    fn evaluates_not_expressions() {
+        for mut interp in Interpreter::all_backends() {
+            assert_eq!(
+                interp.parse_and_eval("(not true)").unwrap(),
+                Expression::Atom(Atom::Boolean(false))
+            );
+
+            assert_eq!(
+                interp.parse_and_eval("(not false)").unwrap(),
+                Expression::Atom(Atom::Boolean(true))
+            );
+
+            assert_eq!(
+                interp
+                    .parse_and_eval("(not (not (not false)))")
+                    .unwrap(),
+                Expression::Atom(Atom::Boolean(true))
+            );
+
+            assert_eq!(
+                interp
+                    .parse_and_eval("(not)")
+                    .unwrap_err()
+                    .inner(),
+                &Error::ExactArityMismatch {
+                    expected: 1,
+                    received: 0
+                }
+            );
+            assert_eq!(
+                interp
+                    .parse_and_eval("(not false true)")
+                    .unwrap_err()
+                    .inner(),
+                &Error::ExactArityMismatch {
+                    expected: 1,
+                    received: 2
+                }
+            );
+        }
+    }
 
-        let expr = parse_expression("(- 3)").unwrap().1;
-        assert_eq!(
-            expr.evaluate(&mut Env::default()).unwrap(),
-            Expression::Atom(Atom::Number(-3.0))
-        );
+    #[test]
+    fn evaluates_cond_expressions() {
+        for mut interp in Interpreter::all_backends() {
+            assert!(interp
+                .parse_and_eval(
+                    "(def even? (fn [x] (= (% x 2) 0)))"
+                )
+                .is_ok());
+
+            assert_eq!(
+                interp.parse_and_eval("(cond true 2)").unwrap(),
+                2_i64.into()
+            );
+            assert_eq!(
+                interp
+                    .parse_and_eval("(cond (even? 4) (* 4 2))")
+                    .unwrap(),
+                8_i64.into()
+            );
+
+            assert_eq!(
+                interp
+                    .parse_and_eval(
+                        "(cond (even? 3) (* 4 2) (even? 4) false)"
+                    )
+                    .unwrap(),
+                false.into()
+            );
+
+            assert_eq!(
+                interp
+                    .parse_and_eval(
+                        "(cond false (* 4 2) false false true)"
+                    )
+                    .unwrap(),
+                true.into()
+            );
+
+            assert!(interp
+                .parse_and_eval(
+                    "(def fibonacci 
+                        (fn [n] 
+                            (cond 
+                                (= n 0) 1
+                                (= n 1) 1
+                                (+ (fibonacci (- n 1)) (fibonacci (- n 2))))))",
+                )
+                .is_ok());
 
-        // FIXME: this is wrong
-        let expr = parse_expression("(- 3 2)").unwrap().1;
-        assert_eq!(
-            expr.evaluate(&mut Env::default()).unwrap(),
-            Expression::Atom(Atom::Number(1.0))
-        );
+            assert_eq!(
+                interp.parse_and_eval("(fibonacci 5)").unwrap(),
+                8_i64.into()
+            );
+        }
     }
 
     #[test]
-    fn evaluates_if_expressions() {
-        let expr = parse_expression("(if true 2)").unwrap().1;
-        assert_eq!(
-            expr.evaluate(&mut Env::default()).unwrap(),
-            Expression::Atom(Atom::Number(2.0))
-        );
+    fn evaluates_let_expressions() {
+        for mut interp in Interpreter::all_backends() {
+            assert_eq!(
+                interp
+                    .parse_and_eval("(let [a 1 b (+ a 1)] b)")
+                    .unwrap(),
+                2_i64.into()
+            );
+
+            // `let` bindings must not leak into the global scope
+            assert_eq!(
+                interp.parse_and_eval("a").unwrap_err(),
+                Error::UnknownSymbol(SmallString::new("a"))
+            );
+
+            // Inner bindings shadow outer ones, both in nested
+            // `let`s and within the same binding vector
+            assert_eq!(
+                interp
+                    .parse_and_eval(
+                        "(let [x 1 y (let [x 10] (* x 2)) x (+ x y)] x)"
+                    )
+                    .unwrap(),
+                21_i64.into()
+            );
+
+            assert!(interp
+                .parse_and_eval("(def x 100)")
+                .is_ok());
+            assert_eq!(
+                interp
+                    .parse_and_eval("(let [x 5] (+ x x))")
+                    .unwrap(),
+                10_i64.into()
+            );
+            assert_eq!(
+                interp.parse_and_eval("x").unwrap(),
+                100_i64.into()
+            );
+
+            // Lambdas see the `let`s surrounding them, and
+            // vice-versa
+            assert!(interp
+                .parse_and_eval(
+                    "(def scale (let [factor 3] (fn [n] (* n factor))))"
+                )
+                .is_ok());
+            assert_eq!(
+                interp.parse_and_eval("(scale 2)").unwrap(),
+                6_i64.into()
+            );
+            assert!(interp
+                .parse_and_eval(
+                    "(def hypotenuse-squared (fn [a b] (let [a2 (* a a) b2 (* b b)] (+ a2 b2))))"
+                )
+                .is_ok());
+            assert_eq!(
+                interp
+                    .parse_and_eval("(hypotenuse-squared 3 4)")
+                    .unwrap(),
+                25_i64.into()
+            );
+
+            assert_eq!(
+                interp
+                    .parse_and_eval("(let [] :empty)")
+                    .unwrap(),
+                Expression::Atom(Atom::Symbol(
+                    SmallString::new("empty")
+                ))
+            );
+        }
+    }
 
-        let expr = parse_expression("(if false 2 (if true 5))")
-            .unwrap()
-            .1;
-        assert_eq!(
-            expr.evaluate(&mut Env::default()).unwrap(),
-            Expression::Atom(Atom::Number(5.0))
-        );
+    #[test]
+    fn evaluates_count_expressions() {
+        for mut interp in Interpreter::all_backends() {
+            assert!(interp
+                .parse_and_eval(
+                    "(def even? (fn [x] (= (% x 2) 0)))"
+                )
+                .is_ok());
+            assert!(interp
+                .parse_and_eval("(def zero? (fn [x] (= x 0)))")
+                .is_ok());
+            matches!(
+                interp
+                    .parse_and_eval(
+                        "(def five-range '(1 2 3 4 5))"
+                    )
+                    .unwrap(),
+                Expression::List(_)
+            );
+
+            assert_eq!(
+                interp
+                    .parse_and_eval(
+                        "(count zero? [0 5 0 6 6 4 9 0 3 4 0])"
+                    )
+                    .unwrap(),
+                4_i64.into()
+            );
+
+            assert_eq!(
+                interp
+                    .parse_and_eval(
+                        "(count (fn [x] (= (% x 2) 1)) five-range)"
+                    )
+                    .unwrap(),
+                3_i64.into()
+            );
+
+            assert_eq!(
+                interp
+                    .parse_and_eval(
+                        "(count (fn [x] (= (% x 2) 1)) [2 4 6 7 8 9 10 12 13])"
+                    )
+                    .unwrap(),
+                3_i64.into()
+            );
+        }
     }
 
     #[test]
-    fn evaluates_not_expressions() {
-        let expr = parse_expression("(not true)").unwrap().1;
-        assert_eq!(
-            expr.evaluate(&mut Env::default()).unwrap(),
-            Expression::Atom(Atom::Boolean(false))
-        );
+    fn evaluates_list_builtins() {
+        for mut interp in Interpreter::all_backends() {
+            let mut eval = |input: &str| {
+                interp
+                    .parse_and_eval(input)
+                    .map(|expression| expression.to_string())
+            };
+
+            assert_eq!(
+                eval("(cons 1 [2 3])").unwrap(),
+                "[1 2 3]"
+            );
+            assert_eq!(eval("(cons [] [])").unwrap(), "[[]]");
+            assert_eq!(eval("(first '(1 2 3))").unwrap(), "1");
+            assert_eq!(eval("(first [])").unwrap(), "nil");
+            assert_eq!(eval("(rest [1 2 3])").unwrap(), "[2 3]");
+            assert_eq!(eval("(rest [])").unwrap(), "[]");
+            assert_eq!(eval("(nth [1 2 3] 2)").unwrap(), "3");
+            assert_eq!(eval("(length [1 [2 3]])").unwrap(), "2");
+            assert_eq!(eval("(empty? [])").unwrap(), "true");
+            assert_eq!(eval("(empty? [nil])").unwrap(), "false");
+            assert_eq!(
+                eval("(append [1] [] [2 3] '(4))").unwrap(),
+                "[1 2 3 4]"
+            );
+            assert_eq!(eval("(append)").unwrap(), "[]");
+            assert_eq!(
+                eval("(reverse [1 2 3])").unwrap(),
+                "[3 2 1]"
+            );
+            assert_eq!(eval("(last [1 2 3])").unwrap(), "3");
+            assert_eq!(eval("(last [])").unwrap(), "nil");
+
+            // Arguments are evaluated
+            assert_eq!(
+                eval(
+                    "(let [xs [1 2 3]]
+                        (cons (length xs) (rest (reverse xs))))"
+                )
+                .unwrap(),
+                "[3 2 1]"
+            );
+
+            assert_eq!(
+                eval("(first [1] [2])").unwrap_err().inner(),
+                &Error::ExactArityMismatch {
+                    expected: 1,
+                    received: 2
+                }
+            );
+            assert_eq!(
+                eval("(cons 1)").unwrap_err().inner(),
+                &Error::ExactArityMismatch {
+                    expected: 2,
+                    received: 1
+                }
+            );
+            assert_eq!(
+                eval("(rest 1)").unwrap_err().inner(),
+                &Error::TypeMismatch {
+                    expected: "list",
+                    received: "integer"
+                }
+            );
+            assert_eq!(
+                eval("(append [1] :two)").unwrap_err().inner(),
+                &Error::TypeMismatch {
+                    expected: "list",
+                    received: "symbol"
+                }
+            );
+            assert_eq!(
+                eval("(nth [1 2] 2)").unwrap_err().inner(),
+                &Error::IndexOutOfBounds {
+                    index: 2,
+                    length: 2
+                }
+            );
+            assert_eq!(
+                eval("(nth [1 2] -1)").unwrap_err().inner(),
+                &Error::IndexOutOfBounds {
+                    index: -1,
+                    length: 2
+                }
+            );
+            assert_eq!(
+                eval("(nth [1 2] 0.5)").unwrap_err().inner(),
+                &Error::TypeMismatch {
+                    expected: "integer",
+                    received: "float"
+                }
+            );
+        }
+    }
 
-        let expr = parse_expression("(not false)").unwrap().1;
-        assert_eq!(
-            expr.evaluate(&mut Env::default()).unwrap(),
-            Expression::Atom(Atom::Boolean(true))
-        );
+    #[test]
+    fn evaluates_string_builtins() {
+        for mut interp in Interpreter::all_backends() {
+            let mut eval = |input: &str| {
+                interp
+                    .parse_and_eval(input)
+                    .map(|expression| expression.to_string())
+            };
+
+            assert_eq!(
+                eval(r#"(str "a" 1 :b 2.5 [1 "c"] nil)"#)
+                    .unwrap(),
+                r#""a1:b2.5[1 \"c\"]nil""#
+            );
+            assert_eq!(eval("(str)").unwrap(), r#""""#);
+            assert_eq!(
+                eval(r#"(string-length "héllo")"#).unwrap(),
+                "5"
+            );
+            assert_eq!(
+                eval(r#"(substring "héllo wörld" 1 4)"#)
+                    .unwrap(),
+                r#""éll""#
+            );
+            assert_eq!(
+                eval(r#"(substring "héllo wörld" 6)"#).unwrap(),
+                r#""wörld""#
+            );
+            assert_eq!(
+                eval(r#"(substring "héllo" 5 5)"#).unwrap(),
+                r#""""#
+            );
+            assert_eq!(
+                eval(r#"(split "a,b,,c" ",")"#).unwrap(),
+                r#"["a" "b" "" "c"]"#
+            );
+            assert_eq!(
+                eval(r#"(split "añb" "")"#).unwrap(),
+                r#"["a" "ñ" "b"]"#
+            );
+            assert_eq!(
+                eval(r#"(join ", " ["a" 1 :b])"#).unwrap(),
+                r#""a, 1, :b""#
+            );
+            assert_eq!(
+                eval(r#"(join ["a" "b"])"#).unwrap(),
+                r#""ab""#
+            );
+            assert_eq!(
+                eval(r#"(upper-case "straße")"#).unwrap(),
+                r#""STRASSE""#
+            );
+            assert_eq!(
+                eval(r#"(lower-case "ÀÉ")"#).unwrap(),
+                r#""àé""#
+            );
+            assert_eq!(
+                eval(r#"(trim "  a b  ")"#).unwrap(),
+                r#""a b""#
+            );
+            assert_eq!(
+                eval(r#"(starts-with? "ceceio" "cec")"#)
+                    .unwrap(),
+                "true"
+            );
+            assert_eq!(
+                eval(r#"(contains? "ceceio" "eio")"#).unwrap(),
+                "true"
+            );
+            assert_eq!(
+                eval(r#"(contains? "ceceio" "x")"#).unwrap(),
+                "false"
+            );
+            assert_eq!(
+                eval(r#"(replace "a-b-c" "-" "+")"#).unwrap(),
+                r#""a+b+c""#
+            );
+
+            let error =
+                eval(r#"(substring "abc" 2 4)"#).unwrap_err();
+            assert_eq!(
+                error.inner(),
+                &Error::StringIndexOutOfBounds {
+                    index: 4,
+                    length: 3
+                }
+            );
+            assert_eq!(
+                error.inner().to_string(),
+                "Index 4 out of bounds for a string of length 3"
+            );
+            let error =
+                eval(r#"(substring "héllo" 3 1)"#).unwrap_err();
+            assert_eq!(
+                error.inner(),
+                &Error::ReversedRange { start: 3, end: 1 }
+            );
+            assert_eq!(
+                error.inner().to_string(),
+                "Substring end 1 is before its start 3"
+            );
+            assert_eq!(
+                eval(r#"(substring "abc" 1 2 3)"#)
+                    .unwrap_err()
+                    .inner(),
+                &Error::ExactArityMismatch {
+                    expected: 3,
+                    received: 4
+                }
+            );
+            assert_eq!(
+                eval("(string-length :abc)")
+                    .unwrap_err()
+                    .inner(),
+                &Error::TypeMismatch {
+                    expected: "string",
+                    received: "symbol"
+                }
+            );
+        }
+    }
 
-        let expr = parse_expression("(not (not (not false)))")
-            .unwrap()
-            .1;
-        assert_eq!(
-            expr.evaluate(&mut Env::default()).unwrap(),
-            Expression::Atom(Atom::Boolean(true))
-        );
+    #[test]
+    fn evaluates_maps_and_sets() {
+        for mut interp in Interpreter::all_backends() {
+            let mut eval = |input: &str| {
+                interp
+                    .parse_and_eval(input)
+                    .map(|expression| expression.to_string())
+            };
+
+            eval(r#"(def person {:name "x" :age (+ 1 2)})"#)
+                .unwrap();
+            assert_eq!(
+                eval("person").unwrap(),
+                r#"{:name "x" :age 3}"#
+            );
+            assert_eq!(eval("(get person :age)").unwrap(), "3");
+            assert_eq!(eval("(get person :id)").unwrap(), "nil");
+            assert_eq!(eval("(get person :id 0)").unwrap(), "0");
+            assert_eq!(eval("(get nil :id)").unwrap(), "nil");
+            assert_eq!(
+                eval("(assoc person :age 4 :id 7)").unwrap(),
+                r#"{:name "x" :age 4 :id 7}"#
+            );
+            // Maps are values: `assoc` leaves the original alone
+            assert_eq!(eval("(get person :age)").unwrap(), "3");
+            assert_eq!(
+                eval("(dissoc person :name :id)").unwrap(),
+                "{:age 3}"
+            );
+            assert_eq!(
+                eval("(keys person)").unwrap(),
+                "[:name :age]"
+            );
+            assert_eq!(
+                eval("(vals person)").unwrap(),
+                r#"["x" 3]"#
+            );
+            assert_eq!(
+                eval("(merge person {:age 5} {:id 1})").unwrap(),
+                r#"{:name "x" :age 5 :id 1}"#
+            );
+            assert_eq!(eval("(merge)").unwrap(), "{}");
+            assert_eq!(
+                eval("(conj {} [:a 1] [:b 2])").unwrap(),
+                "{:a 1 :b 2}"
+            );
+            assert_eq!(
+                eval("(contains? person :name)").unwrap(),
+                "true"
+            );
+            assert_eq!(
+                eval("(contains? person \"x\")").unwrap(),
+                "false"
+            );
+
+            // Keys of any type, compared by value
+            assert_eq!(
+                eval(r#"(get {[1 2] :list "s" :string 1 :one} [1 2])"#)
+                    .unwrap(),
+                ":list"
+            );
+            assert_eq!(
+                eval("(= {:a 1 :b 2} {:b 2 :a 1})").unwrap(),
+                "true"
+            );
+            assert_eq!(eval("{:a 1 :a 2}").unwrap(), "{:a 2}");
+
+            // Sets
+            assert_eq!(
+                eval("#{1 2 (+ 1 1) :a}").unwrap(),
+                "#{1 2 :a}"
+            );
+            assert_eq!(
+                eval("(conj #{1 2} 2 3)").unwrap(),
+                "#{1 2 3}"
+            );
+            assert_eq!(
+                eval("(contains? #{:a :b} :b)").unwrap(),
+                "true"
+            );
+            assert_eq!(eval("(get #{:a :b} :a)").unwrap(), ":a");
+            assert_eq!(
+                eval("(get #{:a :b} :c)").unwrap(),
+                "nil"
+            );
+            assert_eq!(
+                eval("(= #{1 2} #{2 1})").unwrap(),
+                "true"
+            );
+            assert_eq!(
+                eval("(conj [1 2] 3 4)").unwrap(),
+                "[1 2 3 4]"
+            );
+
+            assert_eq!(
+                eval("(assoc {} :a)").unwrap_err().inner(),
+                &Error::MinimumArityMismatch {
+                    at_least: 3,
+                    received: 2
+                }
+            );
+            assert_eq!(
+                eval("(assoc {} :a 1 :b)").unwrap_err().inner(),
+                &Error::ExactArityMismatch {
+                    expected: 5,
+                    received: 4
+                }
+            );
+            assert_eq!(
+                eval("(keys [1 2])").unwrap_err().inner(),
+                &Error::TypeMismatch {
+                    expected: "map",
+                    received: "list"
+                }
+            );
+            assert_eq!(
+                eval("(conj {} [:a])").unwrap_err().inner(),
+                &Error::TypeMismatch {
+                    expected: "[key value] pair",
+                    received: "list"
+                }
+            );
+        }
+    }
 
-        let expr = parse_expression("(not)").unwrap().1;
-        assert_eq!(
-            expr.evaluate(&mut Env::default()).unwrap_err(),
-            Error::ExactArityMismatch {
-                expected: 1,
-                received: 0
-            }
-        );
+    #[test]
+    fn evaluates_symbol_accessors() {
+        for mut interp in Interpreter::all_backends() {
+            let mut eval = |input: &str| {
+                interp
+                    .parse_and_eval(input)
+                    .map(|expression| expression.to_string())
+            };
+
+            eval(r#"(def person {:name "x" :age 3})"#).unwrap();
+            assert_eq!(eval("(:age person)").unwrap(), "3");
+            assert_eq!(eval("(:id person)").unwrap(), "nil");
+            assert_eq!(eval("(:id person 0)").unwrap(), "0");
+            assert_eq!(eval("(:a #{:a :b})").unwrap(), ":a");
+            assert_eq!(
+                eval("(map :age [person {:age 4}])").unwrap(),
+                "[3 4]"
+            );
+            // Named like reserved words and built-ins
+            assert_eq!(
+                eval(r#"(:first {:first "J" :nil 1})"#).unwrap(),
+                r#""J""#
+            );
+            assert_eq!(
+                eval("(get {:count 2 :keys 3} :keys)").unwrap(),
+                "3"
+            );
+            assert_eq!(
+                eval("[:map :nil :if]").unwrap(),
+                "[:map :nil :if]"
+            );
+            eval("(def nil? 1)").unwrap();
+            eval("(def nilly 2)").unwrap();
+            eval("(def truely 3)").unwrap();
+            eval("(def false-count 4)").unwrap();
+            assert_eq!(
+                eval("(+ nil? nilly truely (- false-count 1))")
+                    .unwrap(),
+                "9"
+            );
+            eval("(def name-of (fn [p] (:name p)))").unwrap();
+            assert_eq!(
+                eval("(name-of person)").unwrap(),
+                r#""x""#
+            );
+
+            assert_eq!(
+                eval("(:age)").unwrap_err().inner(),
+                &Error::MinimumArityMismatch {
+                    at_least: 1,
+                    received: 0
+                }
+            );
+            assert_eq!(
+                eval("(:age 3)").unwrap_err().inner(),
+                &Error::TypeMismatch {
+                    expected: "map",
+                    received: "integer"
+                }
+            );
+        }
+    }
 
-        let expr =
-            parse_expression("(not false true)").unwrap().1;
-        assert_eq!(
-            expr.evaluate(&mut Env::default()).unwrap_err(),
-            Error::ExactArityMismatch {
-                expected: 1,
-                received: 2
-            }
-        );
+    #[test]
+    fn evaluates_arbitrary_heads() {
+        for mut interp in Interpreter::all_backends() {
+            let mut eval = |input: &str| {
+                interp
+                    .parse_and_eval(input)
+                    .map(|expression| expression.to_string())
+            };
+
+            assert_eq!(
+                eval("((fn [x] (* x 2)) 21)").unwrap(),
+                "42"
+            );
+            eval("(def make-adder (fn [n] (fn [x] (+ x n))))")
+                .unwrap();
+            assert_eq!(eval("((make-adder 1) 2)").unwrap(), "3");
+            eval("(def flag false)").unwrap();
+            assert_eq!(
+                eval("((if flag + -) 1 2)").unwrap(),
+                "-1"
+            );
+            assert_eq!(
+                eval("(((fn [] :a)) {:a 1})").unwrap(),
+                "1"
+            );
+            eval("(def add +)").unwrap();
+            assert_eq!(eval("(add 1 2)").unwrap(), "3");
+
+            // Calls in tail position don't grow the stack
+            eval(
+                "(def loop (fn [n] (if (= n 0) :done ((fn [m] (loop m)) (- n 1)))))",
+            )
+            .unwrap();
+            assert_eq!(eval("(loop 100000)").unwrap(), ":done");
+
+            assert_eq!(
+                eval("((+ 1 2) 3)").unwrap_err().inner(),
+                &Error::TypeMismatch {
+                    expected: "lambda",
+                    received: "integer"
+                }
+            );
+        }
     }
 
     #[test]
-    fn evaluates_cond_expressions() {
-        let mut interp = Interpreter::new();
-        assert!(interp
-            .parse_and_eval("(def even? (fn [x] (= (% x 2) 0)))")
-            .is_ok());
-
-        assert_eq!(
-            interp.parse_and_eval("(cond true 2)").unwrap(),
-            2.0.into()
-        );
-        assert_eq!(
-            interp
-                .parse_and_eval("(cond (even? 4) (* 4 2))")
+    fn evaluates_shadowed_builtins() {
+        for mut interp in Interpreter::all_backends() {
+            let mut eval = |input: &str| {
+                interp
+                    .parse_and_eval(input)
+                    .map(|expression| expression.to_string())
+            };
+
+            // Built-ins are global bindings that locals shadow
+            assert_eq!(
+                eval("((fn [keys] (first keys)) [1 2])")
+                    .unwrap(),
+                "1"
+            );
+            assert_eq!(
+                eval("(let [rest [1 2]] (rest rest))")
+                    .unwrap_err()
+                    .inner(),
+                &Error::TypeMismatch {
+                    expected: "lambda",
+                    received: "list"
+                }
+            );
+            assert_eq!(
+                eval(
+                    "(let [[first & rest] [1 2]] [first rest])"
+                )
                 .unwrap(),
-            8.0.into()
-        );
+                "[1 [2]]"
+            );
+            assert_eq!(
+                eval("(match [1] [count] count)").unwrap(),
+                "1"
+            );
+            assert_eq!(eval("(first [3])").unwrap(), "3");
+            assert_eq!(
+                eval("first").unwrap(),
+                "<function first>"
+            );
+
+            // Or redefined by global ones
+            eval("(def last (fn [list] :last))").unwrap();
+            assert_eq!(eval("(last [1 2])").unwrap(), ":last");
+            assert_eq!(
+                eval("(map last [[1]])").unwrap(),
+                "[:last]"
+            );
+        }
+    }
 
-        assert_eq!(
-            interp
-                .parse_and_eval(
-                    "(cond (even? 3) (* 4 2) (even? 4) false)"
+    #[test]
+    fn evaluates_macros() {
+        for mut interp in Interpreter::all_backends() {
+            let mut eval = |input: &str| {
+                interp
+                    .eval_program(input)
+                    .map(|expression| expression.to_string())
+            };
+
+            assert_eq!(
+                eval("(defmacro unless [c x] `(if ~c nil ~x))")
+                    .unwrap(),
+                "nil"
+            );
+            assert_eq!(eval("(unless false 1)").unwrap(), "1");
+            assert_eq!(eval("(unless true 1)").unwrap(), "nil");
+            // Arguments aren't evaluated before being handed
+            // over
+            assert_eq!(
+                eval("(unless true (undefined))").unwrap(),
+                "nil"
+            );
+            // Macros are given and return code as data
+            assert_eq!(
+                eval("(macroexpand '(unless (= 1 2) :ok))")
+                    .unwrap(),
+                "[if [= 1 2] nil :ok]"
+            );
+            assert_eq!(eval("'x").unwrap(), "x");
+            assert_eq!(
+                eval("'(f [1 x] \"y\")").unwrap(),
+                "[f [<function list> 1 x] \"y\"]"
+            );
+
+            // Splicing, and macros expanding to other macros
+            eval("(defmacro sum-of [& xs] `(+ 0 ~@xs))")
+                .unwrap();
+            assert_eq!(
+                eval("(sum-of 1 2 (* 3 4))").unwrap(),
+                "15"
+            );
+            eval(
+                "(defmacro twice-sum [& xs] `(* 2 (sum-of ~@xs)))",
+            )
+            .unwrap();
+            assert_eq!(eval("(twice-sum 1 2)").unwrap(), "6");
+            assert_eq!(
+                eval("(macroexpand '(twice-sum 1 2))").unwrap(),
+                "[* 2 [sum-of 1 2]]"
+            );
+
+            // Macros are expanded within lambdas, and defined
+            // before the rest of a program is expanded
+            assert_eq!(
+                eval(
+                    "(defmacro swap [f a b] `(~f ~b ~a))
+                     (def sub-from (fn [x y] (swap - x y)))
+                     (sub-from 1 10)"
                 )
                 .unwrap(),
-            false.into()
-        );
+                "9"
+            );
+
+            // Splicing into `do` blocks
+            eval("(defmacro run-all [& xs] `(do ~@xs))")
+                .unwrap();
+            assert_eq!(
+                eval("(run-all (def q 3) (+ q 1))").unwrap(),
+                "4"
+            );
+
+            // Macros may take any amount of forms
+            eval("(defmacro when [c & body] `(if ~c (do ~@body)))")
+                .unwrap();
+            assert_eq!(
+                eval("(when true (def w 1) (+ w 1))").unwrap(),
+                "2"
+            );
+            assert_eq!(
+                eval("(when false (undefined))").unwrap(),
+                "nil"
+            );
+
+            // Macros can compute their expansion
+            eval("(defmacro square-of [n] (* n n))").unwrap();
+            assert_eq!(eval("(square-of 7)").unwrap(), "49");
+
+            // Including by taking forms apart, as threading
+            // macros do
+            eval(
+                "(defmacro -> [x & forms]
+                    (reduce
+                        (fn [acc form]
+                            (match form
+                                [f & args] `(~f ~acc ~@args)
+                                f `(~f ~acc)))
+                        x
+                        forms))",
+            )
+            .unwrap();
+            assert_eq!(
+                eval("(-> 5 (- 1) (* 10) str)").unwrap(),
+                "\"40\""
+            );
+            assert_eq!(
+                eval("(macroexpand '(-> xs (map f) count))")
+                    .unwrap(),
+                "[count [map xs f]]"
+            );
+
+            // Unquoted forms may be bound to, introducing
+            // bindings named by the macro's caller
+            eval(
+                "(defmacro with-double [name x & body]
+                    `(let [~name (* 2 ~x)] (do ~@body)))",
+            )
+            .unwrap();
+            assert_eq!(
+                eval("(with-double d 21 (+ d 0))").unwrap(),
+                "42"
+            );
+            eval(
+                "(defmacro defn [name params & body]
+                    `(def ~name (fn ~params ~@body)))",
+            )
+            .unwrap();
+            assert_eq!(
+                eval("(defn add [a (b 1)] (+ a b)) [(add 1) (add 1 2)]")
+                    .unwrap(),
+                "[2 3]"
+            );
+            assert_eq!(
+                eval("(defn swap-pair [[a b]] [b a]) (swap-pair [1 2])")
+                    .unwrap(),
+                "[2 1]"
+            );
+            assert_eq!(
+                eval("(macroexpand '(defn id [x] x))").unwrap(),
+                "[def id [fn [x] x]]"
+            );
+            eval("(defmacro adder [p n] `(fn [~p] (+ ~p ~n)))")
+                .unwrap();
+            assert_eq!(eval("((adder y 2) 3)").unwrap(), "5");
+            eval(
+                "(defmacro fn-with [extra params body]
+                    `(fn [~@params ~extra] ~body))",
+            )
+            .unwrap();
+            assert_eq!(
+                eval("((fn-with c [a b] [a b c]) 1 2 3)")
+                    .unwrap(),
+                "[1 2 3]"
+            );
+            // Also when built by macros defining macros
+            eval(
+                "(defmacro defmacro-with [name params body]
+                    `(defmacro ~name [~@params] ~body))",
+            )
+            .unwrap();
+            eval("(defmacro-with twice [x] `(* 2 ~x))").unwrap();
+            assert_eq!(eval("(twice 21)").unwrap(), "42");
+
+            assert_eq!(
+                eval("(+ 1 ~2)").unwrap_err().inner(),
+                &Error::UnquoteOutsideQuasiquote
+            );
+            assert_eq!(
+                eval("(defmacro bad [x] `(if ~@x 1 2)) (bad [true])")
+                    .unwrap_err()
+                    .inner(),
+                &Error::SpliceOutsideSequence
+            );
+            assert_eq!(
+                eval("(defmacro bad-let [] ['let ['x] 'x]) (bad-let)")
+                    .unwrap_err()
+                    .inner(),
+                &Error::MalformedCode {
+                    form: "let",
+                    code: SmallString::new("[let [x] x]"),
+                }
+            );
+        }
 
-        assert_eq!(
-            interp
-                .parse_and_eval(
-                    "(cond false (* 4 2) false false true)"
+        // Unquoted patterns are rejected even when evaluating
+        // without expanding macros first
+        for input in
+            ["(let [~x 1] x)", "(match 1 ~x 2)", "(def ~x 1)"]
+        {
+            assert_eq!(
+                parse_and_eval(input),
+                Err(Error::UnquoteOutsideQuasiquote)
+            );
+        }
+    }
+
+    #[test]
+    fn evaluates_do_blocks() {
+        for mut interp in Interpreter::all_backends() {
+            let mut eval = |input: &str| {
+                interp
+                    .parse_and_eval(input)
+                    .map(|expression| expression.to_string())
+            };
+
+            assert_eq!(eval("(do)").unwrap(), "nil");
+            assert_eq!(eval("(do 1)").unwrap(), "1");
+            // Expressions are evaluated in order, giving the
+            // value of the last one
+            assert_eq!(
+                eval("(do (def a 1) (def b (+ a 1)) [a b])")
+                    .unwrap(),
+                "[1 2]"
+            );
+            assert_eq!(
+                eval("(let [x 2] (do (def y x) (* x 10)))")
+                    .unwrap(),
+                "20"
+            );
+            assert_eq!(eval("y").unwrap(), "2");
+
+            // Lambda bodies are implicitly within a `do` block
+            assert_eq!(eval("(def log [])").unwrap(), "[]");
+            eval(
+                "(def inc-logged
+                    (fn [x]
+                        (def log (conj log x))
+                        (+ x 1)))",
+            )
+            .unwrap();
+            assert_eq!(eval("(inc-logged 1)").unwrap(), "2");
+            assert_eq!(eval("(inc-logged 5)").unwrap(), "6");
+            assert_eq!(eval("log").unwrap(), "[1 5]");
+
+            // Errors stop the evaluation of the rest
+            assert_eq!(
+                eval("(do (def z 1) (undefined) (def z 2))")
+                    .unwrap_err()
+                    .inner(),
+                &Error::UnknownSymbol(SmallString::new(
+                    "undefined"
+                ))
+            );
+            assert_eq!(eval("z").unwrap(), "1");
+        }
+    }
+
+    #[test]
+    fn evaluates_destructuring() {
+        for mut interp in Interpreter::all_backends() {
+            let mut eval = |input: &str| {
+                interp
+                    .parse_and_eval(input)
+                    .map(|expression| expression.to_string())
+            };
+
+            // `let`
+            assert_eq!(
+                eval("(let [[x y] [1 2]] (+ x y))").unwrap(),
+                "3"
+            );
+            assert_eq!(
+                eval("(let [[x & more] [1 2 3] n (length more)] [x n])")
+                    .unwrap(),
+                "[1 2]"
+            );
+            assert_eq!(
+                eval("(let [[[a b] c] [[1 2] 3]] [c b a])")
+                    .unwrap(),
+                "[3 2 1]"
+            );
+            assert_eq!(
+                eval(
+                    "(let [{:keys [name age] [x y] :pos} \
+                        {:name \"ada\" :pos [1 2]}] [name age x y])"
                 )
                 .unwrap(),
-            true.into()
-        );
+                "[\"ada\" nil 1 2]"
+            );
+
+            // `def`
+            assert_eq!(
+                eval("(def [a b] [:a :b])").unwrap(),
+                "[:a :b]"
+            );
+            assert_eq!(eval("[b a]").unwrap(), "[:b :a]");
+
+            // `fn` parameters, including optional and rest ones
+            eval("(def dist (fn [[x1 y1] [x2 y2]] (+ (- x2 x1) (- y2 y1))))")
+                .unwrap();
+            assert_eq!(eval("(dist [0 0] [3 4])").unwrap(), "7");
+            eval("(def greet (fn [{:keys [name]} (greeting \"hi\")] (str greeting \" \" name)))")
+                .unwrap();
+            assert_eq!(
+                eval("(greet {:name \"bob\"})").unwrap(),
+                "\"hi bob\""
+            );
+            eval("(def firsts (fn [& [[x] [y]]] [x y]))")
+                .unwrap();
+            assert_eq!(
+                eval("(firsts [1] [2])").unwrap(),
+                "[1 2]"
+            );
+
+            // Mismatched shapes
+            assert_eq!(
+                eval("(let [[x y] [1 2 3]] x)")
+                    .unwrap_err()
+                    .inner(),
+                &Error::ExactLengthMismatch {
+                    expected: 2,
+                    received: 3
+                }
+            );
+            assert_eq!(
+                eval("(let [[x y & more] [1]] x)")
+                    .unwrap_err()
+                    .inner(),
+                &Error::MinimumLengthMismatch {
+                    at_least: 2,
+                    received: 1
+                }
+            );
+            assert_eq!(
+                eval("(dist 1 [3 4])").unwrap_err().inner(),
+                &Error::TypeMismatch {
+                    expected: "list",
+                    received: "integer"
+                }
+            );
+            assert_eq!(
+                eval("(let [{:keys [x]} [1]] x)")
+                    .unwrap_err()
+                    .inner(),
+                &Error::TypeMismatch {
+                    expected: "map",
+                    received: "list"
+                }
+            );
+            // Nothing gets bound when a `def` fails to match
+            assert!(eval("(def [c d] [1])").is_err());
+            assert!(eval("c").is_err());
+        }
+    }
 
-        assert!(interp
-            .parse_and_eval(
-                "(def fibonacci 
-                    (fn [n] 
-                        (cond 
-                            (= n 0) 1
-                            (= n 1) 1
-                            (+ (fibonacci (- n 1)) (fibonacci (- n 2))))))",
+    #[test]
+    fn evaluates_match_expressions() {
+        for mut interp in Interpreter::all_backends() {
+            let mut eval = |input: &str| {
+                interp
+                    .parse_and_eval(input)
+                    .map(|expression| expression.to_string())
+            };
+
+            eval(
+                "(def describe (fn [x] (match x \
+                    0 :zero \
+                    :ok :symbol \
+                    \"hi\" :string \
+                    [] :empty \
+                    [n] :when (> n 9) [:big n] \
+                    [n] [:one n] \
+                    [a & more] [:many a (length more)] \
+                    _ :other)))",
+            )
+            .unwrap();
+            assert_eq!(eval("(describe 0)").unwrap(), ":zero");
+            assert_eq!(
+                eval("(describe :ok)").unwrap(),
+                ":symbol"
+            );
+            assert_eq!(
+                eval("(describe \"hi\")").unwrap(),
+                ":string"
+            );
+            assert_eq!(eval("(describe [])").unwrap(), ":empty");
+            assert_eq!(
+                eval("(describe [10])").unwrap(),
+                "[:big 10]"
+            );
+            assert_eq!(
+                eval("(describe [1])").unwrap(),
+                "[:one 1]"
+            );
+            assert_eq!(
+                eval("(describe [1 2 3])").unwrap(),
+                "[:many 1 2]"
+            );
+            assert_eq!(
+                eval("(describe 1.5)").unwrap(),
+                ":other"
+            );
+
+            // Bindings see the enclosing scope, and don't
+            // outlive their clause
+            assert_eq!(
+                eval("(let [x 1] (match [2 3] [_ y] (+ x y)))")
+                    .unwrap(),
+                "4"
+            );
+            assert_eq!(
+                eval(
+                    "(let [x 1] \
+                        (match [5] [x] :when (> x 9) x _ x))"
+                )
+                .unwrap(),
+                "1"
+            );
+            assert_eq!(
+                eval("(match {:tag :point :x 3} {:point :tag x :x} x)")
+                    .unwrap(),
+                "3"
+            );
+
+            // Numbers match literals the way `=` compares them
+            assert_eq!(
+                eval("(match 1.0 1 :one _ :other)").unwrap(),
+                ":one"
+            );
+            assert_eq!(
+                eval("(match [2/1 [:a]] [2.0 & [[:a]]] :ok)")
+                    .unwrap(),
+                ":ok"
+            );
+            assert_eq!(
+                eval("(match [1 2] [_ & [x y]] x _ :short)")
+                    .unwrap(),
+                ":short"
+            );
+
+            // Without a matching clause, the result is nil
+            assert_eq!(eval("(match 1 2 :two)").unwrap(), "nil");
+            assert_eq!(eval("(match 1)").unwrap(), "nil");
+
+            // Branches are in tail position
+            eval(
+                "(def count-down (fn [n] (match n \
+                    0 :done \
+                    _ (count-down (- n 1)))))",
+            )
+            .unwrap();
+            assert_eq!(
+                eval("(count-down 100000)").unwrap(),
+                ":done"
+            );
+
+            // `_` matches without binding
+            assert_eq!(eval("(let [_ 1] :ok)").unwrap(), ":ok");
+            assert_eq!(
+                eval("(let [[:ok x] [:error 1]] x)")
+                    .unwrap_err()
+                    .inner(),
+                &Error::LiteralMismatch(SmallString::new(":ok"))
+            );
+
+            // An identifier can't be bound twice by one pattern,
+            // even by one built from code
+            assert!(matches!(
+                eval("(match [1 2] [x x] :same _ :diff)"),
+                Err(Error::ParsingError(_))
+            ));
+            assert!(matches!(
+                eval("(let [{x :a [x] :b} {}] x)"),
+                Err(Error::ParsingError(_))
+            ));
+            eval(
+                "(defmacro same [value name] \
+                    `(match ~value [~name ~name] :same _ :diff))",
             )
-            .is_ok());
+            .unwrap();
+            assert_eq!(
+                eval("(same [1 2] x)").unwrap_err().inner(),
+                &Error::MalformedCode {
+                    form: "pattern",
+                    code: SmallString::new("[x x]"),
+                }
+            );
+            assert_eq!(
+                eval(
+                    "(match [1 1] [x [y]] :nested [x y] :flat)"
+                )
+                .unwrap(),
+                ":flat"
+            );
+        }
+    }
 
-        assert_eq!(
-            interp.parse_and_eval("(fibonacci 5)").unwrap(),
-            8.0.into()
-        );
+    #[test]
+    fn evaluates_string_literals() {
+        for mut interp in Interpreter::all_backends() {
+            let mut eval = |input: &str| {
+                interp
+                    .parse_and_eval(input)
+                    .map(|expression| expression.to_string())
+            };
+
+            assert_eq!(
+                eval(r#"(string-length "a\nb")"#).unwrap(),
+                "3"
+            );
+            assert_eq!(
+                eval(r#"(string-length "\\")"#).unwrap(),
+                "1"
+            );
+            assert_eq!(
+                eval(r#"(string-length "\u{1F600}")"#).unwrap(),
+                "1"
+            );
+            assert_eq!(
+                eval("(string-length \"two\nlines\")").unwrap(),
+                "9"
+            );
+            assert_eq!(
+                eval(r#"(split "a\\b\\c" r"\")"#).unwrap(),
+                r#"["a" "b" "c"]"#
+            );
+            assert_eq!(
+                eval(r##"(= r#"say "hi""# "say \"hi\"")"##)
+                    .unwrap(),
+                "true"
+            );
+
+            // Strings are displayed the way they're written, so
+            // that they read back as themselves
+            let displayed =
+                eval(r#"(str "tab\t" r"\" "\"" "\u{7}")"#)
+                    .unwrap();
+            assert_eq!(displayed, r#""tab\t\\\"\u{7}""#);
+            assert_eq!(eval(&displayed).unwrap(), displayed);
+        }
     }
 
     #[test]
-    fn evaluates_count_expressions() {
-        let mut interp = Interpreter::new();
-
-        assert!(interp
-            .parse_and_eval("(def even? (fn [x] (= (% x 2) 0)))")
-            .is_ok());
-        assert!(interp
-            .parse_and_eval("(def zero? (fn [x] (= x 0)))")
-            .is_ok());
-        matches!(
-            interp
-                .parse_and_eval("(def five-range '(1 2 3 4 5))")
+    fn evaluates_higher_order_builtins() {
+        for mut interp in Interpreter::all_backends() {
+            let mut eval = |input: &str| {
+                interp
+                    .parse_and_eval(input)
+                    .map(|expression| expression.to_string())
+            };
+
+            eval("(def inc (fn [x] (+ x 1)))").unwrap();
+            eval("(def even? (fn [x] (= (% x 2) 0)))").unwrap();
+
+            // Lambdas, identifiers bound to them and built-ins
+            assert_eq!(
+                eval("(map (fn [x] (* x 2)) [1 2 3])").unwrap(),
+                "[2 4 6]"
+            );
+            assert_eq!(
+                eval("(map inc [1 2 3])").unwrap(),
+                "[2 3 4]"
+            );
+            assert_eq!(
+                eval("(map not [true false])").unwrap(),
+                "[false true]"
+            );
+            assert_eq!(eval("(map inc [])").unwrap(), "[]");
+            assert_eq!(
+                eval("(filter even? [1 -2 3 0])").unwrap(),
+                "[-2 0]"
+            );
+            assert_eq!(
+                eval("(reduce + [1 2 3 4])").unwrap(),
+                "10"
+            );
+            assert_eq!(
+                eval("(reduce + 10 [1 2 3 4])").unwrap(),
+                "20"
+            );
+            assert_eq!(
+                eval("(reduce (fn [acc x] (cons x acc)) [] [1 2 3])")
+                    .unwrap(),
+                "[3 2 1]"
+            );
+            assert_eq!(eval("(reduce + 0 [])").unwrap(), "0");
+            assert_eq!(eval("(reduce + [5])").unwrap(), "5");
+            assert_eq!(
+                eval("(reduce (fn [] :empty) [])").unwrap(),
+                ":empty"
+            );
+            assert_eq!(eval("(apply + [1 2 3])").unwrap(), "6");
+            assert_eq!(eval("(apply + 1 2 [3])").unwrap(), "6");
+            assert_eq!(
+                eval("(apply (fn [x y] (- x y)) [5 3])")
+                    .unwrap(),
+                "2"
+            );
+            assert_eq!(
+                eval("(any? even? [1 3 4])").unwrap(),
+                "true"
+            );
+            assert_eq!(
+                eval("(any? even? [])").unwrap(),
+                "false"
+            );
+            assert_eq!(
+                eval("(every? even? [2 4 1])").unwrap(),
+                "false"
+            );
+            assert_eq!(
+                eval("(every? even? [])").unwrap(),
+                "true"
+            );
+            assert_eq!(
+                eval("(for-each inc [1 2])").unwrap(),
+                "nil"
+            );
+
+            // Closures keep access to their scope
+            assert_eq!(
+                eval(
+                    "(let [n 10] (map (fn [x] (+ x n)) [1 2]))"
+                )
                 .unwrap(),
-            Expression::List(_)
-        );
-
-        assert_eq!(
-            interp
-                .parse_and_eval(
-                    "(count zero? [0 5 0 6 6 4 9 0 3 4 0])"
+                "[11 12]"
+            );
+            assert_eq!(
+                eval(
+                    "(map (fn [xs] (reduce + xs)) [[1 2] [3]])"
                 )
                 .unwrap(),
-            4.0.into()
-        );
-
-        assert_eq!(
-            interp
-                .parse_and_eval(
-                    "(count (fn [x] (= (% x 2) 1)) five-range)"
+                "[3 3]"
+            );
+
+            // `any?` and `every?` stop at the first item that
+            // decides their result
+            assert_eq!(
+                eval(
+                    "(any? (fn [x] (= (nth [1 2] x) 1)) [0 5])"
                 )
                 .unwrap(),
-            3.0.into()
-        );
+                "true"
+            );
+            assert_eq!(
+                eval("(every? (fn [x] (= (nth [1 2] x) 2)) [0 5])")
+                    .unwrap(),
+                "false"
+            );
+
+            assert_eq!(
+                eval("(map inc)").unwrap_err().inner(),
+                &Error::ExactArityMismatch {
+                    expected: 2,
+                    received: 1
+                }
+            );
+            assert_eq!(
+                eval("(reduce + 0 [] [])").unwrap_err().inner(),
+                &Error::ExactArityMismatch {
+                    expected: 3,
+                    received: 4
+                }
+            );
+            assert_eq!(
+                eval("(apply +)").unwrap_err().inner(),
+                &Error::MinimumArityMismatch {
+                    at_least: 2,
+                    received: 1
+                }
+            );
+            assert_eq!(
+                eval("(map 1 [1 2])").unwrap_err().inner(),
+                &Error::TypeMismatch {
+                    expected: "lambda",
+                    received: "integer"
+                }
+            );
+            // Applied the same way as when called directly
+            assert_eq!(
+                eval("(1 2)").unwrap_err().inner(),
+                eval("(map 1 [1 2])").unwrap_err().inner()
+            );
+            assert_eq!(
+                eval("(apply cond [false 1 true 2])").unwrap(),
+                "2"
+            );
+            assert_eq!(
+                eval("(apply or [false true])").unwrap(),
+                "true"
+            );
+            assert_eq!(
+                eval("(filter inc [1 2])").unwrap_err().inner(),
+                &Error::TypeMismatch {
+                    expected: "boolean",
+                    received: "integer"
+                }
+            );
+        }
+    }
 
-        assert_eq!(
-            interp
+    #[test]
+    fn evaluates_tail_calls_in_constant_stack() {
+        for mut interp in Interpreter::all_backends() {
+            // Self tail calls through `if`
+            assert!(interp
                 .parse_and_eval(
-                    "(count (fn [x] (= (% x 2) 1)) [2 4 6 7 8 9 10 12 13])"
+                    "(def countdown
+                        (fn [n] (if (= n 0) :done (countdown (- n 1)))))"
                 )
-                .unwrap(),
-            3.0.into()
-        );
+                .is_ok());
+            assert_eq!(
+                interp
+                    .parse_and_eval("(countdown 1000000)")
+                    .unwrap(),
+                Expression::Atom(Atom::Symbol(
+                    SmallString::new("done")
+                ))
+            );
+
+            // Mutual tail calls through `cond` and `let`
+            assert!(interp
+                .parse_and_eval(
+                    "(def even?
+                        (fn [n] (cond (= n 0) true (odd? (- n 1)))))"
+                )
+                .is_ok());
+            assert!(interp
+                .parse_and_eval(
+                    "(def odd?
+                        (fn [n]
+                            (cond
+                                (= n 0) false
+                                (let [m (- n 1)] (even? m)))))"
+                )
+                .is_ok());
+            assert_eq!(
+                interp.parse_and_eval("(even? 100001)").unwrap(),
+                false.into()
+            );
+
+            // Accumulators are kept around between iterations
+            assert!(interp
+                .parse_and_eval(
+                    "(def sum-to
+                        (fn [n acc]
+                            (if (= n 0)
+                                acc
+                                (sum-to (- n 1) (+ acc n)))))"
+                )
+                .is_ok());
+            assert_eq!(
+                interp
+                    .parse_and_eval("(sum-to 100000 0)")
+                    .unwrap(),
+                5000050000_i64.into()
+            );
+
+            // Through the last expression of lambda bodies
+            assert!(interp
+                .parse_and_eval(
+                    "(def count-up
+                        (fn [n acc]
+                            (def last-seen n)
+                            (if (= n 0) acc (count-up (- n 1) (+ acc 1)))))"
+                )
+                .is_ok());
+            assert_eq!(
+                interp
+                    .parse_and_eval("(count-up 100000 0)")
+                    .unwrap(),
+                100000_i64.into()
+            );
+
+            // Scopes are restored after tail calls
+            assert_eq!(
+                interp
+                    .parse_and_eval("(let [n 7] [(even? 4) n])")
+                    .unwrap(),
+                Expression::List(vec![
+                    true.into(),
+                    7_i64.into()
+                ])
+            );
+        }
     }
 }
@@ -1,21 +1,22 @@
 use std::{
     fmt::{self},
-    mem,
+    rc::Rc,
 };
 
 pub mod builtin;
+mod collection;
 pub mod elements;
+pub mod native;
+mod number;
 
 pub use builtin::BuiltIn;
-use slicedisplay::SliceDisplay;
+pub use collection::{Map, Set};
+pub(crate) use number::Numeric;
 
 use self::elements::{
-    Application, Atom, Binding, If, IfElse, Lambda,
-};
-use crate::{
-    evaluatable::resolve_argument, Env, Error, Evaluable,
-    Result, SmallString, Typed,
+    Application, Atom, Binding, If, IfElse, Lambda, Let, Match,
 };
+use crate::{Error, Result, SmallString, Typed};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expression {
@@ -24,19 +25,51 @@ pub enum Expression {
     If(Box<If>),
     IfElse(Box<IfElse>),
     Binding(Box<Binding>),
+    Let(Box<Let>),
+    Match(Box<Match>),
     Lambda(Box<Lambda>),
+    /// `(do expressions*)`, evaluating each expression in order
+    /// and giving the value of the last one, or nil if empty
+    Do(Vec<Expression>),
     List(Vec<Expression>),
+    Map(Rc<Map>),
+    Set(Rc<Set>),
+    /// `'form`: the code `form` taken as data, which is what
+    /// macros are given and return
+    Quote(Rc<Expression>),
+    /// A template of code, `` `form ``, evaluating to that code
+    /// with its unquoted parts filled in
+    Quasiquote(Rc<Expression>),
+    /// `~form`: within a quasiquote, the code `form` evaluates
+    /// to
+    Unquote(Rc<Expression>),
+    /// `~@form`: within a quasiquote, the items of the list
+    /// `form` evaluates to, spliced into the enclosing one
+    UnquoteSplicing(Rc<Expression>),
+    /// `(defmacro name [args] body)`, binding a macro to the
+    /// lambda that expands it
+    DefMacro(Box<Binding>),
 }
 
 impl Expression {
+    /// The value of a number, be it an integer or a float
     pub fn as_number(&self) -> Result<f64> {
-        if let Expression::Atom(Atom::Number(num)) = self {
-            Ok(*num)
-        } else {
-            Err(Error::TypeMismatch {
-                expected: "number",
-                received: self.rough_type(),
-            })
+        self.as_numeric().map(|number| number.as_f64())
+    }
+
+    /// The value of an integer, which must fit in an `i64`
+    pub fn as_integer(&self) -> Result<i64> {
+        match self {
+            Expression::Atom(Atom::Integer(integer)) => {
+                Ok(*integer)
+            }
+            Expression::Atom(Atom::BigInteger(_)) => {
+                Err(Error::IntegerOverflow)
+            }
+            other => Err(Error::TypeMismatch {
+                expected: "integer",
+                received: other.rough_type(),
+            }),
         }
     }
 
@@ -62,90 +95,37 @@ impl Expression {
         }
     }
 
-    pub fn as_list(self) -> Result<Vec<Expression>> {
-        if let Expression::List(list) = self {
-            Ok(list)
+    pub fn as_string(self) -> Result<SmallString> {
+        if let Expression::Atom(Atom::String(string)) = self {
+            Ok(string)
         } else {
             Err(Error::TypeMismatch {
-                expected: "list",
+                expected: "string",
                 received: self.rough_type(),
             })
         }
     }
 
-    pub fn resolve_all(
-        &mut self,
-        fn_arguments: &[SmallString],
-        received_arguments: &[Expression],
-        env: &mut Env,
-    ) -> Result<()> {
-        match self {
-            Expression::Atom(Atom::Identifier(identifier)) => {
-                *self = resolve_argument(
-                    identifier,
-                    fn_arguments,
-                    received_arguments,
-                    env,
-                )
-                .or_else(|_| {
-                    let expr = mem::take(self);
-                    expr.evaluate(env)
-                })?;
-            }
-            // Expression::Cond(conditions) => {
-            //     for cond in conditions.iter_mut() {
-            //         cond.resolve_all(
-            //             fn_arguments,
-            //             received_arguments,
-            //             env,
-            //         )?;
-            //     }
-            // }
-            Expression::Application(app) => {
-                for expression in app.arguments.iter_mut() {
-                    expression.resolve_all(
-                        fn_arguments,
-                        received_arguments,
-                        env,
-                    )?
-                }
-            }
-            Expression::If(if_expr) => {
-                if_expr.condition.resolve_all(
-                    fn_arguments,
-                    received_arguments,
-                    env,
-                )?;
-
-                if_expr.do_this.resolve_all(
-                    fn_arguments,
-                    received_arguments,
-                    env,
-                )?;
-            }
-            Expression::IfElse(if_else) => {
-                if_else.condition.resolve_all(
-                    fn_arguments,
-                    received_arguments,
-                    env,
-                )?;
-
-                if_else.if_true.resolve_all(
-                    fn_arguments,
-                    received_arguments,
-                    env,
-                )?;
-
-                if_else.if_false.resolve_all(
-                    fn_arguments,
-                    received_arguments,
-                    env,
-                )?;
-            }
-            _ => {}
+    pub fn as_map(self) -> Result<Rc<Map>> {
+        if let Expression::Map(map) = self {
+            Ok(map)
+        } else {
+            Err(Error::TypeMismatch {
+                expected: "map",
+                received: self.rough_type(),
+            })
         }
+    }
 
-        Ok(())
+    pub fn as_list(self) -> Result<Vec<Expression>> {
+        if let Expression::List(list) = self {
+            Ok(list)
+        } else {
+            Err(Error::TypeMismatch {
+                expected: "list",
+                received: self.rough_type(),
+            })
+        }
     }
 }
 
@@ -155,6 +135,12 @@ impl From<bool> for Expression {
     }
 }
 
+impl From<i64> for Expression {
+    fn from(integer: i64) -> Self {
+        Expression::Atom(Atom::Integer(integer))
+    }
+}
+
 impl From<f64> for Expression {
     fn from(number: f64) -> Self {
         Expression::Atom(Atom::Number(number))
@@ -165,18 +151,66 @@ impl fmt::Display for Expression {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Expression::List(expressions) => {
-                write!(
-                    f,
-                    "{}",
-                    expressions.display().delimiter(' ')
-                )
+                f.write_str("[")?;
+                for (idx, expression) in
+                    expressions.iter().enumerate()
+                {
+                    if idx > 0 {
+                        f.write_str(" ")?;
+                    }
+                    write!(f, "{expression}")?;
+                }
+                f.write_str("]")
+            }
+            Expression::Map(map) => {
+                f.write_str("{")?;
+                for (idx, (key, value)) in map.iter().enumerate()
+                {
+                    if idx > 0 {
+                        f.write_str(" ")?;
+                    }
+                    write!(f, "{key} {value}")?;
+                }
+                f.write_str("}")
+            }
+            Expression::Set(set) => {
+                f.write_str("#{")?;
+                for (idx, item) in set.iter().enumerate() {
+                    if idx > 0 {
+                        f.write_str(" ")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                f.write_str("}")
             }
             Expression::Lambda(_) => f.write_str("<function>"),
+            Expression::Do(body) => {
+                f.write_str("(do")?;
+                for expression in body {
+                    write!(f, " {expression}")?;
+                }
+                f.write_str(")")
+            }
+            Expression::Quote(data) => write!(f, "'{data}"),
+            Expression::Quasiquote(form) => write!(f, "`{form}"),
+            Expression::Unquote(form) => write!(f, "~{form}"),
+            Expression::UnquoteSplicing(form) => {
+                write!(f, "~@{form}")
+            }
+            Expression::DefMacro(def_macro) => write!(
+                f,
+                "(defmacro {} {})",
+                def_macro.pattern, def_macro.expression
+            ),
             Expression::Atom(atom) => write!(f, "{atom}"),
             Expression::Application(app) => write!(f, "{app}"),
             Expression::Binding(binding) => {
                 write!(f, "{binding}")
             }
+            Expression::Let(let_expr) => write!(f, "{let_expr}"),
+            Expression::Match(match_expr) => {
+                write!(f, "{match_expr}")
+            }
             Expression::If(if_expr) => {
                 write!(
                     f,
@@ -1,11 +1,15 @@
-use std::ops::Not;
+use std::{cmp::Ordering, ops::Not, rc::Rc};
 
+use super::{Map, Numeric};
 use crate::{
-    check::ensure_minimum_arity, ensure_exact_arity, Atom, Env,
-    Error, Evaluable, Expression, Result, Typed,
+    check::{ensure_arity_between, ensure_minimum_arity},
+    ensure_exact_arity,
+    evaluatable::{self, evaluate_all},
+    macros, Atom, Env, Error, Evaluable, Expression, Result,
+    SmallString, Span, Typed,
 };
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 /// Built-in operators
 pub enum BuiltIn {
     /// "+"
@@ -18,157 +22,908 @@ pub enum BuiltIn {
     Divide,
     /// "="
     Equal,
+    /// "not=", or "!=": whether some arguments differ
+    NotEqual,
+    /// "<": whether the arguments are strictly increasing
+    Less,
+    /// ">": whether the arguments are strictly decreasing
+    Greater,
+    /// "<=": whether the arguments never decrease
+    LessEqual,
+    /// ">=": whether the arguments never increase
+    GreaterEqual,
     /// "not"
     Not,
     /// "and"
     And,
     /// "or"
     Or,
-    /// "%"
+    /// "%": the remainder of dividing two numbers
     Remainder,
+    /// `quot`: the quotient of dividing two integers, truncated
+    /// towards zero
+    Quot,
+    /// `rem`: the remainder of dividing two integers
+    Rem,
+    /// `str`: concatenates its arguments, turning those that
+    /// aren't strings into how they're displayed
+    Str,
+    /// `string-length`: the amount of characters in a string
+    StringLength,
+    /// `substring`: the characters of a string between a start
+    /// index and, optionally, an end index
+    Substring,
+    /// `split`: the parts of a string between occurrences of a
+    /// separator
+    Split,
+    /// `join`: concatenates the items of a list, optionally
+    /// placing a separator between them
+    Join,
+    /// `upper-case`: a string in upper case
+    UpperCase,
+    /// `lower-case`: a string in lower case
+    LowerCase,
+    /// `trim`: a string without leading and trailing whitespace
+    Trim,
+    /// `starts-with?`: whether a string starts with another
+    StartsWith,
+    /// `contains?`: whether a string contains another, or a map
+    /// or set a given key
+    Contains,
+    /// `replace`: replaces all occurrences of a string within
+    /// another
+    Replace,
+    /// `exact->inexact`: the float closest to a number
+    ExactToInexact,
+    /// `rationalize`: the exact number a float is written as
+    Rationalize,
     Cond,
+    /// `macroexpand`: the code a macro application expands to,
+    /// expanding it over again while it's still one
+    MacroExpand,
     /// `count`: count how many items
     /// in a list a given predicate returns
     /// true to
     Count,
+    /// `list`: a list of its arguments
+    List,
+    /// `cons`: prepends an item to a list
+    Cons,
+    /// `first`: the first item of a list, or nil if empty
+    First,
+    /// `rest`: a list without its first item
+    Rest,
+    /// `nth`: the item of a list at a given index
+    Nth,
+    /// `length`: how many items a list has
+    Length,
+    /// `empty?`: whether a list has no items
+    IsEmpty,
+    /// `append`: concatenates any amount of lists
+    Append,
+    /// `reverse`: a list with its items in reverse order
+    Reverse,
+    /// `last`: the last item of a list, or nil if empty
+    Last,
+    /// `get`: the value of a key in a map, or the key itself if
+    /// in a set. Missing keys give nil or a given default
+    Get,
+    /// `assoc`: a map with the given keys set to the given
+    /// values
+    Assoc,
+    /// `dissoc`: a map without the given keys
+    Dissoc,
+    /// `keys`: the keys of a map
+    Keys,
+    /// `vals`: the values of a map
+    Vals,
+    /// `merge`: combines maps, the later ones winning over
+    /// keys they share
+    Merge,
+    /// `conj`: adds items to a list, set or map, the latter
+    /// taking `[key value]` pairs
+    Conj,
+    /// `map`: applies a function to each item of a list
+    Map,
+    /// `filter`: the items of a list a given predicate returns
+    /// true to
+    Filter,
+    /// `reduce`: combines the items of a list using a function,
+    /// optionally starting from an initial value
+    Reduce,
+    /// `apply`: calls a function with the items of a list as
+    /// its arguments
+    Apply,
+    /// `any?`: whether a given predicate returns true to some
+    /// item of a list
+    Any,
+    /// `every?`: whether a given predicate returns true to all
+    /// items of a list
+    Every,
+    /// `for-each`: calls a function on each item of a list,
+    /// for its side effects
+    ForEach,
 }
 
 impl BuiltIn {
+    /// The built-in an identifier named `name` refers to, unless
+    /// bound to something else. `cond`, `and` and `or` are left
+    /// out, being reserved words rather than identifiers
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        let built_in = match name {
+            "not" => BuiltIn::Not,
+            "count" => BuiltIn::Count,
+            "quot" => BuiltIn::Quot,
+            "rem" => BuiltIn::Rem,
+            "rationalize" => BuiltIn::Rationalize,
+            "macroexpand" => BuiltIn::MacroExpand,
+            "list" => BuiltIn::List,
+            "cons" => BuiltIn::Cons,
+            "first" => BuiltIn::First,
+            "rest" => BuiltIn::Rest,
+            "nth" => BuiltIn::Nth,
+            "length" => BuiltIn::Length,
+            "empty?" => BuiltIn::IsEmpty,
+            "append" => BuiltIn::Append,
+            "reverse" => BuiltIn::Reverse,
+            "last" => BuiltIn::Last,
+            "get" => BuiltIn::Get,
+            "assoc" => BuiltIn::Assoc,
+            "dissoc" => BuiltIn::Dissoc,
+            "keys" => BuiltIn::Keys,
+            "vals" => BuiltIn::Vals,
+            "merge" => BuiltIn::Merge,
+            "conj" => BuiltIn::Conj,
+            "map" => BuiltIn::Map,
+            "filter" => BuiltIn::Filter,
+            "reduce" => BuiltIn::Reduce,
+            "apply" => BuiltIn::Apply,
+            "any?" => BuiltIn::Any,
+            "every?" => BuiltIn::Every,
+            "for-each" => BuiltIn::ForEach,
+            "str" => BuiltIn::Str,
+            "string-length" => BuiltIn::StringLength,
+            "substring" => BuiltIn::Substring,
+            "split" => BuiltIn::Split,
+            "join" => BuiltIn::Join,
+            "upper-case" => BuiltIn::UpperCase,
+            "lower-case" => BuiltIn::LowerCase,
+            "trim" => BuiltIn::Trim,
+            "starts-with?" => BuiltIn::StartsWith,
+            "contains?" => BuiltIn::Contains,
+            "replace" => BuiltIn::Replace,
+            _ => return None,
+        };
+
+        Some(built_in)
+    }
+
+    /// Applies this built-in to the given, unevaluated,
+    /// arguments. All of them get evaluated first, except for
+    /// `cond`, `and` and `or`, which only evaluate as many as
+    /// needed
     pub fn apply(
+        self,
+        args: Vec<Expression>,
+        env: &mut Env,
+    ) -> Result<Expression> {
+        match self {
+            BuiltIn::Cond => Self::cond(args, env),
+            BuiltIn::And => {
+                ensure_minimum_arity(2, args.len())?;
+                Self::and(
+                    args.into_iter()
+                        .map(|expr| expr.evaluate(env)),
+                )
+            }
+            BuiltIn::Or => {
+                ensure_minimum_arity(2, args.len())?;
+                Self::or(
+                    args.into_iter()
+                        .map(|expr| expr.evaluate(env)),
+                )
+            }
+            _ => {
+                let arguments = evaluate_all(args, env)?;
+                self.call(arguments, env)
+            }
+        }
+    }
+
+    /// Applies this built-in to the given, already evaluated,
+    /// arguments
+    pub fn call(
         self,
         mut args: Vec<Expression>,
         env: &mut Env,
     ) -> Result<Expression> {
-        let arity_received = args.len() as _;
+        let arity_received = args.len();
 
         match self {
             BuiltIn::Plus => {
-                let expressions = args
-                    .into_iter()
-                    .map(|expr| expr.evaluate(env));
-                Self::acc_numeric(|x, y| x + y, 0., expressions)
+                let expressions = args.into_iter().map(Ok);
+                Self::acc_numeric(Numeric::add, 0, expressions)
             }
             BuiltIn::Minus => {
-                let expressions = args
-                    .into_iter()
-                    .map(|expr| expr.evaluate(env));
-                Self::acc_numeric(|x, y| x - y, 0., expressions)
+                let expressions = args.into_iter().map(Ok);
+                Self::acc_numeric(Numeric::sub, 0, expressions)
             }
             BuiltIn::Times => {
-                let expressions = args
-                    .into_iter()
-                    .map(|expr| expr.evaluate(env));
-                Self::acc_numeric(|x, y| x * y, 1., expressions)
+                let expressions = args.into_iter().map(Ok);
+                Self::acc_numeric(Numeric::mul, 1, expressions)
             }
             BuiltIn::Divide => {
-                let expressions = args
-                    .into_iter()
-                    .map(|expr| expr.evaluate(env));
-                Self::acc_numeric(|x, y| x / y, 1., expressions)
+                let expressions = args.into_iter().map(Ok);
+                Self::acc_numeric(Numeric::div, 1, expressions)
             }
             BuiltIn::Equal => {
-                let expressions = args
-                    .into_iter()
-                    .map(|expr| expr.evaluate(env));
+                let expressions = args.into_iter().map(Ok);
                 ensure_minimum_arity(2, arity_received)?;
                 Self::equals(expressions)
             }
+            BuiltIn::NotEqual => {
+                let expressions = args.into_iter().map(Ok);
+                ensure_minimum_arity(2, arity_received)?;
+                let equal =
+                    Self::equals(expressions)?.as_bool()?;
+                Ok((!equal).into())
+            }
+            BuiltIn::Less => {
+                ensure_minimum_arity(2, arity_received)?;
+                Self::compare(Ordering::is_lt, args)
+            }
+            BuiltIn::Greater => {
+                ensure_minimum_arity(2, arity_received)?;
+                Self::compare(Ordering::is_gt, args)
+            }
+            BuiltIn::LessEqual => {
+                ensure_minimum_arity(2, arity_received)?;
+                Self::compare(Ordering::is_le, args)
+            }
+            BuiltIn::GreaterEqual => {
+                ensure_minimum_arity(2, arity_received)?;
+                Self::compare(Ordering::is_ge, args)
+            }
             BuiltIn::Not => {
-                let expressions = args
-                    .into_iter()
-                    .map(|expr| expr.evaluate(env));
+                let expressions = args.into_iter().map(Ok);
                 // `not` can only be applied to one argument
                 ensure_exact_arity(1, arity_received)?;
                 Self::not(expressions)
             }
             BuiltIn::And => {
-                let expressions = args
-                    .into_iter()
-                    .map(|expr| expr.evaluate(env));
+                let expressions = args.into_iter().map(Ok);
                 ensure_minimum_arity(2, arity_received)?;
                 Self::and(expressions)
             }
             BuiltIn::Or => {
                 ensure_minimum_arity(2, arity_received)?;
-
-                Self::or(args, env)
+                Self::or(args.into_iter().map(Ok))
             }
             BuiltIn::Remainder => {
                 ensure_exact_arity(2, arity_received)?;
 
                 // `pop`s will not fail since we've just checked
                 // arity
-                let rhs = args
-                    .pop()
+                let rhs = args.pop().unwrap().as_numeric()?;
+                let lhs = args.pop().unwrap().as_numeric()?;
+
+                lhs.rem(rhs).map(Expression::from)
+            }
+            BuiltIn::Quot | BuiltIn::Rem => {
+                ensure_exact_arity(2, arity_received)?;
+                let mut arguments = args.into_iter();
+                // Won't fail since we've just checked the arity
+                let lhs = arguments
+                    .next()
                     .unwrap()
-                    .evaluate(env)?
-                    .as_number()?;
-                let lhs = args
-                    .pop()
+                    .as_exact_integer()?;
+                let rhs = arguments
+                    .next()
                     .unwrap()
-                    .evaluate(env)?
-                    .as_number()?;
+                    .as_exact_integer()?;
 
-                Ok((lhs % rhs).into())
+                let result = match self {
+                    BuiltIn::Quot => lhs.quot(rhs),
+                    _ => lhs.rem(rhs),
+                };
+                result.map(Expression::from)
+            }
+            BuiltIn::ExactToInexact => {
+                ensure_exact_arity(1, arity_received)?;
+                // Won't fail since we've just checked the arity
+                let number = args.pop().unwrap().as_number()?;
+                Ok(number.into())
+            }
+            BuiltIn::Rationalize => {
+                ensure_exact_arity(1, arity_received)?;
+                // Won't fail since we've just checked the arity
+                let number = args.pop().unwrap().as_numeric()?;
+                number.rationalize().map(Expression::from)
             }
             BuiltIn::Count => {
                 ensure_exact_arity(2, arity_received)?;
-
                 Self::count(args, env)
             }
-            BuiltIn::Cond => Self::cond(args, env),
+            BuiltIn::Cond => {
+                // Being evaluated already, the branch of the
+                // first condition that holds is
+                // picked as it is
+                let mut args = args.into_iter();
+                while let Some(condition) = args.next() {
+                    match args.next() {
+                        // The default branch
+                        None => return Ok(condition),
+                        Some(branch)
+                            if matches!(
+                                condition,
+                                Expression::Atom(Atom::Boolean(
+                                    true
+                                ))
+                            ) =>
+                        {
+                            return Ok(branch)
+                        }
+                        Some(_) => {}
+                    }
+                }
+                Ok(Expression::default())
+            }
+            BuiltIn::MacroExpand => {
+                ensure_exact_arity(1, arity_received)?;
+                // Won't fail since we've just checked the arity
+                let mut code = macros::into_code(
+                    &args.pop().unwrap(),
+                    Span::default(),
+                )?;
+                while let Some(expansion) =
+                    macros::expand_once(&code, env)?
+                {
+                    code = expansion;
+                }
+                Ok(macros::quote(&code))
+            }
+            BuiltIn::List => Ok(Expression::List(args)),
+            BuiltIn::Append => {
+                let lists = args;
+                Self::append(lists)
+            }
+            BuiltIn::Str => {
+                let strings = args;
+                let concatenated: String =
+                    strings.iter().map(display).collect();
+                Ok(Expression::Atom(Atom::String(
+                    SmallString::new(concatenated),
+                )))
+            }
+            BuiltIn::StringLength
+            | BuiltIn::UpperCase
+            | BuiltIn::LowerCase
+            | BuiltIn::Trim => {
+                ensure_exact_arity(1, arity_received)?;
+                let arguments = args;
+                self.apply_to_string(arguments)
+            }
+            BuiltIn::Contains => {
+                ensure_exact_arity(2, arity_received)?;
+                let arguments = args;
+                match arguments[0] {
+                    Expression::Map(_) | Expression::Set(_) => {
+                        self.apply_to_collection(arguments)
+                    }
+                    _ => self.apply_to_string(arguments),
+                }
+            }
+            BuiltIn::Split | BuiltIn::StartsWith => {
+                ensure_exact_arity(2, arity_received)?;
+                let arguments = args;
+                self.apply_to_string(arguments)
+            }
+            BuiltIn::Replace => {
+                ensure_exact_arity(3, arity_received)?;
+                let arguments = args;
+                self.apply_to_string(arguments)
+            }
+            BuiltIn::Substring => {
+                ensure_arity_between(2, 3, arity_received)?;
+                let arguments = args;
+                self.apply_to_string(arguments)
+            }
+            BuiltIn::Join => {
+                ensure_arity_between(1, 2, arity_received)?;
+                let mut arguments = args;
+                // Won't fail since we've just checked the arity
+                let list = arguments.pop().unwrap().as_list()?;
+                let separator = match arguments.pop() {
+                    Some(separator) => separator.as_string()?,
+                    None => SmallString::new(""),
+                };
+
+                let strings: Vec<_> =
+                    list.iter().map(display).collect();
+                Ok(Expression::Atom(Atom::String(
+                    SmallString::new(strings.join(&separator)),
+                )))
+            }
+            BuiltIn::Get => {
+                ensure_arity_between(2, 3, arity_received)?;
+                let arguments = args;
+                self.apply_to_collection(arguments)
+            }
+            BuiltIn::Assoc => {
+                ensure_minimum_arity(3, arity_received)?;
+                // Keys must come along with their values
+                if arity_received.is_multiple_of(2) {
+                    return Err(Error::ExactArityMismatch {
+                        expected: arity_received + 1,
+                        received: arity_received,
+                    });
+                }
+                let arguments = args;
+                self.apply_to_collection(arguments)
+            }
+            BuiltIn::Dissoc | BuiltIn::Conj => {
+                ensure_minimum_arity(1, arity_received)?;
+                let arguments = args;
+                self.apply_to_collection(arguments)
+            }
+            BuiltIn::Keys | BuiltIn::Vals => {
+                ensure_exact_arity(1, arity_received)?;
+                let arguments = args;
+                self.apply_to_collection(arguments)
+            }
+            BuiltIn::Merge => {
+                let maps = args;
+                Self::merge(maps)
+            }
+            BuiltIn::Cons | BuiltIn::Nth => {
+                ensure_exact_arity(2, arity_received)?;
+                let arguments = args;
+                self.apply_to_list(arguments)
+            }
+            BuiltIn::Map
+            | BuiltIn::Filter
+            | BuiltIn::Any
+            | BuiltIn::Every
+            | BuiltIn::ForEach => {
+                ensure_exact_arity(2, arity_received)?;
+                let mut arguments = args.into_iter();
+                // Won't fail since we've just checked the arity
+                let function = arguments.next().unwrap();
+                let list =
+                    arguments.next().unwrap().as_list()?;
+
+                self.apply_to_each(&function, list, env)
+            }
+            BuiltIn::Reduce => {
+                ensure_arity_between(2, 3, arity_received)?;
+                let arguments = args;
+                Self::reduce(arguments, env)
+            }
+            BuiltIn::Apply => {
+                ensure_minimum_arity(2, arity_received)?;
+                let mut arguments = args;
+
+                // Any arguments between the function and the
+                // list are passed before the
+                // items of the list Won't fail
+                // since we've just checked the arity
+                let list = arguments.pop().unwrap().as_list()?;
+                let function = arguments.remove(0);
+                arguments.extend(list);
+
+                call_function(&function, arguments, env)
+            }
+            BuiltIn::First
+            | BuiltIn::Rest
+            | BuiltIn::Length
+            | BuiltIn::IsEmpty
+            | BuiltIn::Reverse
+            | BuiltIn::Last => {
+                ensure_exact_arity(1, arity_received)?;
+                let arguments = args;
+                self.apply_to_list(arguments)
+            }
         }
     }
 
-    fn count(
-        mut expressions: Vec<Expression>,
-        env: &mut Env,
+    /// Applies a string built-in to its evaluated arguments,
+    /// whose amount must have been checked already. The string
+    /// comes first
+    fn apply_to_string(
+        self,
+        arguments: Vec<Expression>,
     ) -> Result<Expression> {
-        let mut acc = 0_usize;
+        let mut arguments = arguments.into_iter();
+        let string = arguments.next().unwrap().as_string()?;
+        // Won't fail since the caller checked the arity
+        let mut next_string =
+            || arguments.next().unwrap().as_string();
+
+        let string_atom = |string: &str| {
+            Expression::Atom(Atom::String(SmallString::new(
+                string,
+            )))
+        };
 
-        let arguments = expressions
-            .pop()
-            .unwrap()
-            .evaluate(env)?
-            .as_list()?;
+        let expression = match self {
+            BuiltIn::StringLength => {
+                (string.chars().count() as i64).into()
+            }
+            BuiltIn::UpperCase => {
+                string_atom(&string.to_uppercase())
+            }
+            BuiltIn::LowerCase => {
+                string_atom(&string.to_lowercase())
+            }
+            BuiltIn::Trim => string_atom(string.trim()),
+            BuiltIn::StartsWith => string
+                .starts_with(next_string()?.as_str())
+                .into(),
+            BuiltIn::Contains => {
+                string.contains(next_string()?.as_str()).into()
+            }
+            BuiltIn::Replace => {
+                let from = next_string()?;
+                let to = next_string()?;
+                string_atom(&string.replace(from.as_str(), &to))
+            }
+            BuiltIn::Split => {
+                let separator = next_string()?;
+                // Splitting on nothing gives each character
+                let parts: Vec<_> = if separator.is_empty() {
+                    string
+                        .chars()
+                        .map(|ch| {
+                            string_atom(
+                                ch.encode_utf8(&mut [0; 4]),
+                            )
+                        })
+                        .collect()
+                } else {
+                    string
+                        .split(separator.as_str())
+                        .map(string_atom)
+                        .collect()
+                };
+                Expression::List(parts)
+            }
+            BuiltIn::Substring => {
+                let start =
+                    arguments.next().unwrap().as_integer()?;
+                let end = arguments
+                    .next()
+                    .map(|end| end.as_integer())
+                    .transpose()?;
+                string_atom(substring(&string, start, end)?)
+            }
+            other => {
+                unreachable!(
+                    "{other:?} is not a string built-in"
+                )
+            }
+        };
 
-        let predicate =
-            expressions.pop().unwrap().evaluate(env)?;
+        Ok(expression)
+    }
 
-        let lambda = match predicate {
-            Expression::Atom(Atom::Identifier(identifier)) => {
-                env.get(identifier)?.as_lambda()?
+    /// Applies a list built-in to its evaluated arguments,
+    /// whose amount must have been checked already
+    fn apply_to_list(
+        self,
+        arguments: Vec<Expression>,
+    ) -> Result<Expression> {
+        let mut arguments = arguments.into_iter();
+        // Won't fail since the caller checked the arity
+        let mut next = || arguments.next().unwrap();
+
+        let expression = match self {
+            BuiltIn::Cons => {
+                let item = next();
+                let mut list = next().as_list()?;
+                list.insert(0, item);
+                Expression::List(list)
+            }
+            BuiltIn::Nth => {
+                let list = next().as_list()?;
+                let index = next().as_integer()?;
+                Self::nth(list, index)?
+            }
+            BuiltIn::First => {
+                let list = next().as_list()?;
+                list.into_iter().next().unwrap_or_default()
+            }
+            BuiltIn::Rest => {
+                let mut list = next().as_list()?;
+                if !list.is_empty() {
+                    list.remove(0);
+                }
+                Expression::List(list)
+            }
+            BuiltIn::Length => {
+                (next().as_list()?.len() as i64).into()
+            }
+            BuiltIn::IsEmpty => {
+                next().as_list()?.is_empty().into()
+            }
+            BuiltIn::Reverse => {
+                let mut list = next().as_list()?;
+                list.reverse();
+                Expression::List(list)
+            }
+            BuiltIn::Last => {
+                next().as_list()?.pop().unwrap_or_default()
             }
-            Expression::Lambda(lambda) => *lambda,
             other => {
-                return Err(Error::TypeMismatch {
-                    expected: "appliable",
-                    received: other.rough_type(),
-                })
+                unreachable!("{other:?} is not a list built-in")
             }
         };
 
-        for argument in arguments {
-            // TODO: this is very inefficient
-            let cond = lambda
-                .clone()
-                .apply(vec![argument], env)?
-                .as_bool()?;
+        Ok(expression)
+    }
+
+    /// Applies a map or set built-in to its evaluated
+    /// arguments, whose amount must have been checked already.
+    /// The collection comes first
+    fn apply_to_collection(
+        self,
+        arguments: Vec<Expression>,
+    ) -> Result<Expression> {
+        let mut arguments = arguments.into_iter();
+        // Won't fail since the caller checked the arity
+        let collection = arguments.next().unwrap();
+
+        let expression = match self {
+            BuiltIn::Get => {
+                let key = arguments.next().unwrap();
+                let default =
+                    arguments.next().unwrap_or_default();
+                match collection {
+                    Expression::Map(map) => {
+                        map.get(&key).cloned().unwrap_or(default)
+                    }
+                    Expression::Set(set)
+                        if set.contains(&key) =>
+                    {
+                        key
+                    }
+                    Expression::Set(_)
+                    | Expression::Atom(Atom::Nil) => default,
+                    other => {
+                        return Err(Error::TypeMismatch {
+                            expected: "map",
+                            received: other.rough_type(),
+                        })
+                    }
+                }
+            }
+            BuiltIn::Contains => {
+                let key = arguments.next().unwrap();
+                match collection {
+                    Expression::Map(map) => {
+                        map.contains_key(&key)
+                    }
+                    Expression::Set(set) => set.contains(&key),
+                    other => unreachable!(
+                        "{other} is neither a map nor a set"
+                    ),
+                }
+                .into()
+            }
+            BuiltIn::Assoc => {
+                let mut map = collection.as_map()?;
+                let entries = Rc::make_mut(&mut map);
+                while let (Some(key), Some(value)) =
+                    (arguments.next(), arguments.next())
+                {
+                    entries.insert(key, value);
+                }
+                Expression::Map(map)
+            }
+            BuiltIn::Dissoc => {
+                let mut map = collection.as_map()?;
+                let entries = Rc::make_mut(&mut map);
+                for key in arguments {
+                    entries.shift_remove(&key);
+                }
+                Expression::Map(map)
+            }
+            BuiltIn::Keys => Expression::List(
+                collection.as_map()?.keys().cloned().collect(),
+            ),
+            BuiltIn::Vals => Expression::List(
+                collection.as_map()?.values().cloned().collect(),
+            ),
+            BuiltIn::Conj => match collection {
+                Expression::List(mut list) => {
+                    list.extend(arguments);
+                    Expression::List(list)
+                }
+                Expression::Set(mut set) => {
+                    Rc::make_mut(&mut set).extend(arguments);
+                    Expression::Set(set)
+                }
+                Expression::Map(mut map) => {
+                    let entries = Rc::make_mut(&mut map);
+                    for pair in arguments {
+                        let (key, value) = as_pair(pair)?;
+                        entries.insert(key, value);
+                    }
+                    Expression::Map(map)
+                }
+                other => {
+                    return Err(Error::TypeMismatch {
+                        expected: "collection",
+                        received: other.rough_type(),
+                    })
+                }
+            },
+            other => {
+                unreachable!(
+                    "{other:?} is not a collection built-in"
+                )
+            }
+        };
+
+        Ok(expression)
+    }
+
+    fn merge(maps: Vec<Expression>) -> Result<Expression> {
+        let mut merged = Map::default();
+
+        for map in maps {
+            merged.extend(Rc::unwrap_or_clone(map.as_map()?));
+        }
+
+        Ok(Expression::Map(Rc::new(merged)))
+    }
+
+    fn nth(
+        list: Vec<Expression>,
+        index: i64,
+    ) -> Result<Expression> {
+        let length = list.len();
+        usize::try_from(index)
+            .ok()
+            .and_then(|idx| list.into_iter().nth(idx))
+            .ok_or(Error::IndexOutOfBounds { index, length })
+    }
+
+    fn append(lists: Vec<Expression>) -> Result<Expression> {
+        let mut appended = vec![];
+
+        for list in lists {
+            appended.extend(list.as_list()?);
+        }
+
+        Ok(Expression::List(appended))
+    }
+
+    fn count(
+        mut arguments: Vec<Expression>,
+        env: &mut Env,
+    ) -> Result<Expression> {
+        let mut acc = 0_usize;
+
+        // Won't fail since the caller checked the arity
+        let list = arguments.pop().unwrap().as_list()?;
+        let predicate = arguments.pop().unwrap();
+
+        for item in list {
+            let cond =
+                call_function(&predicate, vec![item], env)?
+                    .as_bool()?;
 
             if cond {
                 acc += 1;
             }
         }
 
-        Ok((acc as f64).into())
+        Ok((acc as i64).into())
+    }
+
+    /// Applies a higher-order built-in, which calls `function`
+    /// on each item of `list`
+    fn apply_to_each(
+        self,
+        function: &Expression,
+        list: Vec<Expression>,
+        env: &mut Env,
+    ) -> Result<Expression> {
+        let mut call =
+            |item| call_function(function, vec![item], env);
+
+        let expression = match self {
+            BuiltIn::Map => Expression::List(
+                list.into_iter()
+                    .map(call)
+                    .collect::<Result<_>>()?,
+            ),
+            BuiltIn::Filter => {
+                let mut filtered = vec![];
+                for item in list {
+                    if call(item.clone())?.as_bool()? {
+                        filtered.push(item);
+                    }
+                }
+                Expression::List(filtered)
+            }
+            BuiltIn::Any => {
+                for item in list {
+                    if call(item)?.as_bool()? {
+                        return Ok(true.into());
+                    }
+                }
+                false.into()
+            }
+            BuiltIn::Every => {
+                for item in list {
+                    if !call(item)?.as_bool()? {
+                        return Ok(false.into());
+                    }
+                }
+                true.into()
+            }
+            BuiltIn::ForEach => {
+                for item in list {
+                    call(item)?;
+                }
+                Expression::default()
+            }
+            other => unreachable!(
+                "{other:?} is not a higher-order built-in"
+            ),
+        };
+
+        Ok(expression)
+    }
+
+    /// `(reduce f list)` or `(reduce f init list)`. Without an
+    /// initial value, the first item is used as such, and an
+    /// empty list results in calling `f` with no arguments
+    fn reduce(
+        arguments: Vec<Expression>,
+        env: &mut Env,
+    ) -> Result<Expression> {
+        let mut arguments = arguments.into_iter();
+        // Won't fail since the caller checked the arity
+        let function = arguments.next().unwrap();
+        let mut rest: Vec<_> = arguments.collect();
+        let list = rest.pop().unwrap().as_list()?;
+
+        let mut items = rest.into_iter().chain(list);
+        let Some(mut acc) = items.next() else {
+            return call_function(&function, vec![], env);
+        };
+
+        for item in items {
+            acc =
+                call_function(&function, vec![acc, item], env)?;
+        }
+
+        Ok(acc)
     }
 
     fn cond(
-        mut expressions: Vec<Expression>,
+        expressions: Vec<Expression>,
         env: &mut Env,
     ) -> Result<Expression> {
+        match Self::cond_branch(expressions, env)? {
+            Some(branch) => branch.evaluate(env),
+            // Nothing evaluated to true, so we'll return nil
+            None => Ok(Expression::default()),
+        }
+    }
+
+    /// Evaluates the conditions of a `cond` expression in order,
+    /// returning the unevaluated branch of the first one that
+    /// holds. The branch is left for the caller to evaluate so
+    /// that it's in tail position
+    pub(crate) fn cond_branch(
+        mut expressions: Vec<Expression>,
+        env: &mut Env,
+    ) -> Result<Option<Expression>> {
         if expressions.is_empty() {
-            return Ok(Expression::default());
+            return Ok(None);
         }
 
         // We have a default branch if this `cond` expression
@@ -192,24 +947,18 @@ impl BuiltIn {
             // therefore this unwrap won't fail
             let then = expressions.next().unwrap();
             if evaluated_cond {
-                return then.evaluate(env);
+                return Ok(Some(then));
             }
         }
 
-        match default_branch {
-            Some(default) => default.evaluate(env),
-            None => {
-                // Nothing evaluated to true, so we'll return nil
-                Ok(Expression::default())
-            }
-        }
+        Ok(default_branch)
     }
 
     fn not(
         mut expressions: impl Iterator<Item = Result<Expression>>,
     ) -> Result<Expression> {
         // Won't fail because we've checked the arity in
-        // Self::apply
+        // Self::call
         let expr = expressions.next().unwrap();
         debug_assert!(expressions.next().is_none());
 
@@ -234,13 +983,9 @@ impl BuiltIn {
     }
 
     fn or(
-        expressions: Vec<Expression>,
-        env: &mut Env,
+        expressions: impl Iterator<Item = Result<Expression>>,
     ) -> Result<Expression> {
-        for expression in expressions
-            .into_iter()
-            .map(|expr| expr.evaluate(env))
-        {
+        for expression in expressions {
             let expression = expression?;
             let condition = expression.as_bool()?;
 
@@ -255,13 +1000,13 @@ impl BuiltIn {
     fn equals(
         mut expressions: impl Iterator<Item = Result<Expression>>,
     ) -> Result<Expression> {
-        // Safe unwrap: minimum arity was checked in Self::apply
+        // Safe unwrap: minimum arity was checked in Self::call
         let elem = expressions.next().unwrap()?;
 
         // Can't use iter::all due to error treatment
         for expression in expressions {
             let expression = expression?;
-            if expression != elem {
+            if !equal(&expression, &elem) {
                 return Ok(false.into());
             }
         }
@@ -269,42 +1014,171 @@ impl BuiltIn {
         Ok(true.into())
     }
 
+    /// Whether `holds` is true of every pair of adjacent
+    /// arguments, e.g. `(< 1 2 3)` means `1 < 2` and `2 < 3`
+    fn compare(
+        holds: impl Fn(Ordering) -> bool,
+        expressions: Vec<Expression>,
+    ) -> Result<Expression> {
+        // All arguments must be numbers, even after the chain
+        // is known not to hold
+        let numbers = expressions
+            .iter()
+            .map(Expression::as_numeric)
+            .collect::<Result<Vec<_>>>()?;
+
+        // NaN isn't ordered with respect to anything
+        let holds = numbers.windows(2).all(|pair| {
+            pair[0].compare(&pair[1]).is_some_and(&holds)
+        });
+
+        Ok(holds.into())
+    }
+
     /// Folds the argument list using the given function.
     ///
     /// `identity` is either the multiplicative identity or the
     /// additive identity (that is, 1 or 0), depending on the
     /// operation being used.
     fn acc_numeric(
-        func: impl Fn(f64, f64) -> f64,
-        identity: f64,
+        func: impl Fn(Numeric, Numeric) -> Result<Numeric>,
+        identity: i64,
         expressions: impl Iterator<Item = Result<Expression>>,
     ) -> Result<Expression> {
         let mut expressions = expressions.peekable();
 
         let mut acc = match expressions.next() {
             Some(maybe_atom) => {
-                let first_value = maybe_atom?.as_number()?;
+                let first_value = maybe_atom?.as_numeric()?;
                 if expressions.peek().is_none() {
                     // If there are no more variables to fold,
                     // apply the only one we
                     // have to the identity and return
-                    return Ok(
-                        func(identity, first_value).into()
-                    );
+                    return func(
+                        Numeric::Integer(identity),
+                        first_value,
+                    )
+                    .map(Expression::from);
                 }
 
                 first_value
             }
-            None => return Ok(0.0.into()),
+            None => return Ok(0_i64.into()),
         };
 
         for expression in expressions {
             let expression = expression?;
-            let number = expression.as_number()?;
+            let number = expression.as_numeric()?;
 
-            acc = func(acc, number)
+            acc = func(acc, number)?
         }
 
         Ok(acc.into())
     }
 }
+
+/// Whether two values are equal, where numbers are compared by
+/// their value regardless of them being integers or floats
+pub(crate) fn equal(lhs: &Expression, rhs: &Expression) -> bool {
+    match (lhs, rhs) {
+        (Expression::List(lhs), Expression::List(rhs)) => {
+            lhs.len() == rhs.len()
+                && lhs.iter().zip(rhs).all(|(x, y)| equal(x, y))
+        }
+        _ => match (lhs.as_numeric(), rhs.as_numeric()) {
+            (Ok(lhs), Ok(rhs)) => {
+                lhs.compare(&rhs) == Some(Ordering::Equal)
+            }
+            _ => lhs == rhs,
+        },
+    }
+}
+
+/// Calls `function` with the given, already evaluated,
+/// arguments
+fn call_function(
+    function: &Expression,
+    arguments: Vec<Expression>,
+    env: &mut Env,
+) -> Result<Expression> {
+    evaluatable::call(function, arguments, env)?.finish(env)
+}
+
+/// Looks `symbol` up in the map it's applied to, as in
+/// `(:name person)`, optionally falling back to a default as
+/// `get` does
+pub(crate) fn call_symbol(
+    symbol: &SmallString,
+    mut arguments: Vec<Expression>,
+) -> Result<Expression> {
+    ensure_arity_between(1, 2, arguments.len())?;
+    arguments.insert(
+        1,
+        Expression::Atom(Atom::Symbol(symbol.clone())),
+    );
+
+    BuiltIn::Get.apply_to_collection(arguments)
+}
+
+/// Splits a `[key value]` list, as given to `conj` on a map
+fn as_pair(
+    pair: Expression,
+) -> Result<(Expression, Expression)> {
+    match <[Expression; 2]>::try_from(pair.as_list()?) {
+        Ok([key, value]) => Ok((key, value)),
+        Err(_) => Err(Error::TypeMismatch {
+            expected: "[key value] pair",
+            received: "list",
+        }),
+    }
+}
+
+/// How values read when turned into strings, which is as they're
+/// displayed, except for strings not being quoted
+fn display(expression: &Expression) -> String {
+    match expression {
+        Expression::Atom(Atom::String(string)) => {
+            string.to_string()
+        }
+        other => other.to_string(),
+    }
+}
+
+/// The characters of `string` from `start` up to `end`, or up to
+/// its end if not given. Indices count characters, not bytes
+fn substring(
+    string: &str,
+    start: i64,
+    end: Option<i64>,
+) -> Result<&str> {
+    let length = string.chars().count();
+    let end = end.unwrap_or(length as i64);
+    let out_of_bounds =
+        |index| Error::StringIndexOutOfBounds { index, length };
+
+    if start < 0 || start as usize > length {
+        return Err(out_of_bounds(start));
+    }
+    if end as usize > length {
+        return Err(out_of_bounds(end));
+    }
+    if end < start {
+        return Err(Error::ReversedRange { start, end });
+    }
+
+    // The byte offset of each character, and of the end of the
+    // string
+    let mut offsets = string
+        .char_indices()
+        .map(|(offset, _)| offset)
+        .chain([string.len()]);
+    // Safe unwraps: both indices were checked to be in bounds
+    let start_offset = offsets.nth(start as usize).unwrap();
+    let end_offset = if end == start {
+        start_offset
+    } else {
+        offsets.nth((end - start - 1) as usize).unwrap()
+    };
+
+    Ok(&string[start_offset..end_offset])
+}
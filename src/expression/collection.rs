@@ -0,0 +1,72 @@
+use std::{
+    hash::{BuildHasherDefault, Hash, Hasher},
+    mem,
+};
+
+use indexmap::{IndexMap, IndexSet};
+use rustc_hash::FxHasher;
+
+use super::{elements::Atom, Expression};
+
+/// A hash map of the form `{key value*}`. Entries are kept in
+/// insertion order, so that maps are displayed the way they
+/// were written
+pub type Map = IndexMap<
+    Expression,
+    Expression,
+    BuildHasherDefault<FxHasher>,
+>;
+
+/// A hash set of the form `#{item*}`, kept in insertion order
+pub type Set =
+    IndexSet<Expression, BuildHasherDefault<FxHasher>>;
+
+// Floats keep equality from being total, since NaN isn't equal
+// to itself. NaN keys may be inserted, but never found again
+impl Eq for Expression {}
+
+impl Hash for Expression {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
+
+        match self {
+            Expression::Atom(atom) => atom.hash(state),
+            Expression::List(list) => list.hash(state),
+            // Equal maps and sets may hold their items in a
+            // different order, so only their size is hashed
+            Expression::Map(map) => map.len().hash(state),
+            Expression::Set(set) => set.len().hash(state),
+            Expression::Quote(form) => form.hash(state),
+            // Unevaluated expressions only end up as keys of map
+            // literals, which are hashed again once evaluated
+            _ => {}
+        }
+    }
+}
+
+impl Hash for Atom {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
+
+        match self {
+            Atom::Integer(integer) => integer.hash(state),
+            Atom::BigInteger(integer) => integer.hash(state),
+            Atom::Rational(rational) => rational.hash(state),
+            // `0.0` and `-0.0` are equal, so they must hash
+            // alike
+            Atom::Number(float) if *float == 0.0 => {
+                0.0_f64.to_bits().hash(state)
+            }
+            Atom::Number(float) => float.to_bits().hash(state),
+            Atom::Symbol(string)
+            | Atom::Identifier(string)
+            | Atom::String(string) => string.hash(state),
+            Atom::Boolean(boolean) => boolean.hash(state),
+            Atom::BuiltIn(built_in) => built_in.hash(state),
+            Atom::NativeFn(native_fn) => {
+                native_fn.name().hash(state)
+            }
+            Atom::Nil => {}
+        }
+    }
+}
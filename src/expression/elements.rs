@@ -1,17 +1,33 @@
-use std::fmt::Display;
+use std::{
+    fmt::{Display, Write},
+    rc::Rc,
+};
+
+use num_bigint::BigInt;
+use num_rational::BigRational;
 
 mod lambda;
+mod pattern;
 
-pub use lambda::Lambda;
+pub(crate) use lambda::Destructured;
+pub use lambda::{Lambda, Parameters};
+pub use pattern::Pattern;
 
+use super::native::NativeFn;
 use crate::{
-    BuiltIn, CheapClone, Expression, SmallString, Typed,
+    BuiltIn, CheapClone, Expression, SmallString, Span, Typed,
 };
 
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub enum FnIdentifier {
     BuiltIn(BuiltIn),
+    /// A symbol, which looks itself up in the map it's applied
+    /// to, as in `(:name person)`
+    Symbol(SmallString),
     Other(SmallString),
+    /// Any other expression, evaluated to what gets called, as
+    /// in `((fn [x] x) 2)`
+    Expression(Rc<Expression>),
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -19,14 +35,49 @@ pub enum FnIdentifier {
 pub struct Application {
     pub name: FnIdentifier,
     pub arguments: Vec<Expression>,
+    pub span: Span,
 }
 
 /// Represents the binding of an
-/// identifier to an expression
+/// identifier, or of those of a pattern, to an expression
 #[derive(Debug, PartialEq, Clone)]
 pub struct Binding {
-    pub identifier: SmallString,
+    pub pattern: Pattern,
+    pub expression: Expression,
+    pub span: Span,
+}
+
+/// Represents a `let` expression, which binds identifiers
+/// to expressions in a new lexical scope.
+///
+/// Bindings are sequential: each one can refer to the ones
+/// preceding it
+#[derive(Debug, PartialEq, Clone)]
+pub struct Let {
+    pub bindings: Vec<(Pattern, Expression)>,
+    pub body: Expression,
+    pub span: Span,
+}
+
+/// Represents a `match` expression, which evaluates the branch
+/// of the first clause whose pattern matches the value of
+/// `expression`, or gives nil if none does
+#[derive(Debug, PartialEq, Clone)]
+pub struct Match {
     pub expression: Expression,
+    pub clauses: Vec<MatchClause>,
+    pub span: Span,
+}
+
+/// A clause of a `match` expression, of the form
+/// `pattern (:when guard)? branch`. The guard is evaluated with
+/// the bindings of the pattern, and must be true for the clause
+/// to match
+#[derive(Debug, PartialEq, Clone)]
+pub struct MatchClause {
+    pub pattern: Pattern,
+    pub guard: Option<Expression>,
+    pub branch: Expression,
 }
 
 /// Represents an `if` predicate
@@ -36,6 +87,7 @@ pub struct If {
     pub condition: Expression,
     /// Then do this
     pub do_this: Expression,
+    pub span: Span,
 }
 
 /// Represents an `if` predicate
@@ -45,14 +97,22 @@ pub struct IfElse {
     pub condition: Expression,
     pub if_true: Expression,
     pub if_false: Expression,
+    pub span: Span,
 }
 
-// CheapClone since `SmallString` is
+// CheapClone since `SmallString`, `NativeFn` and `Rc` are
 // cheap to clone and the rest is Copy
 impl CheapClone for Atom {}
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Atom {
+    /// An exact integer, e.g. `42`
+    Integer(i64),
+    /// An integer too large for an `i64`
+    BigInteger(Rc<BigInt>),
+    /// An exact fraction, e.g. `1/3`, whose denominator isn't 1
+    Rational(Rc<BigRational>),
+    /// A floating point number, e.g. `4.2`
     Number(f64),
     /// A symbol of the form `:symbol`
     Symbol(SmallString),
@@ -62,6 +122,8 @@ pub enum Atom {
     /// `true` or `false`
     Boolean(bool),
     BuiltIn(BuiltIn),
+    /// A function registered by the embedder
+    NativeFn(NativeFn),
     String(SmallString),
     Nil,
 }
@@ -72,13 +134,39 @@ impl Display for Atom {
         f: &mut std::fmt::Formatter<'_>,
     ) -> std::fmt::Result {
         match self {
-            Atom::Number(num) => write!(f, "{num}"),
+            Atom::Integer(integer) => write!(f, "{integer}"),
+            Atom::BigInteger(integer) => write!(f, "{integer}"),
+            Atom::Rational(rational) => write!(f, "{rational}"),
+            // Debug formatting keeps the decimal point of whole
+            // floats, setting them apart from integers
+            Atom::Number(num) => write!(f, "{num:?}"),
             Atom::Symbol(symbol) => write!(f, ":{symbol}"),
             Atom::Boolean(boolean) => write!(f, "{boolean}"),
-            Atom::String(string) => write!(f, "\"{string}\""),
+            Atom::String(string) => {
+                // Escaped so that strings read back as
+                // themselves
+                f.write_char('"')?;
+                for ch in string.chars() {
+                    match ch {
+                        '"' => f.write_str("\\\"")?,
+                        '\\' => f.write_str("\\\\")?,
+                        '\n' => f.write_str("\\n")?,
+                        '\t' => f.write_str("\\t")?,
+                        '\r' => f.write_str("\\r")?,
+                        ch if ch.is_control() => {
+                            write!(f, "\\u{{{:x}}}", ch as u32)?
+                        }
+                        ch => f.write_char(ch)?,
+                    }
+                }
+                f.write_char('"')
+            }
             Atom::BuiltIn(built_in) => {
                 write!(f, "<function {}>", built_in.rough_type())
             }
+            Atom::NativeFn(native_fn) => {
+                write!(f, "<function {}>", native_fn.name())
+            }
             Atom::Identifier(identifier) => {
                 f.write_str(identifier)
             }
@@ -96,9 +184,15 @@ impl Display for FnIdentifier {
             FnIdentifier::BuiltIn(built_in) => {
                 f.write_str(built_in.rough_type())
             }
+            FnIdentifier::Symbol(symbol) => {
+                write!(f, ":{symbol}")
+            }
             FnIdentifier::Other(identifier) => {
                 f.write_str(identifier)
             }
+            FnIdentifier::Expression(callee) => {
+                write!(f, "{callee}")
+            }
         }
     }
 }
@@ -108,7 +202,12 @@ impl Display for Application {
         &self,
         f: &mut std::fmt::Formatter<'_>,
     ) -> std::fmt::Result {
-        write!(f, "<function {}> ", self.name)
+        // Shown as code, which is how macros see applications
+        write!(f, "({}", self.name)?;
+        for argument in &self.arguments {
+            write!(f, " {argument}")?;
+        }
+        f.write_str(")")
     }
 }
 
@@ -130,10 +229,41 @@ impl Display for Binding {
         &self,
         f: &mut std::fmt::Formatter<'_>,
     ) -> std::fmt::Result {
-        write!(
-            f,
-            "(def {} ({}))",
-            self.identifier, self.expression
-        )
+        write!(f, "(def {} {})", self.pattern, self.expression)
+    }
+}
+
+impl Display for Match {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        write!(f, "(match {}", self.expression)?;
+        for clause in &self.clauses {
+            write!(f, " {}", clause.pattern)?;
+            if let Some(guard) = &clause.guard {
+                write!(f, " :when {guard}")?;
+            }
+            write!(f, " {}", clause.branch)?;
+        }
+        f.write_str(")")
+    }
+}
+
+impl Display for Let {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        f.write_str("(let [")?;
+        for (idx, (pattern, expression)) in
+            self.bindings.iter().enumerate()
+        {
+            if idx > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{pattern} {expression}")?;
+        }
+        write!(f, "] {})", self.body)
     }
 }
@@ -1,274 +1,749 @@
+use std::rc::Rc;
+
+use super::{Let, Pattern};
 use crate::{
-    ensure_exact_arity, Atom, Env, Error, Evaluable, Expression,
-    Result, SmallString,
+    check::ensure_lambda_arity, evaluatable::Step, scope::Scope,
+    Atom, Env, Expression, Result, SmallString, Span,
 };
+#[cfg(feature = "bytecode")]
+use crate::{vm, vm::Function};
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Lambda {
-    pub arguments: Vec<SmallString>,
-    pub body: Expression,
+    pub parameters: Parameters,
+    /// Shared so that lambdas are cheap to clone, as happens
+    /// whenever they're looked up
+    pub body: Rc<Expression>,
+    /// The scope this lambda was defined in, captured when the
+    /// `fn` expression is evaluated. `None` until then, which
+    /// tells `fn` expressions apart from the lambdas they
+    /// evaluate to: those defined at the top level capture an
+    /// empty scope
+    pub captured: Option<Rc<Scope>>,
+    pub span: Span,
+    /// Parameters written as part of a quasiquote template,
+    /// unquoting some or all of them, as in `[x ~@more]` or
+    /// `~params`. They're kept as the items of the vector they
+    /// stand for, `~params` being `[~@params]`, and
+    /// `parameters` are left empty until the template is
+    /// filled in
+    pub(crate) template: Option<Vec<Expression>>,
+    /// The body compiled to bytecode, set for lambdas created
+    /// by the bytecode VM
+    #[cfg(feature = "bytecode")]
+    pub(crate) compiled: Option<Rc<Function>>,
+}
+
+/// The parameters of a lambda, of the form
+/// `[required* (optional default)* (& rest)?]`
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Parameters {
+    pub required: Vec<SmallString>,
+    /// Parameters that may be left out, along with the
+    /// expression giving their value when they are. Defaults
+    /// may refer to the parameters before them
+    pub optional: Vec<(SmallString, Expression)>,
+    /// Bound to a list of the arguments given past the optional
+    /// ones
+    pub rest: Option<SmallString>,
+}
+
+/// `let` bindings destructuring the parameters of a lambda
+/// that were written as a pattern
+pub(crate) type Destructured = Vec<(Pattern, Expression)>;
+
+impl Parameters {
+    /// Parameters written as patterns. Those other than an
+    /// identifier are given a name of their own, which gets
+    /// destructured at the start of the body
+    pub(crate) fn from_patterns(
+        required: Vec<Pattern>,
+        optional: Vec<(Pattern, Expression)>,
+        rest: Option<Pattern>,
+    ) -> (Self, Destructured) {
+        let mut destructured = vec![];
+        let mut name = |pattern| match pattern {
+            Pattern::Identifier(identifier) => identifier,
+            pattern => {
+                // Identifiers can't be written with a `%`, so
+                // this one can't clash with any of them
+                let identifier = SmallString::new(format!(
+                    "%{}",
+                    destructured.len()
+                ));
+                destructured.push((
+                    pattern,
+                    Expression::Atom(Atom::Identifier(
+                        identifier.clone(),
+                    )),
+                ));
+                identifier
+            }
+        };
+
+        let parameters = Parameters {
+            required: required
+                .into_iter()
+                .map(&mut name)
+                .collect(),
+            optional: optional
+                .into_iter()
+                .map(|(pattern, default)| {
+                    (name(pattern), default)
+                })
+                .collect(),
+            rest: rest.map(&mut name),
+        };
+        (parameters, destructured)
+    }
+
+    /// Identifiers of these parameters, in the order their
+    /// arguments are given
+    pub fn identifiers(
+        &self,
+    ) -> impl Iterator<Item = &SmallString> {
+        self.required
+            .iter()
+            .chain(
+                self.optional
+                    .iter()
+                    .map(|(identifier, _)| identifier),
+            )
+            .chain(&self.rest)
+    }
+
+    pub(crate) fn ensure_arity(
+        &self,
+        received: usize,
+    ) -> Result<()> {
+        ensure_lambda_arity(
+            self.required.len(),
+            self.optional.len(),
+            self.rest.is_some(),
+            received,
+        )
+    }
 }
 
 impl Lambda {
+    /// A lambda whose body starts by destructuring its
+    /// parameters written as a pattern
+    pub(crate) fn destructuring(
+        (parameters, destructured): (Parameters, Destructured),
+        body: Expression,
+        span: Span,
+    ) -> Self {
+        let body = if destructured.is_empty() {
+            body
+        } else {
+            Expression::Let(Box::new(Let {
+                bindings: destructured,
+                body,
+                span,
+            }))
+        };
+
+        Lambda {
+            parameters,
+            body: Rc::new(body),
+            captured: None,
+            span,
+            template: None,
+            #[cfg(feature = "bytecode")]
+            compiled: None,
+        }
+    }
+
+    /// A lambda within a template, whose parameters are only
+    /// known once it's filled in
+    pub(crate) fn template(
+        parameters: Vec<Expression>,
+        body: Expression,
+        span: Span,
+    ) -> Self {
+        Lambda {
+            template: Some(parameters),
+            ..Self::destructuring(Default::default(), body, span)
+        }
+    }
+
+    /// Applies this lambda to the given, already evaluated,
+    /// arguments
     pub fn apply(
-        mut self,
-        mut received_arguments: Vec<Expression>,
+        &self,
+        received_arguments: Vec<Expression>,
         env: &mut Env,
     ) -> Result<Expression> {
-        // The code below is essentially the same as building a
-        // local scope with the given arguments into
-        // `env` and then calling `self.body.evaluate(env)`,
-        // but allows us to better manage the arguments'
-        // ownership, and should be generally faster
-        // since we can avoid costly emulated frame stack
-        // building and destruction, at the cost of
-        // having big and weird code
-        ensure_exact_arity(
-            self.arguments.len() as _,
-            received_arguments.len() as _,
-        )?;
-
-        match self.body {
+        self.call(received_arguments, env)?.finish(env)
+    }
+
+    /// Same as [`Lambda::apply`], but leaving the body of
+    /// lambdas that weren't compiled for the caller to
+    /// evaluate
+    #[cfg_attr(
+        not(feature = "bytecode"),
+        allow(unused_variables)
+    )]
+    pub(crate) fn call(
+        &self,
+        received_arguments: Vec<Expression>,
+        env: &mut Env,
+    ) -> Result<Step> {
+        #[cfg(feature = "bytecode")]
+        if let Some(function) = &self.compiled {
+            return vm::call(
+                function.clone(),
+                self.captured.clone(),
+                received_arguments,
+                env,
+            )
+            .map(Step::Done);
+        }
+
+        self.bind(received_arguments)
+    }
+
+    /// Binds the given, already evaluated, arguments, leaving
+    /// the body to be evaluated by the caller. This is what
+    /// allows calls in tail position not to grow the stack
+    pub(crate) fn bind(
+        &self,
+        mut received_arguments: Vec<Expression>,
+    ) -> Result<Step> {
+        let parameters = &self.parameters;
+        parameters.ensure_arity(received_arguments.len())?;
+
+        let mut defaults: Vec<_> = parameters
+            .optional
+            .iter()
+            .skip(
+                received_arguments.len()
+                    - parameters.required.len(),
+            )
+            .map(|(identifier, default)| {
+                (
+                    Pattern::Identifier(identifier.clone()),
+                    default.clone(),
+                )
+            })
+            .collect();
+        if let Some(rest) = &parameters.rest {
+            // Only arguments past every optional one are left
+            // for the rest parameter
+            if defaults.is_empty() {
+                let given = parameters.required.len()
+                    + parameters.optional.len();
+                let rest = received_arguments.split_off(given);
+                received_arguments.push(Expression::List(rest));
+            } else {
+                defaults.push((
+                    Pattern::Identifier(rest.clone()),
+                    Expression::List(vec![]),
+                ));
+            }
+        }
+
+        let identifiers: Vec<_> = parameters
+            .identifiers()
+            .take(received_arguments.len())
+            .cloned()
+            .collect();
+
+        if !defaults.is_empty() {
+            // Defaults are evaluated as the bindings of a `let`
+            // within the scope of the arguments given, so that
+            // they can refer to them
+            let scope = Scope::new(
+                identifiers,
+                received_arguments,
+                self.captured.clone(),
+            );
+
+            return Ok(Step::Continue {
+                scope: Some(Rc::new(scope)),
+                expression: Expression::Let(Box::new(Let {
+                    bindings: defaults,
+                    body: Expression::clone(&self.body),
+                    span: self.span,
+                })),
+            });
+        }
+
+        // Building a scope is only needed when the body can
+        // refer to the arguments, so we skip it for the trivial
+        // cases below
+        let step = match &*self.body {
             Expression::Atom(Atom::Identifier(identifier)) => {
-                // TODO: check if identifier is in the outer
-                // scope
-                let idx = self
-                    .arguments
+                match identifiers
                     .iter()
-                    .position(|arg| arg == &identifier)
-                    .ok_or(Error::UnknownSymbol(identifier))?;
-
-                Ok(received_arguments.swap_remove(idx))
+                    .rposition(|arg| arg == identifier)
+                {
+                    Some(idx) => Step::Done(
+                        received_arguments.swap_remove(idx),
+                    ),
+                    // Not an argument, so it must come from
+                    // the enclosing scopes, e.g.: `(fn [] x)`
+                    None => Step::Continue {
+                        scope: self.captured.clone(),
+                        expression: Expression::Atom(
+                            Atom::Identifier(identifier.clone()),
+                        ),
+                    },
+                }
             }
             Expression::Atom(atom) => {
                 // Other atoms are trivial, so we'll just return
                 // them. E.g.: `(fn [] 3)`
-                Ok(Expression::Atom(atom))
-            }
-            Expression::List(list) => {
-                // Similarly, we'll only evaluate the contents of
-                // lists whenever they get consumed by something
-                Ok(Expression::List(list))
-            }
-            Expression::Application(_)
-            | Expression::If(_)
-            | Expression::IfElse(_) => {
-                self.body.resolve_all(
-                    &self.arguments,
-                    &received_arguments,
-                    env,
-                )?;
-                self.body.evaluate(env)
+                Step::Done(Expression::Atom(atom.clone()))
             }
-            Expression::Binding(binding) => {
-                binding.evaluate(env)
+            // Anything else, `do` blocks included, may refer to
+            // the arguments anywhere within it
+            _ => {
+                let scope = Scope::new(
+                    identifiers,
+                    received_arguments,
+                    self.captured.clone(),
+                );
+
+                Step::Continue {
+                    scope: Some(Rc::new(scope)),
+                    expression: Expression::clone(&self.body),
+                }
             }
-            Expression::Lambda(lambda) => {
-                lambda.apply(received_arguments, env)
-            }
-        }
+        };
+
+        Ok(step)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{Atom, BuiltIn, Expression, Interpreter};
+    use crate::{
+        Atom, BuiltIn, Error, Expression, Interpreter,
+        SmallString,
+    };
 
     #[test]
     /// Evaluates "atomic" lambdas: that is, lambdas that just
     /// return an atom
     fn evaluates_atomic_lambdas() {
-        let mut interp = Interpreter::new();
-
-        assert!(interp
-            .parse_and_eval("(def ok (fn [] :ok))")
-            .is_ok());
-
-        assert_eq!(
-            interp.parse_and_eval("(= (ok) :ok)").unwrap(),
-            true.into()
-        );
-
-        assert!(interp
-            .parse_and_eval("(def nothing (fn [x] nil))")
-            .is_ok());
-
-        assert_eq!(
-            interp
-                .parse_and_eval("(= (nothing 2) nil)")
-                .unwrap(),
-            true.into()
-        );
-
-        assert_eq!(
-            interp
+        for mut interp in Interpreter::all_backends() {
+            assert!(interp
+                .parse_and_eval("(def ok (fn [] :ok))")
+                .is_ok());
+
+            assert_eq!(
+                interp.parse_and_eval("(= (ok) :ok)").unwrap(),
+                true.into()
+            );
+
+            assert!(interp
+                .parse_and_eval("(def nothing (fn [x] nil))")
+                .is_ok());
+
+            assert_eq!(
+                interp
+                    .parse_and_eval("(= (nothing 2) nil)")
+                    .unwrap(),
+                true.into()
+            );
+
+            assert_eq!(
+                interp
+                    .parse_and_eval(
+                        "(= (nothing nothing) (nothing nil) nil)"
+                    )
+                    .unwrap(),
+                true.into()
+            );
+        }
+    }
+
+    #[test]
+    fn evaluates_closures() {
+        for mut interp in Interpreter::all_backends() {
+            assert!(interp
                 .parse_and_eval(
-                    "(= (nothing nothing) (nothing nil) nil)"
+                    "(def make-adder (fn [x] (fn [y] (+ x y))))"
                 )
-                .unwrap(),
-            true.into()
-        );
+                .is_ok());
+            assert!(interp
+                .parse_and_eval("(def add-two (make-adder 2))")
+                .is_ok());
+
+            assert_eq!(
+                interp.parse_and_eval("(add-two 3)").unwrap(),
+                5_i64.into()
+            );
+
+            // The captured `x` must not leak into the global
+            // scope
+            assert_eq!(
+                interp.parse_and_eval("x").unwrap_err(),
+                Error::UnknownSymbol(SmallString::new("x"))
+            );
+
+            // Currying through several levels of nesting
+            assert!(interp
+                .parse_and_eval(
+                    "(def curried (fn [a] (fn [b] (fn [c] [a b c]))))"
+                )
+                .is_ok());
+            assert!(interp
+                .parse_and_eval("(def partial (curried 1))")
+                .is_ok());
+            assert!(interp
+                .parse_and_eval("(def last-step (partial 2))")
+                .is_ok());
+            assert_eq!(
+                interp.parse_and_eval("(last-step 3)").unwrap(),
+                Expression::List(vec![
+                    1_i64.into(),
+                    2_i64.into(),
+                    3_i64.into()
+                ])
+            );
+
+            // Inner arguments shadow captured ones
+            assert!(interp
+                .parse_and_eval(
+                    "(def shadowed (fn [x] (fn [x] (* x 10))))"
+                )
+                .is_ok());
+            assert!(interp
+                .parse_and_eval("(def times-ten (shadowed 1))")
+                .is_ok());
+            assert_eq!(
+                interp.parse_and_eval("(times-ten 5)").unwrap(),
+                50_i64.into()
+            );
+
+            // Closures used as callbacks
+            assert!(interp
+                .parse_and_eval(
+                    "(def multiples-in (fn [n xs] (count (fn [x] (= (% x n) 0)) xs)))"
+                )
+                .is_ok());
+            assert_eq!(
+                interp
+                    .parse_and_eval(
+                        "(multiples-in 3 [3 4 6 9 10])"
+                    )
+                    .unwrap(),
+                3_i64.into()
+            );
+
+            // Captured bindings may be returned directly
+            assert!(interp
+                .parse_and_eval(
+                    "(def constantly (fn [x] (fn [] x)))"
+                )
+                .is_ok());
+            assert!(interp
+                .parse_and_eval(
+                    "(def always-ok (constantly :ok))"
+                )
+                .is_ok());
+            assert_eq!(
+                interp
+                    .parse_and_eval("(= (always-ok) :ok)")
+                    .unwrap(),
+                true.into()
+            );
+
+            // Lambdas passed around as values keep the scope
+            // they were defined in, top level included
+            assert!(interp.parse_and_eval("(def y 99)").is_ok());
+            assert!(interp
+                .parse_and_eval("(def get-y (fn [] y))")
+                .is_ok());
+            assert_eq!(
+                interp
+                    .parse_and_eval(
+                        "(let [y 1] ((apply first [[get-y]])))"
+                    )
+                    .unwrap(),
+                99_i64.into()
+            );
+            assert_eq!(
+                interp
+                    .parse_and_eval(
+                        "(let [y 1] ((first (map (fn [f] f) [get-y]))))"
+                    )
+                    .unwrap(),
+                99_i64.into()
+            );
+        }
     }
 
     #[test]
-    #[ignore = "used only for quick benchmarks"]
-    fn stress_test() {
-        let mut interp = Interpreter::new();
+    fn evaluates_variadic_and_optional_lambdas() {
+        for mut interp in Interpreter::all_backends() {
+            let mut eval = |input: &str| {
+                interp
+                    .parse_and_eval(input)
+                    .map(|expression| expression.to_string())
+            };
+
+            // Rest parameters
+            eval("(def sum (fn [& xs] (reduce + 0 xs)))")
+                .unwrap();
+            assert_eq!(eval("(sum)").unwrap(), "0");
+            assert_eq!(eval("(sum 1 2 3)").unwrap(), "6");
+            assert_eq!(eval("(apply sum [4 5])").unwrap(), "9");
+            assert_eq!(
+                eval("((fn [& xs] xs) 1 2)").unwrap(),
+                "[1 2]"
+            );
+            eval("(def tagged (fn [tag & more] [tag more]))")
+                .unwrap();
+            assert_eq!(eval("(tagged :a)").unwrap(), "[:a []]");
+            assert_eq!(
+                eval("(tagged :a 1 2)").unwrap(),
+                "[:a [1 2]]"
+            );
+            assert_eq!(
+                eval("(tagged)").unwrap_err().inner(),
+                &Error::MinimumArityMismatch {
+                    at_least: 1,
+                    received: 0
+                }
+            );
 
-        assert!(interp
-            .parse_and_eval("(def even? (fn [x] (= (% x 2) 0)))")
-            .is_ok());
+            // Optional parameters, whose defaults may refer to
+            // the parameters before them
+            eval("(def point (fn [x (y 10) (z (+ x y))] [x y z]))")
+                .unwrap();
+            assert_eq!(eval("(point 1)").unwrap(), "[1 10 11]");
+            assert_eq!(eval("(point 1 2)").unwrap(), "[1 2 3]");
+            assert_eq!(
+                eval("(point 1 2 5)").unwrap(),
+                "[1 2 5]"
+            );
+            assert_eq!(
+                eval("(point)").unwrap_err().inner(),
+                &Error::MinimumArityMismatch {
+                    at_least: 1,
+                    received: 0
+                }
+            );
+            assert_eq!(
+                eval("(point 1 2 3 4)").unwrap_err().inner(),
+                &Error::ExactArityMismatch {
+                    expected: 3,
+                    received: 4
+                }
+            );
 
-        for n in -20000..500000 {
-            let is_even = n % 2 == 0;
-            let line = format!("(even? {n})");
+            // More arguments than fit in a byte aren't counted
+            // as fewer
+            let items = vec!["0"; 257].join(" ");
+            eval(&format!("(def l [{items}])")).unwrap();
+            eval("(def g (fn [x] x))").unwrap();
+            assert_eq!(
+                eval("(apply g l)").unwrap_err().inner(),
+                &Error::ExactArityMismatch {
+                    expected: 1,
+                    received: 257
+                }
+            );
 
+            // Both at once
+            eval("(def f (fn [x (y :y) & more] [x y more]))")
+                .unwrap();
+            assert_eq!(eval("(f 1)").unwrap(), "[1 :y []]");
+            assert_eq!(eval("(f 1 2)").unwrap(), "[1 2 []]");
             assert_eq!(
-                interp.parse_and_eval(&line),
-                Ok(is_even.into())
+                eval("(f 1 2 3 4)").unwrap(),
+                "[1 2 [3 4]]"
+            );
+
+            // Defaults are evaluated where the lambda is defined
+            assert_eq!(
+                eval("(let [d 5] ((fn [(x d)] x)))").unwrap(),
+                "5"
+            );
+            assert_eq!(
+                eval("(map (fn [x (y 1)] (+ x y)) [1 2])")
+                    .unwrap(),
+                "[2 3]"
             );
         }
     }
 
+    #[test]
+    #[ignore = "used only for quick benchmarks"]
+    fn stress_test() {
+        for mut interp in Interpreter::all_backends() {
+            assert!(interp
+                .parse_and_eval(
+                    "(def even? (fn [x] (= (% x 2) 0)))"
+                )
+                .is_ok());
+
+            for n in -20000..500000 {
+                let is_even = n % 2 == 0;
+                let line = format!("(even? {n})");
+
+                assert_eq!(
+                    interp.parse_and_eval(&line),
+                    Ok(is_even.into())
+                );
+            }
+        }
+    }
+
     #[test]
     /// Evaluates lambdas that consist of a built-in numeric
     /// operation
     fn evaluates_numeric_lambdas() {
-        let mut interp = Interpreter::new();
+        for mut interp in Interpreter::all_backends() {
+            assert!(interp
+                .parse_and_eval(
+                    "(def even? (fn [x] (= (% x 2) 0)))"
+                )
+                .is_ok());
 
-        assert!(interp
-            .parse_and_eval("(def even? (fn [x] (= (% x 2) 0)))")
-            .is_ok());
+            for n in -200..500 {
+                let is_even = n % 2 == 0;
+                let line = format!("(even? {n})");
 
-        for n in -200..500 {
-            let is_even = n % 2 == 0;
-            let line = format!("(even? {n})");
+                assert_eq!(
+                    interp.parse_and_eval(&line),
+                    Ok(is_even.into())
+                );
+            }
+
+            assert!(interp
+                .parse_and_eval("(def eight (* 2 2 2))")
+                .is_ok());
 
             assert_eq!(
-                interp.parse_and_eval(&line),
-                Ok(is_even.into())
+                interp.parse_and_eval("(even? eight)").unwrap(),
+                true.into()
             );
-        }
 
-        assert!(interp
-            .parse_and_eval("(def eight (* 2 2 2))")
-            .is_ok());
-
-        assert_eq!(
-            interp.parse_and_eval("(even? eight)").unwrap(),
-            true.into()
-        );
-
-        assert_eq!(
-            interp
-                .parse_and_eval("(even? (* eight eight))")
-                .unwrap(),
-            true.into()
-        );
-
-        assert_eq!(
-            interp
-                .parse_and_eval("(even? (- (* eight eight) 1))")
-                .unwrap(),
-            false.into()
-        );
-
-        assert!(interp
-            .parse_and_eval(
-                "(def times-eight (fn [y] (* eight y)))"
-            )
-            .is_ok());
+            assert_eq!(
+                interp
+                    .parse_and_eval("(even? (* eight eight))")
+                    .unwrap(),
+                true.into()
+            );
+
+            assert_eq!(
+                interp
+                    .parse_and_eval(
+                        "(even? (- (* eight eight) 1))"
+                    )
+                    .unwrap(),
+                false.into()
+            );
 
-        assert_eq!(
-            interp
+            assert!(interp
                 .parse_and_eval(
-                    "(= (times-eight 2) (* 2 2 2 2))"
+                    "(def times-eight (fn [y] (* eight y)))"
                 )
-                .unwrap(),
-            true.into()
-        );
+                .is_ok());
+
+            assert_eq!(
+                interp
+                    .parse_and_eval(
+                        "(= (times-eight 2) (* 2 2 2 2))"
+                    )
+                    .unwrap(),
+                true.into()
+            );
+        }
     }
 
     #[test]
     /// Evaluates lambdas that consist of a conditional
     fn evaluates_branching_lambdas() {
-        let mut interp = Interpreter::new();
-
-        assert!(interp
-            .parse_and_eval(
-                "(def unwrap-or (fn [maybe-nil fallback] (if (= maybe-nil nil) fallback maybe-nil)))"
-            )
-            .is_ok());
+        for mut interp in Interpreter::all_backends() {
+            assert!(interp
+                .parse_and_eval(
+                    "(def unwrap-or (fn [maybe-nil fallback] (if (= maybe-nil nil) fallback maybe-nil)))"
+                )
+                .is_ok());
 
-        assert_eq!(
-            interp.parse_and_eval("(unwrap-or nil 2)").unwrap(),
-            2.0.into()
-        );
+            assert_eq!(
+                interp
+                    .parse_and_eval("(unwrap-or nil 2)")
+                    .unwrap(),
+                2_i64.into()
+            );
 
-        assert_eq!(
-            interp.parse_and_eval("(unwrap-or + -)").unwrap(),
-            Expression::Atom(Atom::BuiltIn(BuiltIn::Plus))
-        );
+            assert_eq!(
+                interp
+                    .parse_and_eval("(unwrap-or + -)")
+                    .unwrap(),
+                Expression::Atom(Atom::BuiltIn(BuiltIn::Plus))
+            );
 
-        assert!(interp
-            .parse_and_eval(
-                "(def zero-or-nil (fn [n] (if (= n 0) n)))"
-            )
-            .is_ok());
+            assert!(interp
+                .parse_and_eval(
+                    "(def zero-or-nil (fn [n] (if (= n 0) n)))"
+                )
+                .is_ok());
 
-        assert_eq!(
-            interp.parse_and_eval("(zero-or-nil 5.0)").unwrap(),
-            Expression::default()
-        );
+            assert_eq!(
+                interp
+                    .parse_and_eval("(zero-or-nil 5.0)")
+                    .unwrap(),
+                Expression::default()
+            );
 
-        assert_eq!(
-            interp.parse_and_eval("(zero-or-nil 0.0)").unwrap(),
-            0.0.into()
-        );
+            assert_eq!(
+                interp
+                    .parse_and_eval("(zero-or-nil 0.0)")
+                    .unwrap(),
+                0.0.into()
+            );
 
-        assert_eq!(
-            interp
-                .parse_and_eval(
-                    "(unwrap-or (zero-or-nil 0.0) 5.0)"
-                )
-                .unwrap(),
-            0.0.into()
-        );
+            assert_eq!(
+                interp
+                    .parse_and_eval(
+                        "(unwrap-or (zero-or-nil 0.0) 5.0)"
+                    )
+                    .unwrap(),
+                0.0.into()
+            );
 
-        assert_eq!(
-            interp
-                .parse_and_eval(
-                    "(unwrap-or (zero-or-nil 3.0) 5.0)"
-                )
-                .unwrap(),
-            5.0.into()
-        );
+            assert_eq!(
+                interp
+                    .parse_and_eval(
+                        "(unwrap-or (zero-or-nil 3.0) 5.0)"
+                    )
+                    .unwrap(),
+                5.0.into()
+            );
+        }
     }
 
     #[test]
     fn subs_identifiers_by_their_values() {
-        let mut interp = Interpreter::new();
-
-        assert!(interp
-            .parse_and_eval("(def id (fn [x] x)))")
-            .is_ok());
-
-        assert_eq!(
-            interp
-                .parse_and_eval("(= (id :success) :success)")
-                .unwrap(),
-            true.into()
-        );
-
-        assert!(interp
-            .parse_and_eval("(def double (fn [x] (+ x x)))")
-            .is_ok());
-
-        assert_eq!(
-            interp
-                .parse_and_eval("(= (double 2) (* 4 1))")
-                .unwrap(),
-            true.into()
-        );
+        for mut interp in Interpreter::all_backends() {
+            assert!(interp
+                .parse_and_eval("(def id (fn [x] x))")
+                .is_ok());
+
+            assert_eq!(
+                interp
+                    .parse_and_eval("(= (id :success) :success)")
+                    .unwrap(),
+                true.into()
+            );
+
+            assert!(interp
+                .parse_and_eval("(def double (fn [x] (+ x x)))")
+                .is_ok());
+
+            assert_eq!(
+                interp
+                    .parse_and_eval("(= (double 2) (* 4 1))")
+                    .unwrap(),
+                true.into()
+            );
+        }
     }
 }
@@ -0,0 +1,272 @@
+use std::{fmt::Display, rc::Rc};
+
+use crate::{
+    expression::builtin::equal, Error, Expression, Result,
+    SmallString,
+};
+
+/// What a value gets destructured with when it's bound, as in
+/// `(let [[x y] point] ...)`
+#[derive(Debug, PartialEq, Clone)]
+pub enum Pattern {
+    /// Binds the whole value
+    Identifier(SmallString),
+    /// `_`, which matches anything without binding it
+    Wildcard,
+    /// A number, string, symbol, boolean or nil, which only
+    /// matches values equal to it
+    Literal(Expression),
+    /// `[pattern* (& pattern)?]`, destructuring each item of a
+    /// list. Without a rest pattern, the list must have exactly
+    /// as many items as there are patterns
+    List {
+        items: Vec<Pattern>,
+        rest: Option<Box<Pattern>>,
+    },
+    /// `{(pattern key)* (:keys [identifier*])?}`, destructuring
+    /// the values of a map under the given keys, which are nil
+    /// when missing. `:keys` binds each identifier to the value
+    /// under the symbol of the same name
+    Map(Vec<(Pattern, Expression)>),
+    /// `~form`, within a quasiquote template: the pattern the
+    /// code `form` evaluates to, as in `(let [~name 1] ...)`
+    Unquote(Rc<Expression>),
+}
+
+impl Pattern {
+    /// Identifiers bound by this pattern, in the order their
+    /// values are bound
+    pub fn identifiers(&self) -> Vec<&SmallString> {
+        let mut identifiers = vec![];
+        self.collect_identifiers(&mut identifiers);
+        identifiers
+    }
+
+    /// An identifier this pattern binds more than once, as is
+    /// `x` in `[x x]`. Its values could differ, so such patterns
+    /// are rejected rather than binding the last of them
+    pub(crate) fn repeated_identifier(
+        &self,
+    ) -> Option<&SmallString> {
+        let identifiers = self.identifiers();
+        identifiers.iter().enumerate().find_map(
+            |(idx, identifier)| {
+                identifiers[..idx]
+                    .contains(identifier)
+                    .then_some(*identifier)
+            },
+        )
+    }
+
+    fn collect_identifiers<'a>(
+        &'a self,
+        identifiers: &mut Vec<&'a SmallString>,
+    ) {
+        match self {
+            Pattern::Identifier(identifier) => {
+                identifiers.push(identifier)
+            }
+            Pattern::Wildcard
+            | Pattern::Literal(_)
+            | Pattern::Unquote(_) => {}
+            Pattern::List { items, rest } => {
+                for item in items.iter().chain(rest.as_deref()) {
+                    item.collect_identifiers(identifiers);
+                }
+            }
+            Pattern::Map(entries) => {
+                for (pattern, _) in entries {
+                    pattern.collect_identifiers(identifiers);
+                }
+            }
+        }
+    }
+
+    /// Whether `value` has the shape of this pattern, as is
+    /// checked for each clause of a `match`. Not matching isn't
+    /// an error there, so nothing gets built to report it
+    pub(crate) fn matches(&self, value: &Expression) -> bool {
+        match self {
+            Pattern::Identifier(_) | Pattern::Wildcard => true,
+            Pattern::Literal(literal) => equal(value, literal),
+            Pattern::List { items, rest } => match value {
+                Expression::List(list) => Self::matches_items(
+                    items,
+                    rest.as_deref(),
+                    list,
+                ),
+                _ => false,
+            },
+            Pattern::Map(entries) => match value {
+                Expression::Map(map) => entries.iter().all(
+                    |(pattern, key)| match map.get(key) {
+                        Some(value) => pattern.matches(value),
+                        None => pattern
+                            .matches(&Expression::default()),
+                    },
+                ),
+                _ => false,
+            },
+            // So that destructuring reports it
+            Pattern::Unquote(_) => true,
+        }
+    }
+
+    /// Whether the items of a list match those of a list
+    /// pattern, taking slices of it for rest patterns rather
+    /// than building the lists they'd be bound to
+    fn matches_items(
+        items: &[Pattern],
+        rest: Option<&Pattern>,
+        list: &[Expression],
+    ) -> bool {
+        let length_matches = match rest {
+            Some(_) => list.len() >= items.len(),
+            None => list.len() == items.len(),
+        };
+        if !length_matches
+            || !items
+                .iter()
+                .zip(list)
+                .all(|(item, value)| item.matches(value))
+        {
+            return false;
+        }
+
+        let remaining = &list[items.len()..];
+        match rest {
+            None
+            | Some(Pattern::Identifier(_) | Pattern::Wildcard) => {
+                true
+            }
+            Some(Pattern::List { items, rest }) => {
+                Self::matches_items(
+                    items,
+                    rest.as_deref(),
+                    remaining,
+                )
+            }
+            // Neither of which a list ever matches
+            Some(Pattern::Literal(_) | Pattern::Map(_)) => false,
+            Some(Pattern::Unquote(_)) => true,
+        }
+    }
+
+    /// Destructures `value`, handing each identifier bound to
+    /// `bind` along with its value. Fails if the value doesn't
+    /// have the shape of this pattern, i.e. if it doesn't match
+    pub(crate) fn destructure(
+        &self,
+        value: Expression,
+        bind: &mut impl FnMut(&SmallString, Expression),
+    ) -> Result<()> {
+        match self {
+            Pattern::Identifier(identifier) => {
+                bind(identifier, value)
+            }
+            Pattern::Wildcard => {}
+            Pattern::Literal(literal) => {
+                if !equal(&value, literal) {
+                    return Err(Error::LiteralMismatch(
+                        SmallString::new(literal.to_string()),
+                    ));
+                }
+            }
+            Pattern::List { items, rest } => {
+                let mut list = value.as_list()?;
+
+                match rest {
+                    Some(_) if list.len() < items.len() => {
+                        return Err(
+                            Error::MinimumLengthMismatch {
+                                at_least: items.len(),
+                                received: list.len(),
+                            },
+                        )
+                    }
+                    None if list.len() != items.len() => {
+                        return Err(Error::ExactLengthMismatch {
+                            expected: items.len(),
+                            received: list.len(),
+                        })
+                    }
+                    _ => {}
+                }
+
+                let remaining = list.split_off(items.len());
+                for (item, value) in items.iter().zip(list) {
+                    item.destructure(value, bind)?;
+                }
+                if let Some(rest) = rest {
+                    rest.destructure(
+                        Expression::List(remaining),
+                        bind,
+                    )?;
+                }
+            }
+            Pattern::Map(entries) => {
+                let map = value.as_map()?;
+
+                for (pattern, key) in entries {
+                    let value = map
+                        .get(key)
+                        .cloned()
+                        .unwrap_or_default();
+                    pattern.destructure(value, bind)?;
+                }
+            }
+            // Only templates may hold them, as is checked when
+            // expanding macros, but code can also be evaluated
+            // without expanding them
+            Pattern::Unquote(_) => {
+                return Err(Error::UnquoteOutsideQuasiquote)
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for Pattern {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        match self {
+            Pattern::Identifier(identifier) => {
+                write!(f, "{identifier}")
+            }
+            Pattern::Wildcard => f.write_str("_"),
+            Pattern::Literal(literal) => write!(f, "{literal}"),
+            Pattern::List { items, rest } => {
+                f.write_str("[")?;
+                for (idx, item) in items.iter().enumerate() {
+                    if idx > 0 {
+                        f.write_str(" ")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                if let Some(rest) = rest {
+                    if !items.is_empty() {
+                        f.write_str(" ")?;
+                    }
+                    write!(f, "& {rest}")?;
+                }
+                f.write_str("]")
+            }
+            Pattern::Map(entries) => {
+                f.write_str("{")?;
+                for (idx, (pattern, key)) in
+                    entries.iter().enumerate()
+                {
+                    if idx > 0 {
+                        f.write_str(" ")?;
+                    }
+                    write!(f, "{pattern} {key}")?;
+                }
+                f.write_str("}")
+            }
+            Pattern::Unquote(form) => write!(f, "~{form}"),
+        }
+    }
+}
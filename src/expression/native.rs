@@ -0,0 +1,390 @@
+use std::{fmt, rc::Rc};
+
+use crate::{
+    ensure_exact_arity, Atom, Expression, Result, SmallString,
+};
+
+type NativeFnInner =
+    dyn Fn(Vec<Expression>) -> Result<Expression>;
+
+/// A Rust function registered by the embedder through
+/// [`Interpreter::register_fn`](crate::Interpreter::register_fn)
+#[derive(Clone)]
+pub struct NativeFn {
+    name: SmallString,
+    func: Rc<NativeFnInner>,
+}
+
+impl NativeFn {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Calls this function with the given, already evaluated,
+    /// arguments
+    pub fn call(
+        &self,
+        arguments: Vec<Expression>,
+    ) -> Result<Expression> {
+        (self.func)(arguments)
+    }
+}
+
+impl fmt::Debug for NativeFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "NativeFn({})", self.name)
+    }
+}
+
+impl PartialEq for NativeFn {
+    fn eq(&self, other: &Self) -> bool {
+        // Closures can't be compared, so two native functions
+        // are only equal if they're the very same
+        // registration
+        Rc::ptr_eq(&self.func, &other.func)
+    }
+}
+
+/// Conversion from an evaluated expression into a Rust value,
+/// used to type-check the arguments of native functions
+pub trait FromExpression: Sized {
+    fn from_expression(expression: Expression) -> Result<Self>;
+}
+
+/// Conversion from a Rust value into an expression, used for
+/// the return values of native functions
+pub trait IntoExpression {
+    fn into_expression(self) -> Result<Expression>;
+}
+
+impl FromExpression for Expression {
+    fn from_expression(expression: Expression) -> Result<Self> {
+        Ok(expression)
+    }
+}
+
+impl FromExpression for f64 {
+    fn from_expression(expression: Expression) -> Result<Self> {
+        expression.as_number()
+    }
+}
+
+impl FromExpression for i64 {
+    fn from_expression(expression: Expression) -> Result<Self> {
+        expression.as_integer()
+    }
+}
+
+impl FromExpression for bool {
+    fn from_expression(expression: Expression) -> Result<Self> {
+        expression.as_bool()
+    }
+}
+
+impl FromExpression for SmallString {
+    fn from_expression(expression: Expression) -> Result<Self> {
+        expression.as_string()
+    }
+}
+
+impl FromExpression for String {
+    fn from_expression(expression: Expression) -> Result<Self> {
+        SmallString::from_expression(expression)
+            .map(|string| string.to_string())
+    }
+}
+
+impl FromExpression for Vec<Expression> {
+    fn from_expression(expression: Expression) -> Result<Self> {
+        expression.as_list()
+    }
+}
+
+impl<T: FromExpression> FromExpression for Option<T> {
+    fn from_expression(expression: Expression) -> Result<Self> {
+        match expression {
+            Expression::Atom(Atom::Nil) => Ok(None),
+            other => T::from_expression(other).map(Some),
+        }
+    }
+}
+
+impl IntoExpression for Expression {
+    fn into_expression(self) -> Result<Expression> {
+        Ok(self)
+    }
+}
+
+impl IntoExpression for f64 {
+    fn into_expression(self) -> Result<Expression> {
+        Ok(self.into())
+    }
+}
+
+impl IntoExpression for i64 {
+    fn into_expression(self) -> Result<Expression> {
+        Ok(self.into())
+    }
+}
+
+impl IntoExpression for bool {
+    fn into_expression(self) -> Result<Expression> {
+        Ok(self.into())
+    }
+}
+
+impl IntoExpression for () {
+    fn into_expression(self) -> Result<Expression> {
+        Ok(Expression::default())
+    }
+}
+
+impl IntoExpression for SmallString {
+    fn into_expression(self) -> Result<Expression> {
+        Ok(Expression::Atom(Atom::String(self)))
+    }
+}
+
+impl IntoExpression for String {
+    fn into_expression(self) -> Result<Expression> {
+        SmallString::new(self).into_expression()
+    }
+}
+
+impl IntoExpression for &str {
+    fn into_expression(self) -> Result<Expression> {
+        SmallString::new(self).into_expression()
+    }
+}
+
+impl<T: IntoExpression> IntoExpression for Vec<T> {
+    fn into_expression(self) -> Result<Expression> {
+        self.into_iter()
+            .map(IntoExpression::into_expression)
+            .collect::<Result<_>>()
+            .map(Expression::List)
+    }
+}
+
+impl<T: IntoExpression> IntoExpression for Option<T> {
+    fn into_expression(self) -> Result<Expression> {
+        match self {
+            Some(value) => value.into_expression(),
+            None => Ok(Expression::default()),
+        }
+    }
+}
+
+impl<T: IntoExpression> IntoExpression for Result<T> {
+    fn into_expression(self) -> Result<Expression> {
+        self.and_then(IntoExpression::into_expression)
+    }
+}
+
+/// Rust closures that can be turned into a [`NativeFn`].
+///
+/// `Args` is a tuple of the closure's argument types, which
+/// allows implementing this trait for closures of different
+/// arities.
+pub trait IntoNativeFn<Args> {
+    fn into_native_fn(self, name: SmallString) -> NativeFn;
+}
+
+macro_rules! count_one {
+    ($arg:ident) => {
+        1
+    };
+}
+
+macro_rules! impl_into_native_fn {
+    ($($arg:ident),*) => {
+        impl<F, R, $($arg,)*> IntoNativeFn<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + 'static,
+            R: IntoExpression,
+            $($arg: FromExpression,)*
+        {
+            #[allow(non_snake_case, unused_mut, unused_variables)]
+            fn into_native_fn(self, name: SmallString) -> NativeFn {
+                const ARITY: usize = 0 $(+ count_one!($arg))*;
+
+                let func = move |arguments: Vec<Expression>| {
+                    ensure_exact_arity(ARITY, arguments.len())?;
+
+                    // Won't fail since we've just checked the arity
+                    let mut arguments = arguments.into_iter();
+                    $(
+                        let $arg = $arg::from_expression(
+                            arguments.next().unwrap(),
+                        )?;
+                    )*
+
+                    self($($arg),*).into_expression()
+                };
+
+                NativeFn {
+                    name,
+                    func: Rc::new(func),
+                }
+            }
+        }
+    };
+}
+
+impl_into_native_fn!();
+impl_into_native_fn!(A);
+impl_into_native_fn!(A, B);
+impl_into_native_fn!(A, B, C);
+impl_into_native_fn!(A, B, C, D);
+impl_into_native_fn!(A, B, C, D, E);
+impl_into_native_fn!(A, B, C, D, E, G);
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Atom, Error, Expression, Interpreter, SmallString,
+    };
+
+    #[test]
+    fn calls_registered_functions() {
+        for mut interp in Interpreter::all_backends() {
+            interp.register_fn("square", |x: f64| x * x);
+            interp.register_fn("answer", || 42.0);
+            interp.register_fn("greet", |name: String| {
+                format!("hello, {name}")
+            });
+            interp.register_fn(
+                "clamp",
+                |x: f64, lo: f64, hi: f64| x.max(lo).min(hi),
+            );
+            interp.register_fn(
+                "sum",
+                |list: Vec<Expression>| {
+                    list.iter()
+                        .map(Expression::as_number)
+                        .sum::<Result<f64, _>>()
+                },
+            );
+
+            assert_eq!(
+                interp.parse_and_eval("(square 3)").unwrap(),
+                9.0.into()
+            );
+            assert_eq!(
+                interp.parse_and_eval("(answer)").unwrap(),
+                42.0.into()
+            );
+            assert_eq!(
+                interp
+                    .parse_and_eval("(greet \"world\")")
+                    .unwrap(),
+                Expression::Atom(Atom::String(
+                    SmallString::new("hello, world")
+                ))
+            );
+            assert_eq!(
+                interp
+                    .parse_and_eval("(clamp 15 0 10)")
+                    .unwrap(),
+                10.0.into()
+            );
+            assert_eq!(
+                interp
+                    .parse_and_eval("(sum [1 2 (square 3)])")
+                    .unwrap(),
+                12.0.into()
+            );
+
+            // Native functions compose with the rest of the
+            // language
+            assert!(interp
+                .parse_and_eval(
+                    "(def fourth (fn [x] (square (square x))))"
+                )
+                .is_ok());
+            assert_eq!(
+                interp
+                    .parse_and_eval(
+                        "(let [x 2] (= (fourth x) 16))"
+                    )
+                    .unwrap(),
+                true.into()
+            );
+        }
+    }
+
+    #[test]
+    fn checks_arity_and_types_of_registered_functions() {
+        for mut interp in Interpreter::all_backends() {
+            interp.register_fn("square", |x: f64| x * x);
+            interp.register_fn("negate", |x: bool| !x);
+
+            assert_eq!(
+                interp
+                    .parse_and_eval("(square)")
+                    .unwrap_err()
+                    .inner(),
+                &Error::ExactArityMismatch {
+                    expected: 1,
+                    received: 0
+                }
+            );
+            assert_eq!(
+                interp
+                    .parse_and_eval("(square 1 2)")
+                    .unwrap_err()
+                    .inner(),
+                &Error::ExactArityMismatch {
+                    expected: 1,
+                    received: 2
+                }
+            );
+            assert_eq!(
+                interp
+                    .parse_and_eval("(square :two)")
+                    .unwrap_err()
+                    .inner(),
+                &Error::TypeMismatch {
+                    expected: "number",
+                    received: "symbol"
+                }
+            );
+            assert_eq!(
+                interp
+                    .parse_and_eval("(negate 1)")
+                    .unwrap_err()
+                    .inner(),
+                &Error::TypeMismatch {
+                    expected: "boolean",
+                    received: "integer"
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn propagates_errors_from_registered_functions() {
+        for mut interp in Interpreter::all_backends() {
+            interp.register_fn("checked-sqrt", |x: f64| {
+                if x < 0.0 {
+                    Err(Error::TypeMismatch {
+                        expected: "non-negative number",
+                        received: "negative number",
+                    })
+                } else {
+                    Ok(x.sqrt())
+                }
+            });
+
+            assert_eq!(
+                interp
+                    .parse_and_eval("(checked-sqrt 16)")
+                    .unwrap(),
+                4.0.into()
+            );
+            assert!(interp
+                .parse_and_eval("(checked-sqrt -1)")
+                .is_err());
+        }
+    }
+}
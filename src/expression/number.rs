@@ -0,0 +1,381 @@
+use std::{cmp::Ordering, rc::Rc};
+
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{ToPrimitive, Zero};
+
+use super::{elements::Atom, Expression};
+use crate::{Error, Result, Typed};
+
+/// A number taking part in arithmetic, in the order of the
+/// numeric tower: operating on two numbers gives a number of the
+/// higher of their levels, and exact results are brought back
+/// down to the lowest level able to hold them
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Numeric {
+    Integer(i64),
+    /// An integer outside of the range of `i64`
+    BigInteger(BigInt),
+    /// A fraction whose denominator isn't 1
+    Rational(BigRational),
+    Float(f64),
+}
+
+impl Numeric {
+    /// Parses an integer, or a rational if given a denominator.
+    /// Fails on a denominator of zero
+    pub fn parse(
+        numerator: &str,
+        denominator: Option<&str>,
+    ) -> Option<Self> {
+        let Some(denominator) = denominator else {
+            return Some(match numerator.parse() {
+                Ok(integer) => Numeric::Integer(integer),
+                Err(_) => {
+                    Numeric::BigInteger(numerator.parse().ok()?)
+                }
+            });
+        };
+
+        let denominator: BigInt = denominator.parse().ok()?;
+        if denominator.is_zero() {
+            return None;
+        }
+
+        let rational = BigRational::new(
+            numerator.parse().ok()?,
+            denominator,
+        );
+        Some(Numeric::Rational(rational).normalize())
+    }
+
+    /// Brings exact numbers down to the lowest level of the
+    /// tower able to hold them
+    fn normalize(self) -> Self {
+        match self {
+            Numeric::BigInteger(integer) => {
+                match integer.to_i64() {
+                    Some(integer) => Numeric::Integer(integer),
+                    None => Numeric::BigInteger(integer),
+                }
+            }
+            Numeric::Rational(rational)
+                if rational.is_integer() =>
+            {
+                Numeric::BigInteger(rational.to_integer())
+                    .normalize()
+            }
+            other => other,
+        }
+    }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(
+            self,
+            Numeric::Integer(_) | Numeric::BigInteger(_)
+        )
+    }
+
+    fn is_zero(&self) -> bool {
+        match self {
+            Numeric::Integer(integer) => *integer == 0,
+            Numeric::BigInteger(integer) => integer.is_zero(),
+            Numeric::Rational(rational) => rational.is_zero(),
+            Numeric::Float(float) => *float == 0.0,
+        }
+    }
+
+    pub fn as_f64(&self) -> f64 {
+        match self {
+            Numeric::Integer(integer) => *integer as f64,
+            // Safe unwraps: the conversions saturate to infinity
+            // rather than fail
+            Numeric::BigInteger(integer) => {
+                integer.to_f64().unwrap()
+            }
+            Numeric::Rational(rational) => {
+                rational.to_f64().unwrap_or(f64::NAN)
+            }
+            Numeric::Float(float) => *float,
+        }
+    }
+
+    fn into_big_integer(self) -> BigInt {
+        match self {
+            Numeric::Integer(integer) => integer.into(),
+            Numeric::BigInteger(integer) => integer,
+            other => unreachable!("{other:?} is not an integer"),
+        }
+    }
+
+    fn into_rational(self) -> BigRational {
+        match self {
+            Numeric::Rational(rational) => rational,
+            Numeric::Float(float) => {
+                unreachable!("{float} is not exact")
+            }
+            integer => BigRational::from_integer(
+                integer.into_big_integer(),
+            ),
+        }
+    }
+
+    /// Applies the operation fitting the level of the tower of
+    /// both numbers. Integers that overflow are promoted to big
+    /// integers
+    fn arithmetic(
+        self,
+        rhs: Self,
+        int_op: impl Fn(i64, i64) -> Option<i64>,
+        big_op: impl Fn(BigInt, BigInt) -> BigInt,
+        rational_op: impl Fn(BigRational, BigRational) -> BigRational,
+        float_op: impl Fn(f64, f64) -> f64,
+    ) -> Self {
+        match (self, rhs) {
+            (Numeric::Integer(lhs), Numeric::Integer(rhs)) => {
+                match int_op(lhs, rhs) {
+                    Some(integer) => Numeric::Integer(integer),
+                    None => Numeric::BigInteger(big_op(
+                        lhs.into(),
+                        rhs.into(),
+                    ))
+                    .normalize(),
+                }
+            }
+            (lhs @ Numeric::Float(_), rhs)
+            | (lhs, rhs @ Numeric::Float(_)) => Numeric::Float(
+                float_op(lhs.as_f64(), rhs.as_f64()),
+            ),
+            (lhs, rhs)
+                if lhs.is_integer() && rhs.is_integer() =>
+            {
+                Numeric::BigInteger(big_op(
+                    lhs.into_big_integer(),
+                    rhs.into_big_integer(),
+                ))
+                .normalize()
+            }
+            (lhs, rhs) => Numeric::Rational(rational_op(
+                lhs.into_rational(),
+                rhs.into_rational(),
+            ))
+            .normalize(),
+        }
+    }
+
+    pub fn add(self, rhs: Self) -> Result<Self> {
+        Ok(self.arithmetic(
+            rhs,
+            i64::checked_add,
+            |x, y| x + y,
+            |x, y| x + y,
+            |x, y| x + y,
+        ))
+    }
+
+    pub fn sub(self, rhs: Self) -> Result<Self> {
+        Ok(self.arithmetic(
+            rhs,
+            i64::checked_sub,
+            |x, y| x - y,
+            |x, y| x - y,
+            |x, y| x - y,
+        ))
+    }
+
+    pub fn mul(self, rhs: Self) -> Result<Self> {
+        Ok(self.arithmetic(
+            rhs,
+            i64::checked_mul,
+            |x, y| x * y,
+            |x, y| x * y,
+            |x, y| x * y,
+        ))
+    }
+
+    /// Dividing exact numbers gives an exact result, e.g. a
+    /// rational if integers don't divide evenly
+    pub fn div(self, rhs: Self) -> Result<Self> {
+        match (&self, &rhs) {
+            (Numeric::Float(_), _) | (_, Numeric::Float(_)) => {
+                Ok(Numeric::Float(self.as_f64() / rhs.as_f64()))
+            }
+            _ if rhs.is_zero() => Err(Error::DivisionByZero),
+            (Numeric::Integer(lhs), Numeric::Integer(rhs))
+                if lhs.checked_rem(*rhs) == Some(0) =>
+            {
+                Ok(Numeric::Integer(lhs / rhs))
+            }
+            _ => Ok(Numeric::Rational(
+                self.into_rational() / rhs.into_rational(),
+            )
+            .normalize()),
+        }
+    }
+
+    /// The remainder of truncating division, which has the sign
+    /// of the dividend
+    pub fn rem(self, rhs: Self) -> Result<Self> {
+        if rhs.is_zero() && !matches!(rhs, Numeric::Float(_)) {
+            return Err(Error::DivisionByZero);
+        }
+
+        Ok(self.arithmetic(
+            rhs,
+            // `i64::MIN % -1` overflows, even though its
+            // result, 0, doesn't
+            |x, y| Some(x.wrapping_rem(y)),
+            |x, y| x % y,
+            |x, y| x % y,
+            |x, y| x % y,
+        ))
+    }
+
+    /// The quotient of dividing two integers, truncated towards
+    /// zero
+    pub fn quot(self, rhs: Self) -> Result<Self> {
+        if rhs.is_zero() {
+            return Err(Error::DivisionByZero);
+        }
+
+        Ok(self.arithmetic(
+            rhs,
+            i64::checked_div,
+            |x, y| x / y,
+            |x, y| (x / y).trunc(),
+            |x, y| (x / y).trunc(),
+        ))
+    }
+
+    /// Compares numbers by their exact value, even those too
+    /// large for a float to represent every integer around them
+    pub fn compare(&self, rhs: &Self) -> Option<Ordering> {
+        match (self, rhs) {
+            (Numeric::Integer(lhs), Numeric::Integer(rhs)) => {
+                Some(lhs.cmp(rhs))
+            }
+            (Numeric::Float(lhs), Numeric::Float(rhs)) => {
+                lhs.partial_cmp(rhs)
+            }
+            (exact, Numeric::Float(float)) => {
+                compare_to_float(exact, *float)
+            }
+            (Numeric::Float(float), exact) => {
+                compare_to_float(exact, *float)
+                    .map(Ordering::reverse)
+            }
+            (lhs, rhs) => Some(
+                lhs.clone()
+                    .into_rational()
+                    .cmp(&rhs.clone().into_rational()),
+            ),
+        }
+    }
+
+    /// Turns floats into the exact number they're written as,
+    /// e.g. `0.1` into `1/10` rather than the binary fraction
+    /// actually stored. Exact numbers are left as they are
+    pub fn rationalize(self) -> Result<Self> {
+        let Numeric::Float(float) = self else {
+            return Ok(self);
+        };
+        if !float.is_finite() {
+            return Err(Error::TypeMismatch {
+                expected: "finite number",
+                received: "float",
+            });
+        }
+
+        // Floats are displayed with the fewest digits that read
+        // back as the same float, and never in scientific
+        // notation
+        let written = float.to_string();
+        let (integer, fraction) =
+            written.split_once('.').unwrap_or((&written, ""));
+        let denominator =
+            format!("1{}", "0".repeat(fraction.len()));
+
+        // Safe unwrap: these are all digits, and the denominator
+        // isn't zero
+        Ok(Self::parse(
+            &format!("{integer}{fraction}"),
+            Some(&denominator),
+        )
+        .unwrap())
+    }
+}
+
+/// Compares an exact number with a float, which is exact as
+/// well unless it's infinite or NaN
+fn compare_to_float(
+    exact: &Numeric,
+    float: f64,
+) -> Option<Ordering> {
+    if float.is_nan() {
+        None
+    } else if float.is_infinite() {
+        Some(if float > 0.0 {
+            Ordering::Less
+        } else {
+            Ordering::Greater
+        })
+    } else {
+        let float = BigRational::from_float(float)?;
+        Some(exact.clone().into_rational().cmp(&float))
+    }
+}
+
+impl From<Numeric> for Atom {
+    fn from(number: Numeric) -> Self {
+        match number {
+            Numeric::Integer(integer) => Atom::Integer(integer),
+            Numeric::BigInteger(integer) => {
+                Atom::BigInteger(Rc::new(integer))
+            }
+            Numeric::Rational(rational) => {
+                Atom::Rational(Rc::new(rational))
+            }
+            Numeric::Float(float) => Atom::Number(float),
+        }
+    }
+}
+
+impl From<Numeric> for Expression {
+    fn from(number: Numeric) -> Self {
+        Expression::Atom(number.into())
+    }
+}
+
+impl Expression {
+    pub(crate) fn as_numeric(&self) -> Result<Numeric> {
+        match self {
+            Expression::Atom(Atom::Integer(integer)) => {
+                Ok(Numeric::Integer(*integer))
+            }
+            Expression::Atom(Atom::BigInteger(integer)) => {
+                Ok(Numeric::BigInteger(BigInt::clone(integer)))
+            }
+            Expression::Atom(Atom::Rational(rational)) => Ok(
+                Numeric::Rational(BigRational::clone(rational)),
+            ),
+            Expression::Atom(Atom::Number(float)) => {
+                Ok(Numeric::Float(*float))
+            }
+            other => Err(Error::TypeMismatch {
+                expected: "number",
+                received: other.rough_type(),
+            }),
+        }
+    }
+
+    /// An integer of any size
+    pub(crate) fn as_exact_integer(&self) -> Result<Numeric> {
+        match self.as_numeric() {
+            Ok(integer) if integer.is_integer() => Ok(integer),
+            _ => Err(Error::TypeMismatch {
+                expected: "integer",
+                received: self.rough_type(),
+            }),
+        }
+    }
+}
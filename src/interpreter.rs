@@ -1,19 +1,75 @@
+use nom::{combinator::all_consuming, sequence::terminated};
+
+#[cfg(feature = "bytecode")]
+use crate::vm;
 use crate::{
-    parse_expression, Env, Error, Evaluable, Expression, Result,
+    macros, parse_expression, parse_program,
+    parser::space_or_comment0, Atom, Env, Error, Evaluable,
+    Expression, IntoNativeFn, Location, ParseError, Result,
+    SmallString, SyntaxError,
 };
 
-#[derive(Default)]
+/// Name given to sources evaluated without one, such as the
+/// input of [`Interpreter::parse_and_eval`]
+const UNNAMED_SOURCE: &str = "<input>";
+
+/// How expressions get evaluated
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// Walks the expression tree, evaluating each node as it's
+    /// reached
+    #[default]
+    TreeWalker,
+    /// Compiles expressions to bytecode and runs it on a stack
+    /// machine. Requires the `bytecode` feature
+    #[cfg(feature = "bytecode")]
+    Bytecode,
+}
+
 pub struct Interpreter {
     env: Env,
+    backend: Backend,
+    /// Names of the sources evaluated so far, indexed by the
+    /// `source` field of their spans
+    sources: Vec<SmallString>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Interpreter {
     pub fn new() -> Self {
+        Self::with_backend(Backend::default())
+    }
+
+    /// Creates an interpreter evaluating through the given
+    /// backend.
+    ///
+    /// ```
+    /// use ceceio::{Backend, Interpreter};
+    ///
+    /// let mut interp = Interpreter::with_backend(Backend::TreeWalker);
+    ///
+    /// assert_eq!(
+    ///     interp.parse_and_eval("(+ 1 2)").unwrap(),
+    ///     3_i64.into()
+    /// );
+    /// ```
+    pub fn with_backend(backend: Backend) -> Self {
         Self {
             env: Env::default(),
+            backend,
+            sources: vec![SmallString::new(UNNAMED_SOURCE)],
         }
     }
 
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
     pub fn parse_and_eval(
         &mut self,
         input: &str,
@@ -21,75 +77,400 @@ impl Interpreter {
         self.eval(Self::parse(input)?)
     }
 
+    /// Parses a single expression, failing if anything other
+    /// than whitespace follows it
     pub fn parse(input: &str) -> Result<Expression> {
-        let (_, expr) = parse_expression(input)
-            .map_err(|err| Self::stringify_error(input, err))?;
+        let (_, mut expr) = all_consuming(terminated(
+            parse_expression,
+            space_or_comment0,
+        ))(input)
+        .map_err(|err| Self::syntax_error(input, err, 0))?;
 
-        // assert!(rest.trim().is_empty());
+        expr.resolve_spans(input, 0);
 
         Ok(expr)
     }
 
+    /// Parses a program made of any number of top-level
+    /// expressions
+    pub fn parse_program(
+        input: &str,
+    ) -> Result<Vec<Expression>> {
+        Self::parse_program_from(input, 0)
+    }
+
+    fn parse_program_from(
+        input: &str,
+        source_id: usize,
+    ) -> Result<Vec<Expression>> {
+        let (_, mut program) =
+            parse_program(input).map_err(|err| {
+                Self::syntax_error(input, err, source_id)
+            })?;
+
+        for expression in &mut program {
+            expression.resolve_spans(input, source_id);
+        }
+
+        Ok(program)
+    }
+
+    /// Parses and evaluates each top-level expression of the
+    /// given program in order, returning the value of the last
+    /// one, or `nil` if the program is empty
+    pub fn eval_program(
+        &mut self,
+        input: &str,
+    ) -> Result<Expression> {
+        self.eval_source(UNNAMED_SOURCE, input)
+    }
+
+    /// Same as [`Interpreter::eval_program`], but runtime errors
+    /// will be located within a source called `name`, e.g.
+    /// `script.cec:12:5: Unknown symbol foo`
+    pub fn eval_source(
+        &mut self,
+        name: &str,
+        source: &str,
+    ) -> Result<Expression> {
+        let source_id = self.source_id(name);
+        let program =
+            Self::parse_program_from(source, source_id)?;
+
+        program
+            .into_iter()
+            .try_fold(Expression::default(), |_, expression| {
+                self.eval(expression)
+            })
+    }
+
+    fn source_id(&mut self, name: &str) -> usize {
+        match self
+            .sources
+            .iter()
+            .position(|source| source.as_str() == name)
+        {
+            Some(idx) => idx,
+            None => {
+                self.sources.push(SmallString::new(name));
+                self.sources.len() - 1
+            }
+        }
+    }
+
+    pub fn env(&self) -> &Env {
+        &self.env
+    }
+
+    /// Exposes a Rust function to scripts under the given
+    /// name.
+    ///
+    /// Its arguments and return value are converted through
+    /// [`FromExpression`](crate::FromExpression) and
+    /// [`IntoExpression`](crate::IntoExpression), so arity and
+    /// type errors are reported like those of built-ins.
+    ///
+    /// ```
+    /// use ceceio::Interpreter;
+    ///
+    /// let mut interp = Interpreter::new();
+    /// interp.register_fn("square", |x: f64| x * x);
+    ///
+    /// assert_eq!(
+    ///     interp.parse_and_eval("(square 4)").unwrap(),
+    ///     16.0.into()
+    /// );
+    /// ```
+    pub fn register_fn<Args>(
+        &mut self,
+        name: &str,
+        function: impl IntoNativeFn<Args>,
+    ) {
+        let name = SmallString::new(name);
+        let native_fn = function.into_native_fn(name.clone());
+
+        self.env.insert(
+            name,
+            Expression::Atom(Atom::NativeFn(native_fn)),
+        );
+    }
+
+    /// Expands the macros within `expression`, then evaluates
+    /// it
     pub fn eval(
         &mut self,
-        expression: Expression,
+        mut expression: Expression,
     ) -> Result<Expression> {
-        expression.evaluate(&mut self.env)
+        self.env.take_error_span();
+
+        let result =
+            macros::expand(&mut expression, &mut self.env)
+                .and_then(|()| match self.backend {
+                    Backend::TreeWalker => {
+                        expression.evaluate(&mut self.env)
+                    }
+                    #[cfg(feature = "bytecode")]
+                    Backend::Bytecode => {
+                        vm::eval(&expression, &mut self.env)
+                    }
+                });
+
+        result.map_err(|error| self.locate(error))
+    }
+
+    /// Attaches to `error` the location of the innermost
+    /// application it came from, if known
+    fn locate(&mut self, error: Error) -> Error {
+        match self.env.take_error_span() {
+            Some(span) if span.is_resolved() => Error::Located {
+                location: Location {
+                    source: self.sources[span.source].clone(),
+                    line: span.line,
+                    column: span.column,
+                },
+                error: Box::new(error),
+            },
+            _ => error,
+        }
     }
 
-    fn stringify_error(
+    fn syntax_error(
         input: &str,
-        error: nom::Err<nom::error::VerboseError<&str>>,
+        error: nom::Err<ParseError<'_>>,
+        source_id: usize,
     ) -> Error {
         match error {
             nom::Err::Incomplete(_) => unreachable!(),
             nom::Err::Error(error)
             | nom::Err::Failure(error) => Error::ParsingError(
-                nom::error::convert_error(input, error),
+                SyntaxError::from_parse_error(
+                    input, error, source_id,
+                ),
             ),
         }
     }
 }
 
+#[cfg(test)]
+impl Interpreter {
+    /// An interpreter for each backend, so that tests check they
+    /// all behave the same
+    pub(crate) fn all_backends() -> Vec<Self> {
+        [
+            Backend::TreeWalker,
+            #[cfg(feature = "bytecode")]
+            Backend::Bytecode,
+        ]
+        .map(Self::with_backend)
+        .into()
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::{Atom, Expression, Interpreter};
+    use crate::{
+        Atom, Error, Expression, Interpreter, Location,
+        SmallString,
+    };
 
     #[test]
     fn parses_and_evaluates() {
-        let mut interp = Interpreter::new();
+        for mut interp in Interpreter::all_backends() {
+            assert_eq!(
+                interp
+                    .parse_and_eval("(/ (* 2 3) (- 5 6 7))")
+                    .unwrap()
+                    .to_string(),
+                "-3/4"
+            );
 
-        assert_eq!(
-            interp
-                .parse_and_eval("(/ (* 2 3) (- 5 6 7))")
-                .unwrap(),
-            Expression::Atom(Atom::Number(-0.75))
-        );
+            assert_eq!(
+                interp.parse_and_eval("(def x 5)").unwrap(),
+                Expression::Atom(Atom::Integer(5))
+            );
 
-        assert_eq!(
-            interp.parse_and_eval("(def x 5)").unwrap(),
-            Expression::Atom(Atom::Number(5.0))
-        );
+            assert_eq!(
+                interp
+                    .parse_and_eval("(def twice (+ x x))")
+                    .unwrap(),
+                Expression::Atom(Atom::Integer(10))
+            );
 
-        assert_eq!(
-            interp
-                .parse_and_eval("(def twice (+ x x))")
-                .unwrap(),
-            Expression::Atom(Atom::Number(10.0))
-        );
+            assert_eq!(
+                interp
+                    .parse_and_eval("(= twice (* x 2) 10.0)")
+                    .unwrap(),
+                Expression::Atom(Atom::Boolean(true))
+            );
 
-        assert_eq!(
-            interp
-                .parse_and_eval("(= twice (* x 2) 10.0)")
-                .unwrap(),
-            Expression::Atom(Atom::Boolean(true))
-        );
+            assert_eq!(
+                interp
+                    .parse_and_eval("(= (* x x x x x) 3125.0)")
+                    .unwrap(),
+                Expression::Atom(Atom::Boolean(true))
+            );
+        }
+    }
 
-        assert_eq!(
-            interp
-                .parse_and_eval("(= (* x x x x x) 3125.0)")
-                .unwrap(),
-            Expression::Atom(Atom::Boolean(true))
-        );
+    #[test]
+    fn rejects_trailing_input() {
+        for mut interp in Interpreter::all_backends() {
+            assert!(matches!(
+                interp.parse_and_eval("(def x 1) (def y 2)"),
+                Err(Error::ParsingError(_))
+            ));
+            assert!(matches!(
+                interp.parse_and_eval("(+ 1 2))"),
+                Err(Error::ParsingError(_))
+            ));
+            assert_eq!(
+                interp.parse_and_eval("  (+ 1 2)\n").unwrap(),
+                3_i64.into()
+            );
+        }
+    }
+
+    #[test]
+    fn evaluates_programs() {
+        for mut interp in Interpreter::all_backends() {
+            assert_eq!(
+                interp.eval_program("").unwrap(),
+                Expression::default()
+            );
+
+            assert_eq!(
+                interp
+                    .eval_program(
+                        "(def x 1)
+                         (def y (+ x 1))
+                         (def add (fn [a b]
+                            (+ a b)))
+                         (add x y)"
+                    )
+                    .unwrap(),
+                3_i64.into()
+            );
+
+            // Bindings made by the program remain available
+            assert_eq!(
+                interp.parse_and_eval("y").unwrap(),
+                2_i64.into()
+            );
+
+            // Evaluation stops at the first error
+            assert_eq!(
+                interp
+                    .eval_program(
+                        "(def z 1) (undefined 2) (def w 3)"
+                    )
+                    .unwrap_err()
+                    .inner(),
+                &Error::UnknownSymbol(SmallString::new(
+                    "undefined"
+                ))
+            );
+            assert!(interp.parse_and_eval("z").is_ok());
+            assert!(interp.parse_and_eval("w").is_err());
+
+            // Nothing is evaluated if the program fails to parse
+            assert!(matches!(
+                interp.eval_program("(def v 1) (def u"),
+                Err(Error::ParsingError(_))
+            ));
+            assert!(interp.parse_and_eval("v").is_err());
+        }
+    }
+
+    #[test]
+    fn locates_runtime_errors() {
+        for mut interp in Interpreter::all_backends() {
+            let error = interp
+                .eval_source(
+                    "script.cec",
+                    "(def x 1)\n\n(def y\n    (+ x (foo 2)))",
+                )
+                .unwrap_err();
+
+            assert_eq!(
+                error,
+                Error::Located {
+                    location: Location {
+                        source: SmallString::new("script.cec"),
+                        line: 4,
+                        column: 10,
+                    },
+                    error: Box::new(Error::UnknownSymbol(
+                        SmallString::new("foo")
+                    )),
+                }
+            );
+            assert_eq!(
+                error.to_string(),
+                "script.cec:4:10: Unknown symbol foo"
+            );
+
+            // Errors raised within a lambda point to where it
+            // was defined, even when it's called
+            // from another source
+            assert!(interp
+                .eval_source(
+                    "lib.cec",
+                    "(def half\n  (fn [x] (/ x (not x))))"
+                )
+                .is_ok());
+            assert_eq!(
+                interp
+                    .eval_source("main.cec", "(def ok 1)\n(half 2)")
+                    .unwrap_err()
+                    .to_string(),
+                "lib.cec:2:16: Type error: expected boolean, received \
+                 integer"
+            );
+
+            assert_eq!(
+                interp.parse_and_eval("(+ 1 :two)").unwrap_err().to_string(),
+                "<input>:1:1: Type error: expected number, received \
+                 symbol"
+            );
+
+            // Errors outside of applications point to the form
+            // they happened in
+            assert_eq!(
+                interp
+                    .eval_source(
+                        "script.cec",
+                        "(def y\n   undefined-thing)"
+                    )
+                    .unwrap_err()
+                    .to_string(),
+                "script.cec:1:1: Unknown symbol undefined-thing"
+            );
+            assert_eq!(
+                interp
+                    .eval_source(
+                        "script.cec",
+                        "(let [a 1]\n  (if true nope 2))"
+                    )
+                    .unwrap_err()
+                    .to_string(),
+                "script.cec:2:3: Unknown symbol nope"
+            );
+            assert_eq!(
+                interp
+                    .eval_source(
+                        "script.cec",
+                        "(match 1\n  1 missing)"
+                    )
+                    .unwrap_err()
+                    .to_string(),
+                "script.cec:1:1: Unknown symbol missing"
+            );
+
+            // Bare atoms aren't located
+            assert_eq!(
+                interp.parse_and_eval("bar").unwrap_err(),
+                Error::UnknownSymbol(SmallString::new("bar"))
+            );
+        }
     }
 }
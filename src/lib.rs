@@ -7,15 +7,31 @@ mod evaluatable;
 mod expression;
 mod interner;
 mod interpreter;
+mod macros;
 mod parser;
 mod scope;
 mod small_string;
+mod span;
+#[cfg(feature = "bytecode")]
+mod vm;
 
 pub use cheap_clone::CheapClone;
 pub use check::{ensure_exact_arity, Typed};
-pub use error::{Error, Result};
+pub use error::{
+    Error, Expected, Result, SyntaxContext, SyntaxError,
+};
 pub use evaluatable::{Env, Evaluable};
-pub use expression::{elements::Atom, BuiltIn, Expression};
-pub use interpreter::Interpreter;
-pub use parser::{parse_atom, parse_expression, IResult};
+pub use expression::{
+    elements::Atom,
+    native::{
+        FromExpression, IntoExpression, IntoNativeFn, NativeFn,
+    },
+    BuiltIn, Expression,
+};
+pub use interpreter::{Backend, Interpreter};
+pub use parser::{
+    parse_atom, parse_expression, parse_program, IResult,
+    ParseError,
+};
 pub use small_string::SmallString;
+pub use span::{Location, Span};
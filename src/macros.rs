@@ -0,0 +1,1049 @@
+//! Macros: lambdas taking code as data and returning the code
+//! to evaluate in place of their application. They're expanded
+//! before evaluation, and usually build their expansion out of
+//! quasiquote templates.
+//!
+//! Code is taken as data the way it's written: identifiers as
+//! themselves, and forms as lists of their parts, e.g.
+//! `(if (empty? xs) 0 x)` as `[if [empty? xs] 0 x]`. List
+//! literals are taken as applications of the `list` built-in,
+//! which sets them apart from forms.
+
+use std::{iter, mem, rc::Rc};
+
+use crate::{
+    expression::{
+        elements::{
+            Application, Atom, Binding, FnIdentifier, If,
+            IfElse, Lambda, Let, Match, MatchClause, Parameters,
+            Pattern,
+        },
+        Map, Set,
+    },
+    parser::builtin_named,
+    BuiltIn, Env, Error, Expression, Result, SmallString, Span,
+    Typed,
+};
+
+/// The names of the special forms code may be made of, other
+/// than applications
+const SPECIAL_FORMS: [&str; 11] = [
+    "if",
+    "def",
+    "defmacro",
+    "fn",
+    "let",
+    "match",
+    "do",
+    "quote",
+    "quasiquote",
+    "unquote",
+    "unquote-splicing",
+];
+
+/// What the unquoted forms of a template are filled in with
+type Unquote<'a> =
+    &'a mut dyn FnMut(&Expression) -> Result<Expression>;
+
+/// Expands every macro application within `expression`,
+/// including those making up the expansions themselves
+pub(crate) fn expand(
+    expression: &mut Expression,
+    env: &mut Env,
+) -> Result<()> {
+    expand_within(expression, env, false)
+}
+
+/// Expands `expression` once if it applies a macro, returning
+/// `None` otherwise
+pub(crate) fn expand_once(
+    expression: &Expression,
+    env: &mut Env,
+) -> Result<Option<Expression>> {
+    let Expression::Application(app) = expression else {
+        return Ok(None);
+    };
+    let FnIdentifier::Other(identifier) = &app.name else {
+        return Ok(None);
+    };
+    let Some(expander) = env.get_macro(identifier).cloned()
+    else {
+        return Ok(None);
+    };
+
+    let arguments = app.arguments.iter().map(quote).collect();
+    let result = expander
+        .apply(arguments, env)
+        .and_then(|code| into_code(&code, app.span));
+    env.blame(&result, Some(app.span));
+
+    result.map(Some)
+}
+
+/// Expands the macro applications within `expression`. Those
+/// inside a quasiquote `template` are left alone, except for
+/// unquoted ones, as they're only code once the template is
+/// filled in
+fn expand_within(
+    expression: &mut Expression,
+    env: &mut Env,
+    template: bool,
+) -> Result<()> {
+    if !template {
+        while let Some(expansion) = expand_once(expression, env)?
+        {
+            *expression = expansion;
+        }
+    }
+
+    match expression {
+        Expression::Atom(_) | Expression::Quote(_) => {}
+        Expression::Application(app) => {
+            if let FnIdentifier::Expression(callee) =
+                &mut app.name
+            {
+                expand_within(
+                    Rc::make_mut(callee),
+                    env,
+                    template,
+                )?;
+            }
+            for argument in &mut app.arguments {
+                expand_within(argument, env, template)?;
+            }
+        }
+        Expression::If(if_expr) => {
+            expand_within(
+                &mut if_expr.condition,
+                env,
+                template,
+            )?;
+            expand_within(&mut if_expr.do_this, env, template)?;
+        }
+        Expression::IfElse(if_else) => {
+            expand_within(
+                &mut if_else.condition,
+                env,
+                template,
+            )?;
+            expand_within(&mut if_else.if_true, env, template)?;
+            expand_within(&mut if_else.if_false, env, template)?;
+        }
+        Expression::Binding(binding)
+        | Expression::DefMacro(binding) => {
+            expand_pattern(&mut binding.pattern, env, template)?;
+            expand_within(
+                &mut binding.expression,
+                env,
+                template,
+            )?;
+        }
+        Expression::Let(let_expr) => {
+            for (pattern, expression) in &mut let_expr.bindings {
+                expand_pattern(pattern, env, template)?;
+                expand_within(expression, env, template)?;
+            }
+            expand_within(&mut let_expr.body, env, template)?;
+        }
+        Expression::Match(match_expr) => {
+            expand_within(
+                &mut match_expr.expression,
+                env,
+                template,
+            )?;
+            for clause in &mut match_expr.clauses {
+                expand_pattern(
+                    &mut clause.pattern,
+                    env,
+                    template,
+                )?;
+                if let Some(guard) = &mut clause.guard {
+                    expand_within(guard, env, template)?;
+                }
+                expand_within(
+                    &mut clause.branch,
+                    env,
+                    template,
+                )?;
+            }
+        }
+        Expression::Lambda(lambda) => {
+            for (_, default) in &mut lambda.parameters.optional {
+                expand_within(default, env, template)?;
+            }
+            if let Some(parameters) = &mut lambda.template {
+                if !template {
+                    return Err(Error::UnquoteOutsideQuasiquote);
+                }
+                for parameter in parameters {
+                    expand_within(parameter, env, template)?;
+                }
+            }
+            expand_within(
+                Rc::make_mut(&mut lambda.body),
+                env,
+                template,
+            )?;
+        }
+        Expression::Do(list) | Expression::List(list) => {
+            for expression in list {
+                expand_within(expression, env, template)?;
+            }
+        }
+        Expression::Map(map) => {
+            // Keys can't be changed in place, so entries are
+            // inserted anew, in the same order
+            let entries = mem::take(Rc::make_mut(map));
+            *Rc::make_mut(map) = entries
+                .into_iter()
+                .map(|(mut key, mut value)| {
+                    expand_within(&mut key, env, template)?;
+                    expand_within(&mut value, env, template)?;
+                    Ok((key, value))
+                })
+                .collect::<Result<_>>()?;
+        }
+        Expression::Set(set) => {
+            let items = mem::take(Rc::make_mut(set));
+            *Rc::make_mut(set) = items
+                .into_iter()
+                .map(|mut item| {
+                    expand_within(&mut item, env, template)?;
+                    Ok(item)
+                })
+                .collect::<Result<_>>()?;
+        }
+        Expression::Quasiquote(form) => {
+            expand_within(Rc::make_mut(form), env, true)?;
+        }
+        Expression::Unquote(form)
+        | Expression::UnquoteSplicing(form) => {
+            if !template {
+                return Err(Error::UnquoteOutsideQuasiquote);
+            }
+            expand_within(Rc::make_mut(form), env, false)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Expands the macro applications within the unquoted forms of
+/// `pattern`, which may only be found in templates
+fn expand_pattern(
+    pattern: &mut Pattern,
+    env: &mut Env,
+    template: bool,
+) -> Result<()> {
+    match pattern {
+        Pattern::Identifier(_)
+        | Pattern::Wildcard
+        | Pattern::Literal(_) => {}
+        Pattern::List { items, rest } => {
+            for item in
+                items.iter_mut().chain(rest.as_deref_mut())
+            {
+                expand_pattern(item, env, template)?;
+            }
+        }
+        Pattern::Map(entries) => {
+            for (pattern, _) in entries {
+                expand_pattern(pattern, env, template)?;
+            }
+        }
+        Pattern::Unquote(form) => {
+            if !template {
+                return Err(Error::UnquoteOutsideQuasiquote);
+            }
+            expand_within(Rc::make_mut(form), env, false)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Takes code as data
+pub(crate) fn quote(code: &Expression) -> Expression {
+    // Safe unwrap: only filling in a template may fail
+    Quoter { unquote: None }.code(code).unwrap()
+}
+
+/// Fills in a quasiquote template, taking the value of each of
+/// its unquoted forms from `unquote`, which is called on them
+/// in the order they're written. Gives the code the template
+/// stands for, as data
+pub(crate) fn fill(
+    template: &Expression,
+    unquote: Unquote<'_>,
+) -> Result<Expression> {
+    Quoter {
+        unquote: Some(unquote),
+    }
+    .code(template)
+}
+
+/// Takes code as data, filling in the unquoted forms of the
+/// template it's part of, if any
+struct Quoter<'a> {
+    unquote: Option<Unquote<'a>>,
+}
+
+impl Quoter<'_> {
+    fn code(&mut self, code: &Expression) -> Result<Expression> {
+        let data = match code {
+            Expression::Unquote(form) => {
+                match self.fill_in(form) {
+                    Some(value) => return value,
+                    None => form_of("unquote", [quote(form)]),
+                }
+            }
+            Expression::UnquoteSplicing(form) => {
+                if self.unquote.is_some() {
+                    return Err(Error::SpliceOutsideSequence);
+                }
+                form_of("unquote-splicing", [quote(form)])
+            }
+            Expression::Atom(Atom::BuiltIn(built_in)) => {
+                quote_builtin(*built_in)
+            }
+            Expression::Atom(atom) => {
+                Expression::Atom(atom.clone())
+            }
+            Expression::Quote(data) => {
+                form_of("quote", [(**data).clone()])
+            }
+            // Nested templates are filled in once they're
+            // evaluated
+            Expression::Quasiquote(template) => {
+                form_of("quasiquote", [quote(template)])
+            }
+            Expression::Application(app) => {
+                let mut parts = match &app.name {
+                    // Which may be spliced in, as any other part
+                    FnIdentifier::Expression(callee) => {
+                        self.all(iter::once(&**callee))?
+                    }
+                    FnIdentifier::BuiltIn(built_in) => {
+                        vec![quote_builtin(*built_in)]
+                    }
+                    FnIdentifier::Symbol(symbol) => {
+                        vec![Expression::Atom(Atom::Symbol(
+                            symbol.clone(),
+                        ))]
+                    }
+                    FnIdentifier::Other(name) => {
+                        vec![identifier(name)]
+                    }
+                };
+                parts.extend(self.all(&app.arguments)?);
+                Expression::List(parts)
+            }
+            Expression::If(if_expr) => form_of(
+                "if",
+                [
+                    self.code(&if_expr.condition)?,
+                    self.code(&if_expr.do_this)?,
+                ],
+            ),
+            Expression::IfElse(if_else) => form_of(
+                "if",
+                [
+                    self.code(&if_else.condition)?,
+                    self.code(&if_else.if_true)?,
+                    self.code(&if_else.if_false)?,
+                ],
+            ),
+            Expression::Binding(binding) => form_of(
+                "def",
+                [
+                    self.pattern(&binding.pattern)?,
+                    self.code(&binding.expression)?,
+                ],
+            ),
+            Expression::DefMacro(binding) => {
+                let Expression::Lambda(lambda) =
+                    &binding.expression
+                else {
+                    unreachable!(
+                        "macros are expanded by a lambda"
+                    )
+                };
+                let mut parts = vec![
+                    self.pattern(&binding.pattern)?,
+                    self.parameters(lambda)?,
+                ];
+                parts.extend(self.body(&lambda.body)?);
+                form_of("defmacro", parts)
+            }
+            Expression::Let(let_expr) => {
+                let mut bindings = vec![];
+                for (pattern, expression) in &let_expr.bindings {
+                    bindings.push(self.pattern(pattern)?);
+                    bindings.push(self.code(expression)?);
+                }
+                form_of(
+                    "let",
+                    [
+                        Expression::List(bindings),
+                        self.code(&let_expr.body)?,
+                    ],
+                )
+            }
+            Expression::Match(match_expr) => {
+                let mut parts =
+                    vec![self.code(&match_expr.expression)?];
+                for clause in &match_expr.clauses {
+                    parts.push(self.pattern(&clause.pattern)?);
+                    if let Some(guard) = &clause.guard {
+                        parts.push(Expression::Atom(
+                            Atom::Symbol(SmallString::new(
+                                "when",
+                            )),
+                        ));
+                        parts.push(self.code(guard)?);
+                    }
+                    parts.push(self.code(&clause.branch)?);
+                }
+                form_of("match", parts)
+            }
+            // Already evaluated, so it's a value rather than the
+            // code of a lambda
+            Expression::Lambda(lambda)
+                if lambda.captured.is_some() =>
+            {
+                code.clone()
+            }
+            Expression::Lambda(lambda) => {
+                let mut parts = vec![self.parameters(lambda)?];
+                parts.extend(self.body(&lambda.body)?);
+                form_of("fn", parts)
+            }
+            Expression::Do(body) => {
+                form_of("do", self.all(body)?)
+            }
+            Expression::List(items) => Expression::List(
+                iter::once(Expression::Atom(Atom::BuiltIn(
+                    BuiltIn::List,
+                )))
+                .chain(self.all(items)?)
+                .collect(),
+            ),
+            Expression::Map(map) => {
+                let entries = map
+                    .iter()
+                    .map(|(key, value)| {
+                        Ok((self.code(key)?, self.code(value)?))
+                    })
+                    .collect::<Result<Map>>()?;
+                Expression::Map(Rc::new(entries))
+            }
+            Expression::Set(set) => Expression::Set(Rc::new(
+                self.all(set.iter())?.into_iter().collect(),
+            )),
+        };
+
+        Ok(data)
+    }
+
+    /// Takes a sequence of code as data, splicing in the items
+    /// of the forms unquoted with `~@` when filling in a
+    /// template
+    fn all<'e>(
+        &mut self,
+        codes: impl IntoIterator<Item = &'e Expression>,
+    ) -> Result<Vec<Expression>> {
+        let mut data = vec![];
+
+        for code in codes {
+            let spliced = match code {
+                Expression::UnquoteSplicing(form) => {
+                    self.fill_in(form)
+                }
+                _ => None,
+            };
+            match spliced {
+                Some(items) => {
+                    let items = items?.as_list()?;
+                    // List literals splice their items alone
+                    let items = match is_list_literal(&items) {
+                        true => &items[1..],
+                        false => &items[..],
+                    };
+                    data.extend_from_slice(items);
+                }
+                None => data.push(self.code(code)?),
+            }
+        }
+
+        Ok(data)
+    }
+
+    fn pattern(
+        &mut self,
+        pattern: &Pattern,
+    ) -> Result<Expression> {
+        let data = match pattern {
+            Pattern::Identifier(name) => identifier(name),
+            Pattern::Wildcard => identifier("_"),
+            Pattern::Literal(literal) => literal.clone(),
+            Pattern::List { items, rest } => {
+                let mut list = items
+                    .iter()
+                    .map(|item| self.pattern(item))
+                    .collect::<Result<Vec<_>>>()?;
+                if let Some(rest) = rest {
+                    list.extend([
+                        identifier("&"),
+                        self.pattern(rest)?,
+                    ]);
+                }
+                Expression::List(list)
+            }
+            Pattern::Map(entries) => {
+                let entries = entries
+                    .iter()
+                    .map(|(pattern, key)| {
+                        Ok((self.pattern(pattern)?, key.clone()))
+                    })
+                    .collect::<Result<Map>>()?;
+                Expression::Map(Rc::new(entries))
+            }
+            Pattern::Unquote(form) => match self.fill_in(form) {
+                Some(value) => return value,
+                None => form_of("unquote", [quote(form)]),
+            },
+        };
+
+        Ok(data)
+    }
+
+    /// The expressions making up the body of a lambda, as it's
+    /// written after its parameters
+    fn body(
+        &mut self,
+        body: &Expression,
+    ) -> Result<Vec<Expression>> {
+        match body {
+            Expression::Do(body) if !body.is_empty() => {
+                self.all(body)
+            }
+            body => Ok(vec![self.code(body)?]),
+        }
+    }
+
+    /// Parameters as they're written, with optional ones as
+    /// lists of their name and default
+    fn parameters(
+        &mut self,
+        lambda: &Lambda,
+    ) -> Result<Expression> {
+        if let Some(template) = &lambda.template {
+            return self.all(template).map(Expression::List);
+        }
+
+        let parameters = &lambda.parameters;
+        let mut data: Vec<_> = parameters
+            .required
+            .iter()
+            .map(|name| identifier(name))
+            .collect();
+        for (name, default) in &parameters.optional {
+            data.push(Expression::List(vec![
+                identifier(name),
+                self.code(default)?,
+            ]));
+        }
+        if let Some(rest) = &parameters.rest {
+            data.extend([identifier("&"), identifier(rest)]);
+        }
+
+        Ok(Expression::List(data))
+    }
+
+    /// The value of an unquoted form when filling in a template,
+    /// or `None` otherwise
+    fn fill_in(
+        &mut self,
+        form: &Expression,
+    ) -> Option<Result<Expression>> {
+        self.unquote.as_mut().map(|unquote| unquote(form))
+    }
+}
+
+/// The code `data` stands for, as taken by [`quote`]. Wherever
+/// code is made of patterns, such as the bindings of a `let`,
+/// list literals stand for their items, which is how macros are
+/// given what's written as `[x 1]`. Code is located at `span`,
+/// that of the macro application it's the expansion of
+pub(crate) fn into_code(
+    data: &Expression,
+    span: Span,
+) -> Result<Expression> {
+    let code = match data {
+        Expression::Atom(Atom::Identifier(name)) => {
+            match builtin_named(name) {
+                Some(built_in) => {
+                    Expression::Atom(Atom::BuiltIn(built_in))
+                }
+                None => data.clone(),
+            }
+        }
+        Expression::List(list) => list_into_code(list, span)?,
+        Expression::Map(map) => {
+            let entries = map
+                .iter()
+                .map(|(key, value)| {
+                    Ok((
+                        into_code(key, span)?,
+                        into_code(value, span)?,
+                    ))
+                })
+                .collect::<Result<Map>>()?;
+            Expression::Map(Rc::new(entries))
+        }
+        Expression::Set(set) => {
+            let items = set
+                .iter()
+                .map(|item| into_code(item, span))
+                .collect::<Result<Set>>()?;
+            Expression::Set(Rc::new(items))
+        }
+        // Any other value is code evaluating to itself
+        value => value.clone(),
+    };
+
+    Ok(code)
+}
+
+/// The code a list stands for: the special form it's named
+/// after, if any, or else an application
+fn list_into_code(
+    list: &[Expression],
+    span: Span,
+) -> Result<Expression> {
+    let Some((head, parts)) = list.split_first() else {
+        // Which isn't code, so it stands for itself
+        return Ok(Expression::List(vec![]));
+    };
+    let code = |data: &Expression| into_code(data, span);
+    let form = SPECIAL_FORMS
+        .into_iter()
+        .find(|form| is_named(head, form));
+
+    let code = match (form, parts) {
+        (Some("if"), [condition, do_this]) => {
+            Expression::If(Box::new(If {
+                condition: code(condition)?,
+                do_this: code(do_this)?,
+                span,
+            }))
+        }
+        (Some("if"), [condition, if_true, if_false]) => {
+            Expression::IfElse(Box::new(IfElse {
+                condition: code(condition)?,
+                if_true: code(if_true)?,
+                if_false: code(if_false)?,
+                span,
+            }))
+        }
+        (Some("def"), [pattern, expression]) => {
+            Expression::Binding(Box::new(Binding {
+                pattern: into_pattern(pattern, span, false)?,
+                expression: code(expression)?,
+                span,
+            }))
+        }
+        (Some("defmacro"), [name, parameters, body @ ..])
+            if !body.is_empty() =>
+        {
+            let pattern = into_pattern(name, span, false)?;
+            if !matches!(
+                pattern,
+                Pattern::Identifier(_) | Pattern::Unquote(_)
+            ) {
+                return Err(malformed("defmacro", list));
+            }
+            let lambda = into_lambda(parameters, body, span)?;
+            Expression::DefMacro(Box::new(Binding {
+                pattern,
+                expression: Expression::Lambda(Box::new(lambda)),
+                span,
+            }))
+        }
+        (Some("fn"), [parameters, body @ ..])
+            if !body.is_empty() =>
+        {
+            let lambda = into_lambda(parameters, body, span)?;
+            Expression::Lambda(Box::new(lambda))
+        }
+        (Some("let"), [bindings, body]) => {
+            let bindings = match items_of(bindings) {
+                Some(bindings)
+                    if bindings.len().is_multiple_of(2) =>
+                {
+                    bindings
+                        .chunks(2)
+                        .map(|binding| {
+                            Ok((
+                                into_pattern(
+                                    &binding[0],
+                                    span,
+                                    false,
+                                )?,
+                                code(&binding[1])?,
+                            ))
+                        })
+                        .collect::<Result<_>>()?
+                }
+                _ => return Err(malformed("let", list)),
+            };
+
+            Expression::Let(Box::new(Let {
+                bindings,
+                body: code(body)?,
+                span,
+            }))
+        }
+        (Some("match"), [expression, clauses @ ..]) => {
+            Expression::Match(Box::new(Match {
+                expression: code(expression)?,
+                clauses: into_clauses(clauses, span)
+                    .ok_or_else(|| malformed("match", list))??,
+                span,
+            }))
+        }
+        (Some("do"), body) => Expression::Do(
+            body.iter().map(code).collect::<Result<_>>()?,
+        ),
+        (Some("quote"), [data]) => {
+            Expression::Quote(Rc::new(data.clone()))
+        }
+        (Some("quasiquote"), [template]) => {
+            Expression::Quasiquote(Rc::new(code(template)?))
+        }
+        (Some("unquote"), [form]) => {
+            Expression::Unquote(Rc::new(code(form)?))
+        }
+        (Some("unquote-splicing"), [form]) => {
+            Expression::UnquoteSplicing(Rc::new(code(form)?))
+        }
+        (Some(form), _) => return Err(malformed(form, list)),
+        (None, arguments) => {
+            let name = match code(head)? {
+                Expression::Atom(Atom::BuiltIn(built_in)) => {
+                    FnIdentifier::BuiltIn(built_in)
+                }
+                Expression::Atom(Atom::Symbol(symbol)) => {
+                    FnIdentifier::Symbol(symbol)
+                }
+                Expression::Atom(Atom::Identifier(name)) => {
+                    FnIdentifier::Other(name)
+                }
+                callee => {
+                    FnIdentifier::Expression(Rc::new(callee))
+                }
+            };
+
+            Expression::Application(Application {
+                name,
+                arguments: arguments
+                    .iter()
+                    .map(code)
+                    .collect::<Result<_>>()?,
+                span,
+            })
+        }
+    };
+
+    Ok(code)
+}
+
+/// A lambda out of its parameters and body as data. Within the
+/// parameters, lists are optional parameters along with their
+/// default, as they're written in parentheses, while list
+/// literals are patterns
+fn into_lambda(
+    parameters: &Expression,
+    body: &[Expression],
+    span: Span,
+) -> Result<Lambda> {
+    let malformed = || malformed("parameters", [parameters]);
+    let mut body = body
+        .iter()
+        .map(|data| into_code(data, span))
+        .collect::<Result<Vec<_>>>()?;
+    let body = if body.len() == 1 {
+        // Safe unwrap: we've just checked its length
+        body.pop().unwrap()
+    } else {
+        Expression::Do(body)
+    };
+
+    // Parameters of a lambda within a template, as built by
+    // macros defining macros
+    if is_unquoted(parameters) {
+        let Expression::Unquote(form) =
+            into_code(parameters, span)?
+        else {
+            return Err(malformed());
+        };
+        let template = vec![Expression::UnquoteSplicing(form)];
+        return Ok(Lambda::template(template, body, span));
+    }
+    let items = items_of(parameters).ok_or_else(malformed)?;
+    if items.iter().any(is_unquoted) {
+        let template = items
+            .iter()
+            .map(|item| into_code(item, span))
+            .collect::<Result<_>>()?;
+        return Ok(Lambda::template(template, body, span));
+    }
+
+    let mut required = vec![];
+    let mut optional = vec![];
+    let mut rest = None;
+
+    let mut items = items.iter();
+    while let Some(item) = items.next() {
+        match item {
+            _ if is_named(item, "&") => {
+                match (items.next(), items.next()) {
+                    (Some(pattern), None) => {
+                        rest = Some(into_pattern(
+                            pattern, span, false,
+                        )?)
+                    }
+                    _ => return Err(malformed()),
+                }
+            }
+            Expression::List(list) if !is_list_literal(list) => {
+                match list.as_slice() {
+                    [pattern, default] => optional.push((
+                        into_pattern(pattern, span, false)?,
+                        into_code(default, span)?,
+                    )),
+                    _ => return Err(malformed()),
+                }
+            }
+            pattern if optional.is_empty() => required
+                .push(into_pattern(pattern, span, false)?),
+            _ => return Err(malformed()),
+        }
+    }
+
+    Ok(Lambda::destructuring(
+        Parameters::from_patterns(required, optional, rest),
+        body,
+        span,
+    ))
+}
+
+/// The clauses of a `match`, as written after the expression
+/// being matched, or `None` if they're missing a branch
+fn into_clauses(
+    mut data: &[Expression],
+    span: Span,
+) -> Option<Result<Vec<MatchClause>>> {
+    let is_when = |data: &Expression| {
+        matches!(
+            data,
+            Expression::Atom(Atom::Symbol(symbol))
+                if symbol.as_str() == "when"
+        )
+    };
+    let mut clauses = vec![];
+
+    loop {
+        let (pattern, guard, branch, remaining) = match data {
+            [] => return Some(Ok(clauses)),
+            [pattern, when, guard, branch, remaining @ ..]
+                if is_when(when) =>
+            {
+                (pattern, Some(guard), branch, remaining)
+            }
+            [pattern, branch, remaining @ ..] => {
+                (pattern, None, branch, remaining)
+            }
+            _ => return None,
+        };
+
+        let clause = (|| {
+            Ok(MatchClause {
+                pattern: into_pattern(pattern, span, true)?,
+                guard: guard
+                    .map(|guard| into_code(guard, span))
+                    .transpose()?,
+                branch: into_code(branch, span)?,
+            })
+        })();
+        match clause {
+            Ok(clause) => clauses.push(clause),
+            Err(error) => return Some(Err(error)),
+        }
+        data = remaining;
+    }
+}
+
+/// The pattern `data` stands for. Literals are only taken where
+/// the parser takes them, e.g. within list patterns but not as
+/// the whole pattern of a `def`
+fn into_pattern(
+    data: &Expression,
+    span: Span,
+    literals: bool,
+) -> Result<Pattern> {
+    let pattern = match data {
+        _ if is_named(data, "_") => Pattern::Wildcard,
+        _ if is_named(data, "&") => {
+            return Err(malformed("pattern", [data]))
+        }
+        Expression::Atom(Atom::Identifier(name)) => {
+            Pattern::Identifier(name.clone())
+        }
+        Expression::List(list) => match list.as_slice() {
+            [head, form] if is_named(head, "unquote") => {
+                Pattern::Unquote(Rc::new(into_code(form, span)?))
+            }
+            _ => {
+                // Safe unwrap: `data` is a list
+                let items = items_of(data).unwrap();
+                let (items, rest) = match items
+                    .iter()
+                    .position(|item| is_named(item, "&"))
+                {
+                    Some(idx) if idx + 2 == items.len() => {
+                        (&items[..idx], Some(&items[idx + 1]))
+                    }
+                    Some(_) => {
+                        return Err(malformed("pattern", [data]))
+                    }
+                    None => (items, None),
+                };
+
+                Pattern::List {
+                    items: items
+                        .iter()
+                        .map(|item| {
+                            into_pattern(item, span, true)
+                        })
+                        .collect::<Result<_>>()?,
+                    rest: rest
+                        .map(|rest| {
+                            into_pattern(rest, span, false)
+                        })
+                        .transpose()?
+                        .map(Box::new),
+                }
+            }
+        },
+        Expression::Map(map) => Pattern::Map(
+            map.iter()
+                .map(|(pattern, key)| {
+                    Ok((
+                        into_pattern(pattern, span, true)?,
+                        key.clone(),
+                    ))
+                })
+                .collect::<Result<_>>()?,
+        ),
+        // Taken as it's written
+        Expression::Atom(atom) if literals => {
+            Pattern::Literal(Expression::Atom(atom.clone()))
+        }
+        _ => return Err(malformed("pattern", [data])),
+    };
+
+    // Checked here as the parser does, since templates may build
+    // patterns out of the same unquoted identifier
+    if pattern.repeated_identifier().is_some() {
+        return Err(malformed("pattern", [data]));
+    }
+
+    Ok(pattern)
+}
+
+/// The items of a list written where brackets are syntax rather
+/// than a list literal, as are `let` bindings. Those of a list
+/// literal are taken as well
+fn items_of(data: &Expression) -> Option<&[Expression]> {
+    match data {
+        Expression::List(list) if is_list_literal(list) => {
+            Some(&list[1..])
+        }
+        Expression::List(list) => Some(list),
+        _ => None,
+    }
+}
+
+/// Whether `list` is a list literal taken as data, which starts
+/// with the `list` built-in
+fn is_list_literal(list: &[Expression]) -> bool {
+    matches!(
+        list.first(),
+        Some(Expression::Atom(Atom::BuiltIn(BuiltIn::List)))
+    )
+}
+
+/// Built-ins are taken as the name they're read from, e.g. `+`,
+/// so that macros see them as they're written. Those named by
+/// identifiers, which may be bound to something else, are taken
+/// as themselves, such as the `list` heading list literals
+fn quote_builtin(built_in: BuiltIn) -> Expression {
+    let name = built_in.rough_type();
+
+    match builtin_named(name) {
+        Some(_) => identifier(name),
+        None => Expression::Atom(Atom::BuiltIn(built_in)),
+    }
+}
+
+/// The form `(name parts*)`, as data
+fn form_of(
+    name: &str,
+    parts: impl IntoIterator<Item = Expression>,
+) -> Expression {
+    Expression::List(
+        iter::once(identifier(name)).chain(parts).collect(),
+    )
+}
+
+fn identifier(name: &str) -> Expression {
+    Expression::Atom(Atom::Identifier(SmallString::new(name)))
+}
+
+/// Whether `data` is an `unquote` or `unquote-splicing` form
+fn is_unquoted(data: &Expression) -> bool {
+    matches!(
+        data,
+        Expression::List(list) if matches!(
+            list.as_slice(),
+            [head, _] if is_named(head, "unquote")
+                || is_named(head, "unquote-splicing")
+        )
+    )
+}
+
+/// Whether `data` is the identifier `name`
+fn is_named(data: &Expression, name: &str) -> bool {
+    matches!(
+        data,
+        Expression::Atom(Atom::Identifier(identifier))
+            if identifier.as_str() == name
+    )
+}
+
+fn malformed<'a>(
+    form: &'static str,
+    code: impl IntoIterator<Item = &'a Expression>,
+) -> Error {
+    let code: Vec<_> = code.into_iter().cloned().collect();
+    let code = match <[Expression; 1]>::try_from(code) {
+        Ok([code]) => code,
+        Err(list) => Expression::List(list),
+    };
+
+    Error::MalformedCode {
+        form,
+        code: SmallString::new(code.to_string()),
+    }
+}
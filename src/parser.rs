@@ -1,34 +1,87 @@
 use nom::{
-    branch::alt, bytes::streaming::tag,
-    character::complete::alphanumeric1, combinator::not,
-    error::VerboseError, sequence::terminated,
+    branch::alt,
+    bytes::complete::{tag, take_while1},
+    character::complete::satisfy,
+    combinator::{not, recognize, verify},
+    sequence::terminated,
+    Parser,
 };
 
 mod atom;
+mod error;
 mod expression;
+mod whitespace;
 
+pub(crate) use atom::builtin_named;
 pub use atom::parse_atom;
-pub use expression::parse_expression;
+use atom::{parse_named_builtin, parse_operator};
+pub use error::ParseError;
+pub(crate) use error::ParseErrorKind;
+pub use expression::{parse_expression, parse_program};
+pub(crate) use whitespace::space_or_comment0;
+
+use crate::Expected;
 
 /// The result of a parsing operation with added error context
 pub type IResult<'a, T> =
-    nom::IResult<&'a str, T, VerboseError<&'a str>>;
+    nom::IResult<&'a str, T, ParseError<'a>>;
 
 /// Parses all words considered to be reserved.
 ///
 /// Used as an auxiliary parser to guarantee that reserved words
 /// aren't used as identifiers
-fn parse_reserved_word(input: &str) -> IResult<&str> {
-    terminated(
-        alt((
-            tag("if"),
-            tag("true"),
-            tag("false"),
-            tag("nil"),
-            tag("def"),
-            tag("fn"),
-            tag("cond"),
+fn parse_reserved_word(input: &str) -> IResult<'_, &str> {
+    alt((
+        verify(take_while1(is_identifier_char), |word: &str| {
+            matches!(
+                word,
+                "if" | "true"
+                    | "false"
+                    | "nil"
+                    | "def"
+                    | "defmacro"
+                    | "let"
+                    | "match"
+                    | "fn"
+                    | "do"
+                    | "cond"
+                    | "and"
+                    | "or"
+            )
+        }),
+        recognize(parse_named_builtin),
+        recognize(parse_operator),
+    ))(input)
+}
+
+/// Whether `ch` may be part of an identifier, e.g. `->` or
+/// `html->text`. Those made of just an operator, such as `-`,
+/// are read as the operator
+fn is_identifier_char(ch: char) -> bool {
+    ch.is_ascii_alphanumeric()
+        || matches!(ch, '-' | '_' | '?' | '<' | '>')
+}
+
+/// Parses `word`, as long as it's not just the beginning of a
+/// longer identifier, e.g. `count` but not `count-all`
+fn keyword<'a>(
+    word: &'static str,
+) -> impl FnMut(&'a str) -> IResult<'a, &'a str> {
+    terminated(tag(word), not(satisfy(is_identifier_char)))
+}
+
+/// Runs `parser`, failing without backtracking if it doesn't
+/// succeed, and reporting `expected` as what should have been
+/// at `input`. Used past the point where the construct being
+/// parsed is known, e.g. the keyword of a special form
+fn expect<'a, T>(
+    expected: Expected,
+    mut parser: impl Parser<&'a str, T, ParseError<'a>>,
+) -> impl FnMut(&'a str) -> IResult<'a, T> {
+    move |input| match parser.parse(input) {
+        Err(nom::Err::Error(_)) => Err(nom::Err::Failure(
+            ParseError::expected(input, expected.clone()),
         )),
-        not(alphanumeric1),
-    )(input)
+        result => result,
+    }
 }
@@ -1,33 +1,41 @@
 use nom::{
     branch::alt,
-    bytes::complete::{escaped, tag, take_while1},
-    character::complete::{
-        alphanumeric1, digit1, none_of, one_of,
+    bytes::complete::{
+        escaped_transform, is_not, tag, take_while1,
+        take_while_m_n,
+    },
+    character::complete::{char, digit1, one_of, satisfy},
+    combinator::{
+        all_consuming, map_opt, not, opt, recognize, value,
     },
-    combinator::{cut, not, recognize, value},
     error::context,
+    multi::many0_count,
     number::complete::double,
     sequence::{delimited, pair, preceded, terminated},
     Parser,
 };
 
-use super::{parse_reserved_word, IResult};
+use super::{
+    expect, is_identifier_char, keyword, parse_reserved_word,
+    IResult, ParseError, ParseErrorKind,
+};
 use crate::{
     expression::{
         elements::{Atom, FnIdentifier},
-        BuiltIn,
+        BuiltIn, Numeric,
     },
-    SmallString,
+    Expected, SmallString,
 };
 
-pub fn parse_atom(input: &str) -> IResult<Atom> {
+pub fn parse_atom(input: &str) -> IResult<'_, Atom> {
     context(
         "atom",
         alt((
+            parse_exact.map(Atom::from),
             parse_double.map(Atom::Number),
             parse_boolean.map(Atom::Boolean),
             parse_builtin.map(Atom::BuiltIn),
-            value(Atom::Nil, tag("nil")),
+            value(Atom::Nil, keyword("nil")),
             parse_symbol.map(SmallString::new).map(Atom::Symbol),
             parse_string.map(SmallString::new).map(Atom::String),
             parse_identifier
@@ -37,68 +45,87 @@ pub fn parse_atom(input: &str) -> IResult<Atom> {
     )(input)
 }
 
-pub fn parse_identifier(input: &str) -> IResult<&str> {
-    let acceptable_chars = |ch: char| {
-        ch.is_ascii_alphanumeric()
-            || matches!(ch, '-' | '_' | '?')
-    };
-
-    let (rest, identifier) = recognize(pair(
-        // Ensure that the identifier doesn't start with a
-        // digit
-        not(digit1),
-        take_while1(acceptable_chars),
-    ))(input)?;
+pub fn parse_identifier(input: &str) -> IResult<'_, &str> {
+    let (rest, identifier) = parse_name(input)?;
 
     not(parse_reserved_word)(input)?;
 
     Ok((rest, identifier))
 }
 
-fn parse_symbol(input: &str) -> IResult<&str> {
+/// Parses what identifiers and symbols are made of, including
+/// reserved words
+fn parse_name(input: &str) -> IResult<'_, &str> {
+    recognize(pair(
+        // Ensure that the name doesn't start with a digit
+        not(digit1),
+        take_while1(is_identifier_char),
+    ))(input)
+}
+
+/// Parses symbols, e.g. `:name`. They're never looked up, so
+/// reserved words make for symbols as well, such as `:first`
+fn parse_symbol(input: &str) -> IResult<'_, &str> {
     context(
         "symbol",
-        preceded(tag(":"), cut(parse_identifier)),
+        preceded(
+            tag(":"),
+            expect(Expected::Identifier, parse_name),
+        ),
     )(input)
 }
 
 #[inline(always)]
-fn parse_builtin(input: &str) -> IResult<BuiltIn> {
+fn parse_builtin(input: &str) -> IResult<'_, BuiltIn> {
     context(
         "builtin",
-        alt((
-            parse_operator,
-            value(
-                BuiltIn::Not,
-                terminated(tag("not"), not(alphanumeric1)),
-            ),
-            value(
-                BuiltIn::And,
-                terminated(tag("and"), not(alphanumeric1)),
-            ),
-            value(
-                BuiltIn::Or,
-                terminated(tag("or"), not(alphanumeric1)),
-            ),
-            value(
-                BuiltIn::Cond,
-                terminated(tag("cond"), not(alphanumeric1)),
-            ),
-            value(
-                BuiltIn::Count,
-                terminated(tag("count"), not(alphanumeric1)),
-            ),
-        )),
+        alt((parse_operator, parse_named_builtin)),
     )(input)
 }
 
+/// The built-in `name` is read as, if any, e.g. `+` or `cond`,
+/// but not `first`, which is read as an identifier
+pub(crate) fn builtin_named(name: &str) -> Option<BuiltIn> {
+    all_consuming(parse_builtin)(name)
+        .ok()
+        .map(|(_, built_in)| built_in)
+}
+
+/// Parses the built-ins that have names rather than being
+/// operators but aren't looked up as identifiers are: those
+/// whose names can't be identifiers, and the reserved words
+/// `cond`, `and` and `or`, which don't evaluate all of their
+/// arguments. The rest are global bindings that may be shadowed
+pub(super) fn parse_named_builtin(
+    input: &str,
+) -> IResult<'_, BuiltIn> {
+    alt((
+        value(BuiltIn::NotEqual, keyword("not=")),
+        value(
+            BuiltIn::ExactToInexact,
+            keyword("exact->inexact"),
+        ),
+        map_opt(take_while1(is_identifier_char), |word| {
+            match word {
+                "cond" => Some(BuiltIn::Cond),
+                "and" => Some(BuiltIn::And),
+                "or" => Some(BuiltIn::Or),
+                _ => None,
+            }
+        }),
+    ))(input)
+}
+
 pub fn parse_fn_identifier(
     input: &str,
-) -> IResult<FnIdentifier> {
+) -> IResult<'_, FnIdentifier> {
     context(
         "identifier",
         alt((
             parse_builtin.map(FnIdentifier::BuiltIn),
+            parse_symbol
+                .map(SmallString::new)
+                .map(FnIdentifier::Symbol),
             parse_identifier
                 .map(SmallString::new)
                 .map(FnIdentifier::Other),
@@ -107,9 +134,9 @@ pub fn parse_fn_identifier(
 }
 
 #[inline(always)]
-fn parse_boolean(input: &str) -> IResult<bool> {
+fn parse_boolean(input: &str) -> IResult<'_, bool> {
     let (rest, boolean) =
-        alt((tag("true"), tag("false")))(input)?;
+        alt((keyword("true"), keyword("false")))(input)?;
 
     let is_true = boolean == "true";
 
@@ -117,9 +144,35 @@ fn parse_boolean(input: &str) -> IResult<bool> {
 }
 
 #[inline(always)]
-fn parse_operator(input: &str) -> IResult<BuiltIn> {
+pub(super) fn parse_operator(
+    input: &str,
+) -> IResult<'_, BuiltIn> {
+    // Operators may start identifiers, such as `->`, though
+    // not numbers, as in `(=7 8)`
+    let continues_identifier = |ch: char| {
+        is_identifier_char(ch) && !ch.is_ascii_digit()
+    };
+
+    terminated(
+        parse_bare_operator,
+        not(satisfy(continues_identifier)),
+    )(input)
+}
+
+fn parse_bare_operator(input: &str) -> IResult<'_, BuiltIn> {
+    // Two-character operators go first, as each starts with a
+    // single-character one
+    let two_chars: IResult<'_, BuiltIn> = alt((
+        value(BuiltIn::LessEqual, tag("<=")),
+        value(BuiltIn::GreaterEqual, tag(">=")),
+        value(BuiltIn::NotEqual, tag("!=")),
+    ))(input);
+    if two_chars.is_ok() {
+        return two_chars;
+    }
+
     let (rest, op) =
-        context("operator", one_of("+-*/=%"))(input)?;
+        context("operator", one_of("+-*/=%<>"))(input)?;
 
     let op = match op {
         '+' => BuiltIn::Plus,
@@ -128,9 +181,11 @@ fn parse_operator(input: &str) -> IResult<BuiltIn> {
         '/' => BuiltIn::Divide,
         '=' => BuiltIn::Equal,
         '%' => BuiltIn::Remainder,
+        '<' => BuiltIn::Less,
+        '>' => BuiltIn::Greater,
         _ => {
             unreachable!(
-                "we checked that `op in [+-*/=%]` above"
+                "we checked that `op in [+-*/=%<>]` above"
             )
         }
     };
@@ -138,21 +193,130 @@ fn parse_operator(input: &str) -> IResult<BuiltIn> {
     Ok((rest, op))
 }
 
+/// Parses exact numbers: integers, which may be of any size,
+/// and rationals such as `1/3`
+fn parse_exact(input: &str) -> IResult<'_, Numeric> {
+    let (rest, (numerator, denominator)) = terminated(
+        pair(
+            recognize(pair(opt(one_of("+-")), digit1)),
+            opt(preceded(char('/'), digit1)),
+        ),
+        // Otherwise, it's the integer part of a float
+        not(one_of(".eE")),
+    )(input)?;
+
+    match Numeric::parse(numerator, denominator) {
+        Some(number) => Ok((rest, number)),
+        // Only a denominator of zero is rejected, which mustn't
+        // be read as a division by the parsers after this one
+        None => Err(nom::Err::Failure(ParseError {
+            errors: vec![
+                (
+                    &input[numerator.len() + 1..],
+                    ParseErrorKind::Expected(
+                        Expected::NonZeroDenominator,
+                    ),
+                ),
+                (input, ParseErrorKind::Context("rational")),
+            ],
+        })),
+    }
+}
+
 #[inline(always)]
-fn parse_double(input: &str) -> IResult<f64> {
+fn parse_double(input: &str) -> IResult<'_, f64> {
     double(input)
 }
 
-fn parse_string(input: &str) -> IResult<&str> {
-    let esc = escaped(none_of("\\\""), '\\', tag("\""));
-    let esc_or_empty = alt((esc, tag("")));
+/// Parses string literals, which may span many lines, decoding
+/// their escape sequences
+fn parse_string(input: &str) -> IResult<'_, String> {
+    alt((
+        parse_raw_string.map(str::to_owned),
+        parse_escaped_string,
+    ))(input)
+}
 
-    delimited(tag("\""), esc_or_empty, tag("\""))(input)
+fn parse_escaped_string(input: &str) -> IResult<'_, String> {
+    // Past a backslash, only an escape sequence may follow
+    let escape = expect(
+        Expected::EscapeSequence,
+        alt((
+            value('\n', char('n')),
+            value('\t', char('t')),
+            value('\r', char('r')),
+            value('\\', char('\\')),
+            value('"', char('"')),
+            parse_unicode_escape,
+        )),
+    );
+    let contents =
+        escaped_transform(is_not("\\\""), '\\', escape);
+    let contents_or_empty =
+        alt((contents, value(String::new(), tag(""))));
+
+    delimited(
+        tag("\""),
+        contents_or_empty,
+        expect(Expected::ClosingQuote, tag("\"")),
+    )(input)
+}
+
+/// Parses the code point of a `\u{...}` escape, given in hex
+fn parse_unicode_escape(input: &str) -> IResult<'_, char> {
+    map_opt(
+        preceded(
+            char('u'),
+            delimited(
+                char('{'),
+                take_while_m_n(1, 6, |ch: char| {
+                    ch.is_ascii_hexdigit()
+                }),
+                char('}'),
+            ),
+        ),
+        |hex| {
+            u32::from_str_radix(hex, 16)
+                .ok()
+                .and_then(char::from_u32)
+        },
+    )(input)
+}
+
+/// Parses raw strings, in which backslashes are just
+/// backslashes: `r"..."`, or `r#"..."#` for those containing
+/// quotes, with as many `#`s as needed
+fn parse_raw_string(input: &str) -> IResult<'_, &str> {
+    let (rest, hashes) = delimited(
+        char('r'),
+        recognize(many0_count(char('#'))),
+        char('"'),
+    )(input)?;
+    let closing = format!("\"{hashes}");
+
+    match rest.find(&closing) {
+        Some(end) => {
+            Ok((&rest[end + closing.len()..], &rest[..end]))
+        }
+        // Once the opening quote is seen, it must be a raw
+        // string
+        None => Err(nom::Err::Failure(ParseError {
+            errors: vec![
+                (
+                    rest,
+                    ParseErrorKind::Expected(
+                        Expected::ClosingQuote,
+                    ),
+                ),
+                (input, ParseErrorKind::Context("raw string")),
+            ],
+        })),
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{parse_double, parse_string};
+    use super::{parse_double, parse_exact, parse_string};
     use crate::{
         expression::{elements::Atom, BuiltIn},
         parser::atom::{
@@ -168,6 +332,14 @@ mod tests {
             parse_atom("2.3"),
             Ok(("", Atom::Number(2.3)))
         );
+        assert_eq!(
+            parse_atom("23"),
+            Ok(("", Atom::Integer(23)))
+        );
+        assert_eq!(
+            parse_atom("2e3"),
+            Ok(("", Atom::Number(2e3)))
+        );
 
         assert_eq!(
             parse_atom("true"),
@@ -185,6 +357,13 @@ mod tests {
         );
 
         assert_eq!(parse_atom("nil"), Ok(("", Atom::Nil)));
+        assert_eq!(
+            parse_atom("nil-count"),
+            Ok((
+                "",
+                Atom::Identifier(SmallString::new("nil-count"))
+            ))
+        );
 
         assert_eq!(
             parse_atom("\"hey\""),
@@ -210,6 +389,9 @@ mod tests {
             Ok((" false", false))
         );
 
+        // Only as whole words
+        assert!(parse_boolean("truely").is_err());
+        assert!(parse_boolean("false?").is_err());
         assert!(parse_boolean("False").is_err());
         assert!(parse_boolean("True").is_err());
         assert!(parse_boolean("1").is_err());
@@ -223,6 +405,9 @@ mod tests {
             Ok((" other", "arg"))
         );
 
+        assert_eq!(parse_symbol(":first"), Ok(("", "first")));
+        assert_eq!(parse_symbol(":nil"), Ok(("", "nil")));
+
         assert!(parse_symbol("arg1").is_err());
         assert!(parse_symbol(":1arg").is_err());
     }
@@ -245,10 +430,6 @@ mod tests {
             parse_builtin("= +-/"),
             Ok((" +-/", BuiltIn::Equal))
         );
-        assert_eq!(
-            parse_builtin("not =+-/"),
-            Ok((" =+-/", BuiltIn::Not))
-        );
         assert_eq!(
             parse_builtin("and =+-/not"),
             Ok((" =+-/not", BuiltIn::And))
@@ -259,7 +440,22 @@ mod tests {
             Ok((" =+and-/not", BuiltIn::Or))
         );
 
+        assert_eq!(
+            parse_builtin("not= 1 2"),
+            Ok((" 1 2", BuiltIn::NotEqual))
+        );
+        assert_eq!(
+            parse_builtin("exact->inexact 1"),
+            Ok((" 1", BuiltIn::ExactToInexact))
+        );
+
         assert!(parse_builtin("a 1.2").is_err());
+        assert!(parse_builtin("android").is_err());
+
+        // Other built-ins are looked up as identifiers, so that
+        // they can be shadowed
+        assert!(parse_builtin("not true").is_err());
+        assert!(parse_builtin("empty? []").is_err());
     }
 
     #[test]
@@ -285,6 +481,18 @@ mod tests {
         assert!(parse_identifier("fn").is_err());
         assert!(parse_identifier("fnn").is_ok());
 
+        // Unlike the names of most built-ins
+        assert_eq!(parse_identifier("rest"), Ok(("", "rest")));
+        assert!(parse_identifier("cond").is_err());
+        assert_eq!(
+            parse_identifier("rest-args"),
+            Ok(("", "rest-args"))
+        );
+        assert_eq!(
+            parse_identifier("if-positive"),
+            Ok(("", "if-positive"))
+        );
+
         assert_eq!(
             parse_identifier("even? 123"),
             Ok((" 123", "even?"))
@@ -294,26 +502,105 @@ mod tests {
             parse_identifier("is-even? 123"),
             Ok((" 123", "is-even?"))
         );
+
+        // Identifiers may be made of `<` and `>` as well, though
+        // operators on their own are read as built-ins
+        assert_eq!(parse_identifier("->"), Ok(("", "->")));
+        assert_eq!(
+            parse_identifier("html->text)"),
+            Ok((")", "html->text"))
+        );
+        assert_eq!(parse_identifier("<tag>"), Ok(("", "<tag>")));
+        assert!(parse_identifier("-").is_err());
+        assert!(parse_identifier("<= 1").is_err());
+        assert!(parse_identifier(">2").is_err());
     }
 
     #[test]
     fn parses_strings() {
-        assert_eq!(parse_string("\"hey\""), Ok(("", "hey")));
+        assert_eq!(
+            parse_string("\"hey\""),
+            Ok(("", "hey".into()))
+        );
+        assert_eq!(parse_string("\"\""), Ok(("", "".into())));
 
         assert_eq!(
             parse_string("\"hello world\" hello"),
-            Ok((" hello", "hello world"))
+            Ok((" hello", "hello world".into()))
+        );
+
+        assert_eq!(
+            parse_string(r#""hello \"world" hello"#),
+            Ok((" hello", "hello \"world".into()))
+        );
+        assert_eq!(
+            parse_string(r#""\n\t\r\\\"\u{e9}\u{1F600}""#),
+            Ok(("", "\n\t\r\\\"é😀".into()))
+        );
+        assert_eq!(
+            parse_string("\"two\nlines\""),
+            Ok(("", "two\nlines".into()))
         );
 
+        // Raw strings
+        assert_eq!(
+            parse_string(r#"r"\d+\.\d*" 1"#),
+            Ok((" 1", r"\d+\.\d*".into()))
+        );
         assert_eq!(
-            parse_string("\"hello \\\"world\" hello"),
-            Ok((" hello", "hello \\\"world"))
+            parse_string(r##"r#"say "hi""# 1"##),
+            Ok((" 1", r#"say "hi""#.into()))
+        );
+        assert_eq!(parse_string(r#"r"""#), Ok(("", "".into())));
+
+        assert!(parse_string(r#""\q""#).is_err());
+        assert!(parse_string(r#""\u{110000}""#).is_err());
+        assert!(parse_string(r#"r"unterminated"#).is_err());
+        assert!(
+            parse_string(r##"r#"wrongly terminated""##).is_err()
         );
 
         assert!(parse_string("\"missing closing quote").is_err());
         assert!(parse_string("missing opening quote\"").is_err());
     }
 
+    #[test]
+    fn parses_exact_numbers() {
+        let parse = |input| {
+            parse_exact(input)
+                .map(|(rest, number)| (rest, Atom::from(number)))
+        };
+
+        assert_eq!(parse("42 1"), Ok((" 1", Atom::Integer(42))));
+        assert_eq!(parse("-7)"), Ok((")", Atom::Integer(-7))));
+        assert_eq!(parse("+7"), Ok(("", Atom::Integer(7))));
+        assert_eq!(
+            parse("9223372036854775807"),
+            Ok(("", Atom::Integer(i64::MAX)))
+        );
+        assert_eq!(
+            parse("-9223372036854775809").unwrap().1.to_string(),
+            "-9223372036854775809"
+        );
+        assert_eq!(parse("1/3").unwrap().1.to_string(), "1/3");
+        assert_eq!(
+            parse("-2/6 ").unwrap().1.to_string(),
+            "-1/3"
+        );
+        assert_eq!(parse("4/2"), Ok(("", Atom::Integer(2))));
+
+        // Floats, operators and rationals dividing by zero
+        assert!(parse("4.2").is_err());
+        assert!(parse("4e2").is_err());
+        assert!(parse("-").is_err());
+        assert!(parse("/2").is_err());
+        assert!(matches!(
+            parse("1/0"),
+            Err(nom::Err::Failure(error))
+                if error.errors[0].0 == "0"
+        ));
+    }
+
     #[test]
     fn parses_operators() {
         assert_eq!(
@@ -336,6 +623,26 @@ mod tests {
             parse_operator("%=+-/"),
             Ok(("=+-/", BuiltIn::Remainder))
         );
+        assert_eq!(
+            parse_operator("< 1"),
+            Ok((" 1", BuiltIn::Less))
+        );
+        assert_eq!(
+            parse_operator("<= 1"),
+            Ok((" 1", BuiltIn::LessEqual))
+        );
+        assert_eq!(
+            parse_operator(">=1"),
+            Ok(("1", BuiltIn::GreaterEqual))
+        );
+        assert_eq!(
+            parse_operator("> =1"),
+            Ok((" =1", BuiltIn::Greater))
+        );
+        assert_eq!(
+            parse_operator("!= 1"),
+            Ok((" 1", BuiltIn::NotEqual))
+        );
 
         assert!(parse_double("a 1.2").is_err());
     }
@@ -0,0 +1,90 @@
+use nom::error::{ContextError, ErrorKind};
+
+use crate::Expected;
+
+/// What the parsers report when they fail: where each of them
+/// failed, innermost first, and why
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError<'a> {
+    pub(crate) errors: Vec<(&'a str, ParseErrorKind)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum ParseErrorKind {
+    /// Something the failing parser would have accepted
+    Expected(Expected),
+    /// A construct that was being parsed, e.g. `"let bindings"`
+    Context(&'static str),
+    /// A failure of one of nom's parsers, which says too little
+    /// to be reported on its own
+    Nom(ErrorKind),
+}
+
+impl<'a> ParseError<'a> {
+    /// Fails at `input`, expecting something else there
+    pub(crate) fn expected(
+        input: &'a str,
+        expected: Expected,
+    ) -> Self {
+        Self {
+            errors: vec![(
+                input,
+                ParseErrorKind::Expected(expected),
+            )],
+        }
+    }
+}
+
+impl<'a> nom::error::ParseError<&'a str> for ParseError<'a> {
+    fn from_error_kind(input: &'a str, kind: ErrorKind) -> Self {
+        let kind = match kind {
+            ErrorKind::Eof => {
+                ParseErrorKind::Expected(Expected::EndOfInput)
+            }
+            kind => ParseErrorKind::Nom(kind),
+        };
+
+        Self {
+            errors: vec![(input, kind)],
+        }
+    }
+
+    fn append(
+        input: &'a str,
+        kind: ErrorKind,
+        mut other: Self,
+    ) -> Self {
+        other.errors.push((input, ParseErrorKind::Nom(kind)));
+        other
+    }
+
+    fn from_char(input: &'a str, ch: char) -> Self {
+        let expected = match ch {
+            ')' => Expected::ClosingParen,
+            ']' => Expected::ClosingBracket,
+            '}' => Expected::ClosingBrace,
+            ch => Expected::Char(ch),
+        };
+
+        Self::expected(input, expected)
+    }
+
+    /// Keeps the error of the last alternative tried, as nom's
+    /// `VerboseError` does
+    fn or(self, other: Self) -> Self {
+        other
+    }
+}
+
+impl<'a> ContextError<&'a str> for ParseError<'a> {
+    fn add_context(
+        input: &'a str,
+        context: &'static str,
+        mut other: Self,
+    ) -> Self {
+        other
+            .errors
+            .push((input, ParseErrorKind::Context(context)));
+        other
+    }
+}
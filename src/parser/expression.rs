@@ -1,10 +1,12 @@
+use std::rc::Rc;
+
 use nom::{
     branch::alt,
     bytes::complete::tag,
-    character::complete::{char, multispace0, multispace1},
-    combinator::{cut, opt},
-    error::{context, VerboseError},
-    multi::many0,
+    character::complete::char,
+    combinator::{all_consuming, cut, opt, verify},
+    error::context,
+    multi::{many0, many1},
     sequence::{delimited, preceded, terminated, tuple},
     Parser,
 };
@@ -12,187 +14,784 @@ use nom::{
 use crate::{
     expression::{
         elements::{
-            Application, Binding, FnIdentifier, If, IfElse,
-            Lambda,
+            Application, Atom, Binding, Destructured,
+            FnIdentifier, If, IfElse, Lambda, Let, Match,
+            MatchClause, Parameters, Pattern,
         },
-        Expression,
+        Expression, Map, Set,
+    },
+    macros, parse_atom,
+    parser::{
+        atom::{parse_fn_identifier, parse_identifier},
+        expect, keyword,
+        whitespace::space_or_comment0,
+        ParseError,
     },
-    parse_atom,
-    parser::atom::{parse_fn_identifier, parse_identifier},
-    IResult, SmallString,
+    Expected, IResult, SmallString, Span,
 };
 
-pub fn parse_expression(input: &str) -> IResult<Expression> {
-    preceded(
-        multispace0,
-        alt((
-            parse_atom.map(Expression::Atom),
+pub fn parse_expression(input: &str) -> IResult<'_, Expression> {
+    let (input, ()) = space_or_comment0(input)?;
+
+    // Expressions are told apart by how they start, so only the
+    // parsers that may succeed are tried
+    match input.as_bytes().first() {
+        Some(b'(') => alt((
             parse_if,
             parse_binding.map(Box::new).map(Expression::Binding),
+            parse_defmacro
+                .map(Box::new)
+                .map(Expression::DefMacro),
+            parse_let.map(Box::new).map(Expression::Let),
+            parse_match.map(Box::new).map(Expression::Match),
             parse_lambda.map(Box::new).map(Expression::Lambda),
+            parse_do,
             parse_application.map(Expression::Application),
-            parse_list.map(Expression::List),
-            parse_quote.map(Expression::List),
-        )),
+        ))(input),
+        Some(b'[') => {
+            parse_list.map(Expression::List).parse(input)
+        }
+        Some(b'\'') => parse_quote(input),
+        Some(b'{') => parse_map
+            .map(Rc::new)
+            .map(Expression::Map)
+            .parse(input),
+        Some(b'#') => parse_set
+            .map(Rc::new)
+            .map(Expression::Set)
+            .parse(input),
+        Some(b'`' | b'~') => parse_quasiquote(input),
+        _ => parse_atom.map(Expression::Atom).parse(input),
+    }
+}
+
+/// Parses a whole program: a sequence of top-level
+/// expressions. Fails if any input is left unconsumed
+pub fn parse_program(
+    input: &str,
+) -> IResult<'_, Vec<Expression>> {
+    all_consuming(terminated(
+        many0(parse_expression),
+        space_or_comment0,
+    ))(input)
+}
+
+/// Parses an expression that must follow, e.g. the value given
+/// to `def`
+pub(super) fn parse_required_expression(
+    input: &str,
+) -> IResult<'_, Expression> {
+    preceded(
+        space_or_comment0,
+        expect(Expected::Expression, parse_expression),
     )(input)
 }
 
-fn parse_list(input: &str) -> IResult<Vec<Expression>> {
-    parse_square_brackets_enclosed(many0(parse_expression))(
-        input,
+/// Parses a pattern that must follow, e.g. the name given to
+/// `def`
+fn parse_required_pattern(input: &str) -> IResult<'_, Pattern> {
+    preceded(
+        space_or_comment0,
+        expect(Expected::Pattern, parse_pattern),
+    )(input)
+}
+
+fn parse_list(input: &str) -> IResult<'_, Vec<Expression>> {
+    parse_square_brackets_enclosed(
+        "list",
+        many0(parse_expression),
+    )(input)
+}
+
+/// Parses the reader syntax of quotes, `'form`, taking `form`
+/// as data. `'()` gives the empty list, which isn't code, and
+/// the keywords of special forms, e.g. `'fn`, give themselves
+fn parse_quote(input: &str) -> IResult<'_, Expression> {
+    let empty = tuple((char('('), space_or_comment0, char(')')))
+        .map(|_| Expression::List(vec![]));
+    let special_form = alt((
+        keyword("if"),
+        keyword("defmacro"),
+        keyword("def"),
+        keyword("fn"),
+        keyword("let"),
+        keyword("match"),
+        keyword("do"),
+    ))
+    .map(|name| {
+        Expression::Atom(Atom::Identifier(SmallString::new(
+            name,
+        )))
+    });
+
+    preceded(
+        char('\''),
+        alt((
+            empty,
+            special_form,
+            parse_required_expression
+                .map(|code| macros::quote(&code)),
+        )),
     )
+    .map(Rc::new)
+    .map(Expression::Quote)
+    .parse(input)
 }
 
-fn parse_quote(input: &str) -> IResult<Vec<Expression>> {
+/// Parses a map literal, e.g. `{:name "x" :age 3}`. Keys
+/// repeated within it keep the last value given to them
+fn parse_map(input: &str) -> IResult<'_, Map> {
+    let entries = many0(tuple((
+        parse_expression,
+        parse_required_expression,
+    )));
+
+    parse_braces_enclosed(
+        "map",
+        char('{'),
+        context("map entries", entries),
+    )
+    .map(|entries| entries.into_iter().collect())
+    .parse(input)
+}
+
+/// Parses a set literal, e.g. `#{1 2 3}`
+fn parse_set(input: &str) -> IResult<'_, Set> {
+    parse_braces_enclosed(
+        "set",
+        tag("#{"),
+        many0(parse_expression),
+    )
+    .map(|items| items.into_iter().collect())
+    .parse(input)
+}
+
+/// Parses the reader syntax of quasiquotes: `` `form ``,
+/// `~form` and `~@form`
+fn parse_quasiquote(input: &str) -> IResult<'_, Expression> {
+    let quoted = |prefix| {
+        preceded(tag(prefix), parse_required_expression)
+            .map(Rc::new)
+    };
+
+    alt((
+        quoted("`").map(Expression::Quasiquote),
+        // Must go before `~`, which it starts with
+        quoted("~@").map(Expression::UnquoteSplicing),
+        quoted("~").map(Expression::Unquote),
+    ))(input)
+}
+
+/// Parses the parameters of a lambda or macro, of the form
+/// `[required* (optional default)* (& rest)?]`
+fn parse_parameters(
+    input: &str,
+) -> IResult<'_, (Parameters, Destructured)> {
+    let parse_optional = preceded(
+        space_or_comment0,
+        parse_parenthesis_enclosed(
+            "optional parameter",
+            tuple((parse_pattern, parse_expression)),
+        ),
+    );
+
+    parse_square_brackets_enclosed(
+        "parameters",
+        tuple((
+            many0(parse_pattern),
+            many0(parse_optional),
+            opt(parse_rest_pattern),
+        )),
+    )
+    .map(|(required, optional, rest)| {
+        Parameters::from_patterns(required, optional, rest)
+    })
+    .parse(input)
+}
+
+/// Parses what a value gets bound to: an identifier, `_`, or a
+/// list or map pattern destructuring it
+fn parse_pattern(input: &str) -> IResult<'_, Pattern> {
     preceded(
-        multispace0,
-        delimited(
-            tag("'("),
-            preceded(multispace0, many0(parse_expression)),
-            context(
-                "closing brackets",
-                cut(preceded(multispace0, char(')'))),
-            ),
+        space_or_comment0,
+        alt((
+            keyword("_").map(|_| Pattern::Wildcard),
+            parse_identifier
+                .map(SmallString::new)
+                .map(Pattern::Identifier),
+            distinct_identifiers(parse_list_pattern),
+            distinct_identifiers(parse_map_pattern),
+            preceded(char('~'), parse_required_expression)
+                .map(Rc::new)
+                .map(Pattern::Unquote),
+        )),
+    )(input)
+}
+
+/// Fails if the pattern parsed binds an identifier more than
+/// once, as `[x x]` would, pointing at its start
+fn distinct_identifiers<'a>(
+    mut parser: impl FnMut(&'a str) -> IResult<'a, Pattern>,
+) -> impl FnMut(&'a str) -> IResult<'a, Pattern> {
+    move |input| {
+        let (rest, pattern) = parser(input)?;
+
+        match pattern.repeated_identifier() {
+            Some(_) => {
+                Err(nom::Err::Failure(ParseError::expected(
+                    input,
+                    Expected::DistinctIdentifiers,
+                )))
+            }
+            None => Ok((rest, pattern)),
+        }
+    }
+}
+
+/// Parses a pattern that may also be a literal. Binding a value
+/// to a lone literal would leave it unused, so literals are only
+/// allowed in `match` clauses and within other patterns
+fn parse_checking_pattern(input: &str) -> IResult<'_, Pattern> {
+    alt((
+        parse_pattern,
+        preceded(
+            space_or_comment0,
+            parse_literal.map(Pattern::Literal),
         ),
+    ))(input)
+}
+
+/// Parses an atom other than an identifier, which is taken as it
+/// is written rather than looked up
+fn parse_literal(input: &str) -> IResult<'_, Expression> {
+    verify(parse_atom, |atom| {
+        !matches!(atom, Atom::Identifier(_))
+    })
+    .map(Expression::Atom)
+    .parse(input)
+}
+
+fn parse_rest_pattern(input: &str) -> IResult<'_, Pattern> {
+    preceded(
+        space_or_comment0,
+        preceded(char('&'), parse_required_pattern),
     )(input)
 }
 
-fn parse_identifier_list(
-    input: &str,
-) -> IResult<Vec<SmallString>> {
-    parse_square_brackets_enclosed(many0(preceded(
-        multispace0,
-        parse_identifier.map(SmallString::new),
-    )))(input)
+fn parse_list_pattern(input: &str) -> IResult<'_, Pattern> {
+    parse_square_brackets_enclosed(
+        "list pattern",
+        tuple((
+            many0(parse_checking_pattern),
+            opt(parse_rest_pattern),
+        )),
+    )
+    .map(|(items, rest)| Pattern::List {
+        items,
+        rest: rest.map(Box::new),
+    })
+    .parse(input)
 }
 
+fn parse_map_pattern(input: &str) -> IResult<'_, Pattern> {
+    // `:keys [x y]` stands for `x :x y :y`
+    let parse_keys = preceded(
+        keyword(":keys"),
+        cut(parse_square_brackets_enclosed(
+            ":keys",
+            many0(preceded(space_or_comment0, parse_identifier)),
+        )),
+    )
+    .map(|identifiers| {
+        identifiers
+            .into_iter()
+            .map(|identifier| {
+                (
+                    Pattern::Identifier(SmallString::new(
+                        identifier,
+                    )),
+                    Expression::Atom(Atom::Symbol(
+                        SmallString::new(identifier),
+                    )),
+                )
+            })
+            .collect()
+    });
+    // Keys are taken as they're written, so they can't be
+    // identifiers
+    let parse_entry = tuple((
+        parse_checking_pattern,
+        preceded(space_or_comment0, parse_literal),
+    ))
+    .map(|entry| vec![entry]);
+
+    parse_braces_enclosed(
+        "map pattern",
+        char('{'),
+        context(
+            "map pattern entries",
+            many0(preceded(
+                space_or_comment0,
+                alt((parse_keys, parse_entry)),
+            )),
+        ),
+    )
+    .map(|entries| {
+        Pattern::Map(entries.into_iter().flatten().collect())
+    })
+    .parse(input)
+}
+
+/// Parses `[inner]`, reporting failures within it as happening
+/// while parsing `label`, e.g. `"list"`
 fn parse_square_brackets_enclosed<'a, T, F>(
+    label: &'static str,
     inner: F,
-) -> impl FnMut(&'a str) -> IResult<T>
+) -> impl FnMut(&'a str) -> IResult<'a, T>
 where
-    F: Parser<&'a str, T, VerboseError<&'a str>>,
+    F: Parser<&'a str, T, ParseError<'a>>,
 {
     preceded(
-        multispace0,
-        delimited(
-            char('['),
-            preceded(multispace0, inner),
-            context(
-                "closing brackets",
-                cut(preceded(multispace0, char(']'))),
+        space_or_comment0,
+        context(
+            label,
+            delimited(
+                char('['),
+                preceded(space_or_comment0, inner),
+                cut(preceded(space_or_comment0, char(']'))),
             ),
         ),
     )
 }
 
-fn parse_lambda(input: &str) -> IResult<Lambda> {
-    fn parse_lambda_inner(input: &str) -> IResult<Lambda> {
-        let (rest, _) = tag("fn ")(input)?;
-        let (rest, arguments) = parse_identifier_list(rest)?;
-        let (rest, body) = parse_expression(rest)?;
+/// Parses `inner` between `opening` and `}`, reporting failures
+/// within them as happening while parsing `label`
+fn parse_braces_enclosed<'a, O, P, T, F>(
+    label: &'static str,
+    opening: P,
+    inner: F,
+) -> impl FnMut(&'a str) -> IResult<'a, T>
+where
+    P: Parser<&'a str, O, ParseError<'a>>,
+    F: Parser<&'a str, T, ParseError<'a>>,
+{
+    preceded(
+        space_or_comment0,
+        context(
+            label,
+            delimited(
+                opening,
+                preceded(space_or_comment0, inner),
+                cut(preceded(space_or_comment0, char('}'))),
+            ),
+        ),
+    )
+}
 
-        let lambda = Lambda { arguments, body };
-        Ok((rest, lambda))
+fn parse_lambda(input: &str) -> IResult<'_, Lambda> {
+    fn parse_lambda_inner(
+        input: &str,
+    ) -> IResult<'_, (WrittenParameters, Expression)> {
+        preceded(
+            keyword("fn"),
+            cut(tuple((parse_required_parameters, parse_body))),
+        )(input)
     }
 
-    parse_parenthesis_enclosed(parse_lambda_inner)(input)
+    let (rest, ((parameters, body), span)) = spanned(
+        parse_parenthesis_enclosed("lambda", parse_lambda_inner),
+    )(input)?;
+
+    Ok((rest, parameters.into_lambda(body, span)))
 }
 
-fn parse_if(input: &str) -> IResult<Expression> {
-    fn parse_if_inner(input: &str) -> IResult<Expression> {
-        let (rest, (condition, if_true, if_false)) =
-            preceded(
-                terminated(tag("if"), multispace1),
-                cut(tuple((
-                    parse_expression,
-                    parse_expression,
-                    opt(parse_expression),
-                ))),
-            )(input)?;
+/// The parameters of a lambda or macro, as they were written
+enum WrittenParameters {
+    Patterns((Parameters, Destructured)),
+    /// Within a quasiquote template, unquoting some or all of
+    /// them
+    Template(Vec<Expression>),
+}
 
-        let expr = match if_false {
-            Some(if_false) => {
-                Expression::IfElse(Box::new(IfElse {
-                    condition,
-                    if_true,
-                    if_false,
-                }))
+impl WrittenParameters {
+    fn into_lambda(
+        self,
+        body: Expression,
+        span: Span,
+    ) -> Lambda {
+        match self {
+            WrittenParameters::Patterns(parameters) => {
+                Lambda::destructuring(parameters, body, span)
+            }
+            WrittenParameters::Template(parameters) => {
+                Lambda::template(parameters, body, span)
+            }
+        }
+    }
+}
+
+/// Parses the parameters of a lambda or macro, which must follow
+fn parse_required_parameters(
+    input: &str,
+) -> IResult<'_, WrittenParameters> {
+    // Parameters that aren't a template are parsed anew as
+    // patterns, which also reports what's wrong with them
+    let parse_template =
+        |input| match parse_template_parameters(input) {
+            Err(nom::Err::Failure(error)) => {
+                Err(nom::Err::Error(error))
             }
-            None => Expression::If(Box::new(If {
-                condition,
-                do_this: if_true,
-            })),
+            result => result,
         };
 
-        Ok((rest, expr))
-    }
+    preceded(
+        space_or_comment0,
+        expect(
+            Expected::Parameters,
+            alt((
+                parse_template.map(WrittenParameters::Template),
+                parse_parameters
+                    .map(WrittenParameters::Patterns),
+            )),
+        ),
+    )(input)
+}
 
-    parse_parenthesis_enclosed(parse_if_inner)(input)
+/// Parses parameters written as part of a quasiquote template,
+/// as the items of the vector they stand for: either `~params`,
+/// which is taken as `[~@params]`, or a vector unquoting some of
+/// its items, e.g. `[x ~@more]`
+fn parse_template_parameters(
+    input: &str,
+) -> IResult<'_, Vec<Expression>> {
+    let parse_item = preceded(
+        space_or_comment0,
+        alt((
+            char('&').map(|_| {
+                Expression::Atom(Atom::Identifier(
+                    SmallString::new("&"),
+                ))
+            }),
+            parse_expression,
+        )),
+    );
+    let is_unquote = |item: &Expression| {
+        matches!(
+            item,
+            Expression::Unquote(_)
+                | Expression::UnquoteSplicing(_)
+        )
+    };
+
+    alt((
+        preceded(char('~'), parse_required_expression).map(
+            |form| {
+                vec![Expression::UnquoteSplicing(Rc::new(form))]
+            },
+        ),
+        verify(
+            parse_square_brackets_enclosed(
+                "parameters",
+                many0(parse_item),
+            ),
+            move |items: &Vec<_>| items.iter().any(is_unquote),
+        ),
+    ))(input)
+}
+
+/// Parses the body of a lambda or macro: one or more
+/// expressions, as if within a `do` block
+fn parse_body(input: &str) -> IResult<'_, Expression> {
+    let (rest, mut body) = preceded(
+        space_or_comment0,
+        expect(Expected::Expression, many1(parse_expression)),
+    )(input)?;
+
+    // A body spliced into a template, as in `(fn [x] ~@body)`,
+    // may stand for many expressions
+    let body = match body.as_slice() {
+        [Expression::UnquoteSplicing(_)] => Expression::Do(body),
+        // Safe unwrap: the slice holds one expression
+        [_] => body.pop().unwrap(),
+        _ => Expression::Do(body),
+    };
+    Ok((rest, body))
 }
 
-fn parse_binding(input: &str) -> IResult<Binding> {
-    fn parse_identifier_and_expr(
+fn parse_do(input: &str) -> IResult<'_, Expression> {
+    parse_parenthesis_enclosed(
+        "do block",
+        preceded(keyword("do"), many0(parse_expression)),
+    )
+    .map(Expression::Do)
+    .parse(input)
+}
+
+fn parse_if(input: &str) -> IResult<'_, Expression> {
+    fn parse_if_inner(
         input: &str,
-    ) -> IResult<(&str, Expression)> {
-        let (rest, _) = tag("def ")(input)?;
-        let (rest, identifier) = parse_identifier(rest)?;
-        let (rest, expression) = parse_expression(rest)?;
+    ) -> IResult<'_, (Expression, Expression, Option<Expression>)>
+    {
+        preceded(
+            keyword("if"),
+            cut(tuple((
+                parse_required_expression,
+                parse_required_expression,
+                opt(parse_expression),
+            ))),
+        )(input)
+    }
+
+    let (rest, ((condition, if_true, if_false), span)) =
+        spanned(parse_parenthesis_enclosed(
+            "if",
+            parse_if_inner,
+        ))(input)?;
 
-        Ok((rest, (identifier, expression)))
+    let expr = match if_false {
+        Some(if_false) => Expression::IfElse(Box::new(IfElse {
+            condition,
+            if_true,
+            if_false,
+            span,
+        })),
+        None => Expression::If(Box::new(If {
+            condition,
+            do_this: if_true,
+            span,
+        })),
+    };
+
+    Ok((rest, expr))
+}
+
+fn parse_binding(input: &str) -> IResult<'_, Binding> {
+    fn parse_pattern_and_expr(
+        input: &str,
+    ) -> IResult<'_, (Pattern, Expression)> {
+        preceded(
+            keyword("def"),
+            cut(tuple((
+                parse_required_pattern,
+                parse_required_expression,
+            ))),
+        )(input)
     }
-    let (rest, (identifier, expression)) =
-        parse_parenthesis_enclosed(parse_identifier_and_expr)(
-            input,
-        )?;
+    let (rest, ((pattern, expression), span)) =
+        spanned(parse_parenthesis_enclosed(
+            "definition",
+            parse_pattern_and_expr,
+        ))(input)?;
     let binding = Binding {
-        identifier: SmallString::new(identifier),
+        pattern,
         expression,
+        span,
     };
     Ok((rest, binding))
 }
 
+fn parse_defmacro(input: &str) -> IResult<'_, Binding> {
+    type Definition = (Pattern, WrittenParameters, Expression);
+
+    fn parse_defmacro_inner(
+        input: &str,
+    ) -> IResult<'_, Definition> {
+        // Macros defined within templates may be named by
+        // unquoting their name
+        let parse_name = alt((
+            parse_identifier
+                .map(SmallString::new)
+                .map(Pattern::Identifier),
+            preceded(char('~'), parse_required_expression)
+                .map(Rc::new)
+                .map(Pattern::Unquote),
+        ));
+
+        preceded(
+            keyword("defmacro"),
+            cut(tuple((
+                preceded(
+                    space_or_comment0,
+                    expect(Expected::Identifier, parse_name),
+                ),
+                parse_required_parameters,
+                parse_body,
+            ))),
+        )(input)
+    }
+
+    let (rest, ((pattern, parameters, body), span)) =
+        spanned(parse_parenthesis_enclosed(
+            "macro definition",
+            parse_defmacro_inner,
+        ))(input)?;
+
+    let lambda = parameters.into_lambda(body, span);
+    let binding = Binding {
+        pattern,
+        expression: Expression::Lambda(Box::new(lambda)),
+        span,
+    };
+    Ok((rest, binding))
+}
+
+fn parse_let(input: &str) -> IResult<'_, Let> {
+    type Bindings = Vec<(Pattern, Expression)>;
+
+    fn parse_let_inner(
+        input: &str,
+    ) -> IResult<'_, (Bindings, Expression)> {
+        preceded(
+            keyword("let"),
+            cut(tuple((
+                preceded(
+                    space_or_comment0,
+                    parse_square_brackets_enclosed(
+                        "let bindings",
+                        many0(tuple((
+                            parse_pattern,
+                            parse_required_expression,
+                        ))),
+                    ),
+                ),
+                parse_required_expression,
+            ))),
+        )(input)
+    }
+
+    let (rest, ((bindings, body), span)) = spanned(
+        parse_parenthesis_enclosed("let", parse_let_inner),
+    )(input)?;
+
+    Ok((
+        rest,
+        Let {
+            bindings,
+            body,
+            span,
+        },
+    ))
+}
+
+fn parse_match(input: &str) -> IResult<'_, Match> {
+    fn parse_clause(input: &str) -> IResult<'_, MatchClause> {
+        tuple((
+            parse_checking_pattern,
+            opt(preceded(
+                preceded(space_or_comment0, keyword(":when")),
+                parse_required_expression,
+            )),
+            parse_required_expression,
+        ))
+        .map(|(pattern, guard, branch)| MatchClause {
+            pattern,
+            guard,
+            branch,
+        })
+        .parse(input)
+    }
+
+    fn parse_match_inner(
+        input: &str,
+    ) -> IResult<'_, (Expression, Vec<MatchClause>)> {
+        preceded(
+            keyword("match"),
+            cut(tuple((
+                parse_required_expression,
+                context("match clauses", many0(parse_clause)),
+            ))),
+        )(input)
+    }
+
+    let (rest, ((expression, clauses), span)) = spanned(
+        parse_parenthesis_enclosed("match", parse_match_inner),
+    )(input)?;
+
+    Ok((
+        rest,
+        Match {
+            expression,
+            clauses,
+            span,
+        },
+    ))
+}
+
+/// Runs the given parser, also returning the span of the input
+/// it consumed
+fn spanned<'a, T, F>(
+    mut parser: F,
+) -> impl FnMut(&'a str) -> IResult<'a, (T, Span)>
+where
+    F: Parser<&'a str, T, ParseError<'a>>,
+{
+    move |input| {
+        let (rest, output) = parser.parse(input)?;
+
+        Ok((rest, (output, Span::from_remaining(input, rest))))
+    }
+}
+
+/// Parses `(inner)`, reporting failures within it as happening
+/// while parsing `label`, e.g. `"application"`
 // Based on https://github.com/Geal/nom/blob/761ab0a24fccb4c560367b583b608fbae5f31647/examples/s_expression.rs#L155
 fn parse_parenthesis_enclosed<'a, T, F>(
+    label: &'static str,
     inner: F,
-) -> impl FnMut(&'a str) -> IResult<T>
+) -> impl FnMut(&'a str) -> IResult<'a, T>
 where
-    F: Parser<&'a str, T, VerboseError<&'a str>>,
+    F: Parser<&'a str, T, ParseError<'a>>,
 {
-    delimited(
-        char('('),
-        preceded(multispace0, inner),
-        context(
-            "closing parenthesis",
-            cut(preceded(multispace0, char(')'))),
+    context(
+        label,
+        delimited(
+            char('('),
+            preceded(space_or_comment0, inner),
+            cut(preceded(space_or_comment0, char(')'))),
         ),
     )
 }
 
-fn parse_application(input: &str) -> IResult<Application> {
+fn parse_application(input: &str) -> IResult<'_, Application> {
     #[inline]
     fn parse_name_and_args(
         input: &str,
-    ) -> IResult<(FnIdentifier, Vec<Expression>)> {
-        let (rest, name) = parse_fn_identifier(input)?;
+    ) -> IResult<'_, (FnIdentifier, Vec<Expression>)> {
+        let (rest, name) = alt((
+            parse_fn_identifier,
+            parse_expression
+                .map(Rc::new)
+                .map(FnIdentifier::Expression),
+        ))(input)?;
 
         let (rest, args) = many0(preceded(
-            multispace0,
+            space_or_comment0,
             parse_expression,
         ))(rest)?;
 
         Ok((rest, (name, args)))
     }
 
-    let (rest, (name, arguments)) =
-        parse_parenthesis_enclosed(parse_name_and_args)(input)?;
+    let (rest, ((name, arguments), span)) =
+        spanned(parse_parenthesis_enclosed(
+            "application",
+            parse_name_and_args,
+        ))(input)?;
 
-    Ok((rest, Application { name, arguments }))
+    Ok((
+        rest,
+        Application {
+            name,
+            arguments,
+            span,
+        },
+    ))
 }
 
-// fn parse_cond(input: &str) -> IResult<Vec<Expression>> {
+// fn parse_cond(input: &str) -> IResult<'_, Vec<Expression>> {
 //     fn parse_cond_inner(
 //         input: &str,
-//     ) -> IResult<Vec<Expression>> {
+//     ) -> IResult<'_, Vec<Expression>> {
 //         let (rest, _) = tag("cond")(input)?;
 
 //         many0(parse_expression)(rest)
@@ -203,20 +802,26 @@ fn parse_application(input: &str) -> IResult<Application> {
 
 #[cfg(test)]
 mod tests {
+    use std::rc::Rc;
+
     use super::{
-        parse_application, parse_binding, parse_identifier_list,
-        parse_lambda, Application,
+        parse_application, parse_binding,
+        parse_checking_pattern, parse_lambda, parse_let,
+        parse_parameters, parse_pattern, Application,
     };
     use crate::{
         expression::{
             elements::{
                 Atom, Binding, FnIdentifier, If, IfElse, Lambda,
+                Let, Parameters, Pattern,
             },
             BuiltIn, Expression,
         },
         parse_expression,
-        parser::expression::{parse_if, parse_list},
-        SmallString,
+        parser::expression::{
+            parse_if, parse_list, parse_program,
+        },
+        SmallString, Span,
     };
 
     #[test]
@@ -229,7 +834,8 @@ mod tests {
                     name: FnIdentifier::Other(SmallString::new(
                         "nothing"
                     )),
-                    arguments: vec![]
+                    arguments: vec![],
+                    span: Span::default(),
                 }
             ))
         );
@@ -242,7 +848,8 @@ mod tests {
                     name: FnIdentifier::Other(SmallString::new(
                         "exit"
                     )),
-                    arguments: vec![]
+                    arguments: vec![],
+                    span: Span::default(),
                 }
             ))
         );
@@ -254,10 +861,49 @@ mod tests {
                 Application {
                     name: FnIdentifier::BuiltIn(BuiltIn::Plus),
                     arguments: vec![
-                        Expression::Atom(Atom::Number(5.0)),
-                        Expression::Atom(Atom::Number(2.0)),
-                        Expression::Atom(Atom::Number(3.0))
-                    ]
+                        Expression::Atom(Atom::Integer(5)),
+                        Expression::Atom(Atom::Integer(2)),
+                        Expression::Atom(Atom::Integer(3))
+                    ],
+                    span: Span::default(),
+                }
+            ))
+        );
+
+        assert_eq!(
+            parse_application("(:name person)"),
+            Ok((
+                "",
+                Application {
+                    name: FnIdentifier::Symbol(
+                        SmallString::new("name")
+                    ),
+                    arguments: vec![Expression::Atom(
+                        Atom::Identifier(SmallString::new(
+                            "person"
+                        ))
+                    )],
+                    span: Span::default(),
+                }
+            ))
+        );
+
+        assert_eq!(
+            parse_application("((f) 2)"),
+            Ok((
+                "",
+                Application {
+                    name: FnIdentifier::Expression(Rc::new(
+                        Expression::Application(Application {
+                            name: FnIdentifier::Other(
+                                SmallString::new("f")
+                            ),
+                            arguments: vec![],
+                            span: Span::default(),
+                        })
+                    )),
+                    arguments: vec![2_i64.into()],
+                    span: Span::default(),
                 }
             ))
         );
@@ -274,16 +920,18 @@ mod tests {
                                 BuiltIn::Minus
                             ),
                             arguments: vec![
-                                Expression::Atom(Atom::Number(
-                                    2.0
+                                Expression::Atom(Atom::Integer(
+                                    2
                                 )),
-                                Expression::Atom(Atom::Number(
-                                    3.0
+                                Expression::Atom(Atom::Integer(
+                                    3
                                 ))
-                            ]
+                            ],
+                            span: Span::default(),
                         },),
-                        Expression::Atom(Atom::Number(5.0))
-                    ]
+                        Expression::Atom(Atom::Integer(5))
+                    ],
+                    span: Span::default(),
                 }
             ))
         );
@@ -299,7 +947,8 @@ mod tests {
                     condition: Expression::Atom(Atom::Boolean(
                         true
                     )),
-                    do_this: Expression::Atom(Atom::Number(2.0)),
+                    do_this: Expression::Atom(Atom::Integer(2)),
+                    span: Span::default(),
                 }))
             ))
         );
@@ -312,10 +961,9 @@ mod tests {
                     condition: Expression::Atom(Atom::Boolean(
                         true
                     )),
-                    if_true: Expression::Atom(Atom::Number(2.0)),
-                    if_false: Expression::Atom(Atom::Number(
-                        3.0
-                    ))
+                    if_true: Expression::Atom(Atom::Integer(2)),
+                    if_false: Expression::Atom(Atom::Integer(3)),
+                    span: Span::default(),
                 }))
             ))
         );
@@ -333,8 +981,13 @@ mod tests {
             Ok((
                 "",
                 Lambda {
-                    arguments: vec![],
-                    body: 2.0.into()
+                    parameters: Parameters::default(),
+                    body: Rc::new(2_i64.into()),
+                    captured: None,
+                    span: Span::default(),
+                    template: None,
+                    #[cfg(feature = "bytecode")]
+                    compiled: None,
                 }
             ))
         );
@@ -344,20 +997,31 @@ mod tests {
             Ok((
                 "",
                 Lambda {
-                    arguments: vec![SmallString::new("x")],
-                    body: Expression::Application(Application {
-                        name: FnIdentifier::BuiltIn(
-                            BuiltIn::Plus
-                        ),
-                        arguments: vec![
-                            Expression::Atom(
-                                Atom::Identifier(
-                                    SmallString::new("x")
-                                )
-                            );
-                            2
-                        ]
-                    })
+                    parameters: Parameters {
+                        required: vec![SmallString::new("x")],
+                        ..Parameters::default()
+                    },
+                    body: Rc::new(Expression::Application(
+                        Application {
+                            name: FnIdentifier::BuiltIn(
+                                BuiltIn::Plus
+                            ),
+                            arguments: vec![
+                                Expression::Atom(
+                                    Atom::Identifier(
+                                        SmallString::new("x")
+                                    )
+                                );
+                                2
+                            ],
+                            span: Span::default(),
+                        }
+                    )),
+                    captured: None,
+                    span: Span::default(),
+                    template: None,
+                    #[cfg(feature = "bytecode")]
+                    compiled: None,
                 }
             ))
         );
@@ -370,10 +1034,13 @@ mod tests {
             Ok((
                 "",
                 Binding {
-                    identifier: SmallString::new("two"),
-                    expression: Expression::Atom(Atom::Number(
-                        2.0
-                    ))
+                    pattern: Pattern::Identifier(
+                        SmallString::new("two")
+                    ),
+                    expression: Expression::Atom(Atom::Integer(
+                        2
+                    )),
+                    span: Span::default(),
                 }
             ))
         );
@@ -383,22 +1050,26 @@ mod tests {
             Ok((
                 "",
                 Binding {
-                    identifier: SmallString::new("five"),
+                    pattern: Pattern::Identifier(
+                        SmallString::new("five")
+                    ),
                     expression: Expression::Application(
                         Application {
                             name: FnIdentifier::BuiltIn(
                                 BuiltIn::Plus
                             ),
                             arguments: vec![
-                                Expression::Atom(Atom::Number(
-                                    2.0
+                                Expression::Atom(Atom::Integer(
+                                    2
                                 )),
-                                Expression::Atom(Atom::Number(
-                                    3.0
+                                Expression::Atom(Atom::Integer(
+                                    3
                                 ))
-                            ]
+                            ],
+                            span: Span::default(),
                         }
-                    )
+                    ),
+                    span: Span::default(),
                 }
             ))
         );
@@ -407,11 +1078,60 @@ mod tests {
         assert!(parse_binding("(def x)").is_err());
     }
 
+    #[test]
+    fn parses_lets() {
+        assert_eq!(
+            parse_let("(let [a 1 b a] b)"),
+            Ok((
+                "",
+                Let {
+                    bindings: vec![
+                        (
+                            Pattern::Identifier(
+                                SmallString::new("a")
+                            ),
+                            1_i64.into(),
+                        ),
+                        (
+                            Pattern::Identifier(
+                                SmallString::new("b")
+                            ),
+                            Expression::Atom(Atom::Identifier(
+                                SmallString::new("a")
+                            ))
+                        ),
+                    ],
+                    body: Expression::Atom(Atom::Identifier(
+                        SmallString::new("b")
+                    )),
+                    span: Span::default(),
+                }
+            ))
+        );
+
+        assert_eq!(
+            parse_let("(let [] 2)"),
+            Ok((
+                "",
+                Let {
+                    bindings: vec![],
+                    body: 2_i64.into(),
+                    span: Span::default(),
+                }
+            ))
+        );
+
+        assert!(parse_let("(let)").is_err());
+        assert!(parse_let("(let [a] a)").is_err());
+        assert!(parse_let("(let [2 2] 2)").is_err());
+        assert!(parse_let("(let [a 2])").is_err());
+    }
+
     #[test]
     fn parses_expressions() {
         assert_eq!(
             parse_expression("5"),
-            Ok(("", Expression::Atom(Atom::Number(5.0))))
+            Ok(("", Expression::Atom(Atom::Integer(5))))
         );
 
         assert_eq!(
@@ -423,9 +1143,10 @@ mod tests {
                         "add"
                     )),
                     arguments: vec![
-                        Expression::Atom(Atom::Number(2.0)),
-                        Expression::Atom(Atom::Number(3.0))
-                    ]
+                        Expression::Atom(Atom::Integer(2)),
+                        Expression::Atom(Atom::Integer(3))
+                    ],
+                    span: Span::default(),
                 })
             ))
         );
@@ -438,10 +1159,9 @@ mod tests {
                     condition: Expression::Atom(Atom::Boolean(
                         false
                     )),
-                    if_true: Expression::Atom(Atom::Number(5.0)),
-                    if_false: Expression::Atom(Atom::Number(
-                        4.0
-                    ))
+                    if_true: Expression::Atom(Atom::Integer(5)),
+                    if_false: Expression::Atom(Atom::Integer(4)),
+                    span: Span::default(),
                 }))
             ))
         );
@@ -452,40 +1172,175 @@ mod tests {
         .is_ok());
     }
 
+    #[test]
+    fn parses_programs() {
+        assert_eq!(parse_program(""), Ok(("", vec![])));
+        assert_eq!(parse_program("  \n "), Ok(("", vec![])));
+
+        assert_eq!(
+            parse_program("(def x 1)\n(def y 2)\n x "),
+            Ok((
+                "",
+                vec![
+                    Expression::Binding(Box::new(Binding {
+                        pattern: Pattern::Identifier(
+                            SmallString::new("x")
+                        ),
+                        expression: 1_i64.into(),
+                        span: Span::default(),
+                    })),
+                    Expression::Binding(Box::new(Binding {
+                        pattern: Pattern::Identifier(
+                            SmallString::new("y")
+                        ),
+                        expression: 2_i64.into(),
+                        span: Span::default(),
+                    })),
+                    Expression::Atom(Atom::Identifier(
+                        SmallString::new("x")
+                    )),
+                ]
+            ))
+        );
+
+        // Multi-line forms
+        assert_eq!(
+            parse_program("(+ 1\n   2)\n:ok").unwrap().1.len(),
+            2
+        );
+
+        // Unconsumed input
+        assert!(parse_program("(def x 1) )").is_err());
+        assert!(parse_program("(def x 1) (+ 2").is_err());
+    }
+
+    #[test]
+    fn parses_comments() {
+        assert_eq!(
+            parse_expression("[1 ; one\n #| two |# 2 #_3]"),
+            parse_expression("[1 2]")
+        );
+        assert_eq!(
+            parse_expression("'( #_ignored true ; why\n false)"),
+            parse_expression("'(true false)")
+        );
+        assert_eq!(
+            parse_parameters("[x #| y |# z ; w\n]"),
+            parse_parameters("[x z]")
+        );
+        assert_eq!(
+            parse_expression(
+                "(fn ; arguments\n [x #_y] #| body |# (+ x #_x 1))"
+            ),
+            parse_expression("(fn [x] (+ x 1))")
+        );
+        assert_eq!(
+            parse_expression(
+                "(let [a 1 ; first\n #_#_b 2 c 3] #_a c)"
+            ),
+            parse_expression("(let [a 1 c 3] c)")
+        );
+        assert_eq!(
+            parse_expression("(def #| name |# x ; value\n 2)"),
+            parse_expression("(def x 2)")
+        );
+        assert_eq!(
+            parse_expression("(if #_false true 1 2)"),
+            parse_expression("(if true 1 2)")
+        );
+
+        assert_eq!(
+            parse_program(
+                "; Header\n(def x 1) #| (def y 2) |#\n#_(def z 3)\nx ; end"
+            )
+            .unwrap()
+            .1
+            .len(),
+            2
+        );
+
+        assert!(parse_program("#| unterminated").is_err());
+        assert!(parse_expression("[1 #_]").is_err());
+    }
+
     #[test]
     fn parses_argument_lists() {
+        let required = |names: &[&str]| {
+            let parameters = Parameters {
+                required: names
+                    .iter()
+                    .copied()
+                    .map(SmallString::new)
+                    .collect(),
+                ..Parameters::default()
+            };
+            (parameters, vec![])
+        };
+
+        assert_eq!(
+            parse_parameters("[] "),
+            Ok((" ", required(&[])))
+        );
         assert_eq!(
-            parse_identifier_list("[] "),
-            Ok((" ", vec![]))
+            parse_parameters("[ ]"),
+            Ok(("", required(&[])))
         );
         assert_eq!(
-            parse_identifier_list("[ ]"),
-            Ok(("", vec![]))
+            parse_parameters("[x y]"),
+            Ok(("", required(&["x", "y"])))
         );
         assert_eq!(
-            parse_identifier_list("[x y]"),
+            parse_parameters(" [ x y z w e f     ] "),
+            Ok((" ", required(&["x", "y", "z", "w", "e", "f"])))
+        );
+
+        // Optional and rest parameters
+        assert_eq!(
+            parse_parameters("[x (y 2) (z y) & more]"),
             Ok((
                 "",
-                vec![
-                    SmallString::new("x"),
-                    SmallString::new("y")
-                ]
+                (
+                    Parameters {
+                        required: vec![SmallString::new("x")],
+                        optional: vec![
+                            (
+                                SmallString::new("y"),
+                                2_i64.into()
+                            ),
+                            (
+                                SmallString::new("z"),
+                                Expression::Atom(
+                                    Atom::Identifier(
+                                        SmallString::new("y")
+                                    )
+                                )
+                            ),
+                        ],
+                        rest: Some(SmallString::new("more")),
+                    },
+                    vec![]
+                )
             ))
         );
         assert_eq!(
-            parse_identifier_list(" [ x y z w e f     ] "),
+            parse_parameters("[& xs]"),
             Ok((
-                " ",
-                vec![
-                    SmallString::new("x"),
-                    SmallString::new("y"),
-                    SmallString::new("z"),
-                    SmallString::new("w"),
-                    SmallString::new("e"),
-                    SmallString::new("f"),
-                ]
+                "",
+                (
+                    Parameters {
+                        rest: Some(SmallString::new("xs")),
+                        ..Parameters::default()
+                    },
+                    vec![]
+                )
             ))
         );
+        // Required parameters come first, and the rest parameter
+        // last
+        assert!(parse_parameters("[(x 1) y]").is_err());
+        assert!(parse_parameters("[& xs y]").is_err());
+        assert!(parse_parameters("[x &]").is_err());
+        assert!(parse_parameters("[(x)]").is_err());
     }
 
     #[test]
@@ -550,28 +1405,41 @@ mod tests {
 
     #[test]
     fn parses_quotes() {
+        let quote = |data| Expression::Quote(Rc::new(data));
+        let boolean =
+            |boolean| Expression::Atom(Atom::Boolean(boolean));
+        let identifier = |name: &str| {
+            Expression::Atom(Atom::Identifier(SmallString::new(
+                name,
+            )))
+        };
+        let list =
+            Expression::Atom(Atom::BuiltIn(BuiltIn::List));
+
         assert_eq!(
             parse_expression("'(true false)"),
             Ok((
                 "",
-                Expression::List(vec![
-                    Expression::Atom(Atom::Boolean(true)),
-                    Expression::Atom(Atom::Boolean(false)),
-                ])
+                quote(Expression::List(vec![
+                    boolean(true),
+                    boolean(false),
+                ]))
             ))
         );
 
+        // List literals within are applications of `list`
         assert_eq!(
             parse_expression("'(true [true false])"),
             Ok((
                 "",
-                Expression::List(vec![
-                    Expression::Atom(Atom::Boolean(true)),
+                quote(Expression::List(vec![
+                    boolean(true),
                     Expression::List(vec![
-                        Expression::Atom(Atom::Boolean(true)),
-                        Expression::Atom(Atom::Boolean(false)),
+                        list.clone(),
+                        boolean(true),
+                        boolean(false),
                     ]),
-                ])
+                ]))
             ))
         );
 
@@ -579,21 +1447,311 @@ mod tests {
             parse_expression("'(true [true '(true false)])"),
             Ok((
                 "",
-                Expression::List(vec![
-                    Expression::Atom(Atom::Boolean(true)),
+                quote(Expression::List(vec![
+                    boolean(true),
                     Expression::List(vec![
-                        Expression::Atom(Atom::Boolean(true)),
+                        list,
+                        boolean(true),
                         Expression::List(vec![
-                            Expression::Atom(Atom::Boolean(
-                                true
-                            )),
-                            Expression::Atom(Atom::Boolean(
-                                false
-                            )),
+                            identifier("quote"),
+                            Expression::List(vec![
+                                boolean(true),
+                                boolean(false),
+                            ]),
                         ]),
                     ]),
+                ]))
+            ))
+        );
+
+        assert_eq!(
+            parse_expression("'x"),
+            Ok(("", quote(identifier("x"))))
+        );
+        assert_eq!(
+            parse_expression("'(if x (+ 1 2))"),
+            Ok((
+                "",
+                quote(Expression::List(vec![
+                    identifier("if"),
+                    identifier("x"),
+                    Expression::List(vec![
+                        identifier("+"),
+                        Expression::Atom(Atom::Integer(1)),
+                        Expression::Atom(Atom::Integer(2)),
+                    ]),
+                ]))
+            ))
+        );
+        assert_eq!(
+            parse_expression("'( )"),
+            Ok(("", quote(Expression::List(vec![]))))
+        );
+    }
+
+    #[test]
+    fn parses_maps_and_sets() {
+        let symbol = |name: &str| {
+            Expression::Atom(Atom::Symbol(SmallString::new(
+                name,
+            )))
+        };
+
+        let Ok(("", Expression::Map(map))) =
+            parse_expression("{:a 1 ; one\n :b [2]}")
+        else {
+            panic!("expected a map");
+        };
+        assert_eq!(
+            map.iter().collect::<Vec<_>>(),
+            [
+                (&symbol("a"), &1_i64.into()),
+                (
+                    &symbol("b"),
+                    &Expression::List(vec![2_i64.into()])
+                ),
+            ]
+        );
+
+        let Ok(("", Expression::Set(set))) =
+            parse_expression("#{:a :b :a}")
+        else {
+            panic!("expected a set");
+        };
+        assert_eq!(
+            set.iter().collect::<Vec<_>>(),
+            [&symbol("a"), &symbol("b")]
+        );
+
+        assert!(parse_expression("{}").is_ok());
+        assert!(parse_expression("#{ }").is_ok());
+        assert!(parse_expression("{:a 1 :b}").is_err());
+        assert!(parse_expression("{:a 1").is_err());
+        assert!(parse_expression("#{1 2]").is_err());
+    }
+
+    #[test]
+    fn parses_macros() {
+        let identifier = |name: &str| {
+            Expression::Atom(Atom::Identifier(SmallString::new(
+                name,
+            )))
+        };
+
+        let Ok(("", Expression::DefMacro(def_macro))) =
+            parse_expression("(defmacro id [x] x)")
+        else {
+            panic!("expected a macro definition");
+        };
+        assert_eq!(
+            def_macro.pattern,
+            Pattern::Identifier(SmallString::new("id"))
+        );
+        let Expression::Lambda(lambda) = def_macro.expression
+        else {
+            panic!("expected a lambda");
+        };
+        assert_eq!(
+            lambda.parameters.required,
+            [SmallString::new("x")]
+        );
+        assert_eq!(*lambda.body, identifier("x"));
+
+        assert_eq!(
+            parse_expression("`[~a ~@b]"),
+            Ok((
+                "",
+                Expression::Quasiquote(Rc::new(
+                    Expression::List(vec![
+                        Expression::Unquote(Rc::new(
+                            identifier("a")
+                        )),
+                        Expression::UnquoteSplicing(Rc::new(
+                            identifier("b")
+                        )),
+                    ])
+                ))
+            ))
+        );
+
+        assert!(parse_expression("(defmacro [x] x)").is_err());
+        let Ok(("", Expression::DefMacro(def_macro))) =
+            parse_expression("(defmacro m [x] (f x) x)")
+        else {
+            panic!("expected a macro definition");
+        };
+        let Expression::Lambda(lambda) = def_macro.expression
+        else {
+            panic!("expected a lambda");
+        };
+        assert!(matches!(*lambda.body, Expression::Do(_)));
+        assert!(parse_expression("`").is_err());
+    }
+
+    #[test]
+    fn parses_do_blocks() {
+        let identifier = |name: &str| {
+            Expression::Atom(Atom::Identifier(SmallString::new(
+                name,
+            )))
+        };
+
+        assert_eq!(
+            parse_expression("(do)"),
+            Ok(("", Expression::Do(vec![])))
+        );
+        assert_eq!(
+            parse_expression("(do a ; first\n b)"),
+            Ok((
+                "",
+                Expression::Do(vec![
+                    identifier("a"),
+                    identifier("b")
                 ])
             ))
         );
+        // `do` is reserved, but may start longer identifiers
+        assert!(parse_expression("(def do 1)").is_err());
+        assert_eq!(
+            parse_expression("done"),
+            Ok(("", identifier("done")))
+        );
+
+        // Lambda bodies with many expressions are `do` blocks
+        let Ok(("", Expression::Lambda(lambda))) =
+            parse_expression("(fn [x] (f x) x)")
+        else {
+            panic!("expected a lambda");
+        };
+        assert_eq!(lambda.body.to_string(), "(do (f x) x)");
+        let Ok(("", Expression::Lambda(lambda))) =
+            parse_expression("(fn [x] x)")
+        else {
+            panic!("expected a lambda");
+        };
+        assert_eq!(*lambda.body, identifier("x"));
+        assert!(parse_expression("(fn [x])").is_err());
+    }
+
+    #[test]
+    fn parses_patterns() {
+        let identifier = |name: &str| {
+            Pattern::Identifier(SmallString::new(name))
+        };
+        let symbol = |name: &str| {
+            Expression::Atom(Atom::Symbol(SmallString::new(
+                name,
+            )))
+        };
+
+        assert_eq!(
+            parse_pattern("[x [y] & more]"),
+            Ok((
+                "",
+                Pattern::List {
+                    items: vec![
+                        identifier("x"),
+                        Pattern::List {
+                            items: vec![identifier("y")],
+                            rest: None
+                        }
+                    ],
+                    rest: Some(Box::new(identifier("more")))
+                }
+            ))
+        );
+        assert_eq!(
+            parse_pattern("{:keys [a b] [x y] :pos}"),
+            Ok((
+                "",
+                Pattern::Map(vec![
+                    (identifier("a"), symbol("a")),
+                    (identifier("b"), symbol("b")),
+                    (
+                        Pattern::List {
+                            items: vec![
+                                identifier("x"),
+                                identifier("y")
+                            ],
+                            rest: None
+                        },
+                        symbol("pos")
+                    ),
+                ])
+            ))
+        );
+        // Keys are literals
+        assert!(parse_pattern("{x y}").is_err());
+        assert!(parse_pattern("[x &]").is_err());
+        assert_eq!(
+            parse_checking_pattern("[_ :ok \"s\" 1]"),
+            Ok((
+                "",
+                Pattern::List {
+                    items: vec![
+                        Pattern::Wildcard,
+                        Pattern::Literal(symbol("ok")),
+                        Pattern::Literal(Expression::Atom(
+                            Atom::String(SmallString::new("s"))
+                        )),
+                        Pattern::Literal(Expression::Atom(
+                            Atom::Integer(1)
+                        )),
+                    ],
+                    rest: None
+                }
+            ))
+        );
+        assert_eq!(
+            parse_pattern("_x"),
+            Ok(("", identifier("_x")))
+        );
+        assert_eq!(
+            parse_checking_pattern(":ok"),
+            Ok(("", Pattern::Literal(symbol("ok"))))
+        );
+        assert!(parse_pattern(":ok").is_err());
+
+        // Parameters written as patterns are destructured at the
+        // start of the body
+        let Ok(("", lambda)) = parse_lambda("(fn [[x y] z] x)")
+        else {
+            panic!("expected a lambda");
+        };
+        assert_eq!(
+            lambda.parameters.required,
+            [SmallString::new("%0"), SmallString::new("z")]
+        );
+        assert_eq!(
+            lambda.body.to_string(),
+            "(let [[x y] %0] x)"
+        );
+    }
+
+    #[test]
+    fn parses_match_expressions() {
+        let (rest, expression) = parse_expression(
+            "(match x [a & _] :when (> a 1) a :ok 1 _ 2)",
+        )
+        .unwrap();
+        assert_eq!(rest, "");
+        let Expression::Match(match_expr) = expression else {
+            panic!("expected a match expression");
+        };
+        assert_eq!(match_expr.clauses.len(), 3);
+        assert!(match_expr.clauses[0].guard.is_some());
+        assert_eq!(
+            match_expr.clauses[2].pattern,
+            Pattern::Wildcard
+        );
+        assert_eq!(
+            Expression::Match(match_expr).to_string(),
+            "(match x [a & _] :when (> a 1) a :ok 1 _ 2)"
+        );
+
+        // Every pattern needs a branch
+        assert!(parse_expression("(match x 1)").is_err());
+        assert!(parse_expression("(match x 1 :when)").is_err());
+        assert!(parse_expression("(def match 1)").is_err());
     }
 }
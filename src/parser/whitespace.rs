@@ -0,0 +1,152 @@
+use nom::{
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::{char, multispace0, not_line_ending},
+    combinator::recognize,
+    error::context,
+    sequence::preceded,
+};
+
+use super::{
+    expression::parse_required_expression, IResult, ParseError,
+};
+use crate::Expected;
+
+/// Skips any amount of whitespace, comments and discarded
+/// forms. Used wherever plain whitespace is accepted
+pub fn space_or_comment0(input: &str) -> IResult<'_, ()> {
+    let (mut rest, _) = multispace0(input)?;
+
+    // This runs between every two tokens, so comments are only
+    // looked for where one may start
+    while rest.starts_with([';', '#']) {
+        match parse_comment(rest) {
+            Ok((after, _)) => (rest, _) = multispace0(after)?,
+            // Something else starting with `#`, such as a set
+            Err(nom::Err::Error(_)) => break,
+            Err(error) => return Err(error),
+        }
+    }
+
+    Ok((rest, ()))
+}
+
+fn parse_comment(input: &str) -> IResult<'_, &str> {
+    alt((
+        parse_line_comment,
+        parse_block_comment,
+        parse_discarded_form,
+    ))(input)
+}
+
+/// Parses a comment spanning from `;` to the end of the line
+fn parse_line_comment(input: &str) -> IResult<'_, &str> {
+    recognize(preceded(char(';'), not_line_ending))(input)
+}
+
+/// Parses a `#| ... |#` comment. Block comments may be nested,
+/// so that commenting out code that contains them works
+fn parse_block_comment(input: &str) -> IResult<'_, &str> {
+    fn parse_block_comment_inner(
+        input: &str,
+    ) -> IResult<'_, &str> {
+        let (mut rest, _) = tag("#|")(input)?;
+        let mut depth = 1_usize;
+
+        while depth > 0 {
+            if let Some(after) = rest.strip_prefix("|#") {
+                depth -= 1;
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix("#|") {
+                depth += 1;
+                rest = after;
+            } else {
+                let mut chars = rest.chars();
+                if chars.next().is_none() {
+                    return Err(nom::Err::Failure(
+                        ParseError::expected(
+                            rest,
+                            Expected::EndOfComment,
+                        ),
+                    ));
+                }
+                rest = chars.as_str();
+            }
+        }
+
+        let consumed = &input[..input.len() - rest.len()];
+        Ok((rest, consumed))
+    }
+
+    context("block comment", parse_block_comment_inner)(input)
+}
+
+/// Parses the `#_` reader macro, which ignores the form that
+/// follows it
+fn parse_discarded_form(input: &str) -> IResult<'_, &str> {
+    context(
+        "discarded form",
+        recognize(preceded(
+            tag("#_"),
+            parse_required_expression,
+        )),
+    )(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        parse_block_comment, parse_line_comment,
+        space_or_comment0,
+    };
+
+    #[test]
+    fn parses_line_comments() {
+        assert_eq!(
+            parse_line_comment("; hey\n(+ 1 2)"),
+            Ok(("\n(+ 1 2)", "; hey"))
+        );
+        assert_eq!(parse_line_comment(";"), Ok(("", ";")));
+        assert!(parse_line_comment("(+ 1 2) ; hey").is_err());
+    }
+
+    #[test]
+    fn parses_block_comments() {
+        assert_eq!(
+            parse_block_comment("#| hey\nthere |# 2"),
+            Ok((" 2", "#| hey\nthere |#"))
+        );
+        assert_eq!(
+            parse_block_comment(
+                "#| outer #| inner |# still |#)"
+            ),
+            Ok((")", "#| outer #| inner |# still |#"))
+        );
+
+        assert!(parse_block_comment("#| unterminated").is_err());
+        assert!(parse_block_comment("#| #| |#").is_err());
+    }
+
+    #[test]
+    fn skips_whitespace_and_comments() {
+        assert_eq!(space_or_comment0(""), Ok(("", ())));
+        assert_eq!(space_or_comment0("2"), Ok(("2", ())));
+        assert_eq!(
+            space_or_comment0(
+                " ; one\n #| two |#\n\t#_(three) 4"
+            ),
+            Ok(("4", ()))
+        );
+
+        // Discarded forms may be nested
+        assert_eq!(
+            space_or_comment0("#_ #_ a b c"),
+            Ok(("c", ()))
+        );
+        assert_eq!(
+            space_or_comment0("#_[1 #| 2 |# (f 4)] 5"),
+            Ok(("5", ()))
+        );
+        assert!(space_or_comment0("#_").is_err());
+    }
+}
@@ -0,0 +1,364 @@
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, IsTerminal, Write},
+    path::PathBuf,
+};
+
+use ceceio::{Error, Interpreter};
+
+const PROMPT: &str = "ceceio> ";
+const CONTINUATION_PROMPT: &str = "   ...> ";
+
+/// Name under which REPL input is reported in error messages
+const SOURCE_NAME: &str = "<repl>";
+
+const HELP: &str = "\
+Commands:
+  :env      list the global bindings
+  :history  list previous inputs
+  :help     show this message
+  :quit     exit the REPL (as does Ctrl-D)";
+
+pub struct Repl {
+    interp: Interpreter,
+    history: History,
+    /// Whether we're talking to a person, in which case prompts
+    /// are shown
+    interactive: bool,
+}
+
+impl Repl {
+    pub fn new(interp: Interpreter) -> Self {
+        Self {
+            interp,
+            history: History::load(),
+            interactive: io::stdin().is_terminal(),
+        }
+    }
+
+    pub fn run(&mut self) -> io::Result<()> {
+        let stdin = io::stdin();
+        let mut lines = stdin.lock().lines();
+        let mut input = String::new();
+
+        loop {
+            self.prompt(if input.is_empty() {
+                PROMPT
+            } else {
+                CONTINUATION_PROMPT
+            })?;
+
+            let Some(line) = lines.next().transpose()? else {
+                // EOF: evaluate whatever was left unfinished so
+                // that its error gets reported
+                if !input.trim().is_empty() {
+                    self.evaluate(&input);
+                }
+                break;
+            };
+
+            if input.is_empty() {
+                match line.trim() {
+                    "" => continue,
+                    ":quit" | ":q" => break,
+                    ":env" => {
+                        self.print_env();
+                        continue;
+                    }
+                    ":history" => {
+                        self.print_history();
+                        continue;
+                    }
+                    ":help" => {
+                        println!("{HELP}");
+                        continue;
+                    }
+                    // Anything else, including symbols such as
+                    // `:ok`, gets evaluated
+                    _ => {}
+                }
+            }
+
+            if !input.is_empty() {
+                input.push('\n');
+            }
+            input.push_str(&line);
+
+            if is_balanced(&input) {
+                self.history.push(&input);
+                self.evaluate(&input);
+                input.clear();
+            }
+        }
+
+        if self.interactive {
+            println!();
+        }
+
+        Ok(())
+    }
+
+    fn prompt(&self, prompt: &str) -> io::Result<()> {
+        if self.interactive {
+            let mut stdout = io::stdout();
+            stdout.write_all(prompt.as_bytes())?;
+            stdout.flush()?;
+        }
+
+        Ok(())
+    }
+
+    fn evaluate(&mut self, input: &str) {
+        match self.interp.eval_source(SOURCE_NAME, input) {
+            Ok(expression) => println!("{expression}"),
+            Err(Error::ParsingError(error)) => {
+                eprintln!("error: {}", error.render(input))
+            }
+            Err(error) => eprintln!("error: {error}"),
+        }
+    }
+
+    fn print_env(&self) {
+        for (identifier, expression) in
+            self.interp.env().bindings()
+        {
+            println!("{identifier} = {expression}");
+        }
+    }
+
+    fn print_history(&self) {
+        for (idx, entry) in
+            self.history.entries.iter().enumerate()
+        {
+            println!("{:>4}  {entry}", idx + 1);
+        }
+    }
+}
+
+/// Whether every parenthesis, bracket, brace, string and block
+/// comment opened in `input` was closed, in which case the input
+/// can be evaluated. Surplus closing delimiters also count as
+/// balanced, so that the resulting parsing error gets reported
+/// right away
+pub fn is_balanced(input: &str) -> bool {
+    let mut depth = 0_i64;
+    let mut comment_depth = 0_usize;
+    let mut in_string = false;
+    let mut rest = input;
+
+    while let Some(ch) = rest.chars().next() {
+        rest = &rest[ch.len_utf8()..];
+
+        if comment_depth > 0 {
+            if ch == '|' && rest.starts_with('#') {
+                comment_depth -= 1;
+                rest = &rest[1..];
+            } else if ch == '#' && rest.starts_with('|') {
+                comment_depth += 1;
+                rest = &rest[1..];
+            }
+            continue;
+        }
+
+        match ch {
+            '\\' if in_string => {
+                // Skip whatever is being escaped
+                let mut chars = rest.chars();
+                chars.next();
+                rest = chars.as_str();
+            }
+            '"' => in_string = !in_string,
+            _ if in_string => {}
+            'r' => {
+                // Raw strings, `r"..."` or `r#"..."#`, have no
+                // escapes and end at a quote followed by as many
+                // `#`s as they started with
+                let hashes = rest.len()
+                    - rest.trim_start_matches('#').len();
+                if let Some(body) =
+                    rest[hashes..].strip_prefix('"')
+                {
+                    let closing =
+                        format!("\"{}", &rest[..hashes]);
+                    match body.find(&closing) {
+                        Some(end) => {
+                            rest = &body[end + closing.len()..]
+                        }
+                        None => return false,
+                    }
+                }
+            }
+            ';' => {
+                // Skip the rest of the line
+                rest = rest
+                    .find('\n')
+                    .map_or("", |idx| &rest[idx..]);
+            }
+            '#' if rest.starts_with('|') => {
+                comment_depth += 1;
+                rest = &rest[1..];
+            }
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            _ => {}
+        }
+    }
+
+    depth <= 0 && !in_string && comment_depth == 0
+}
+
+/// Inputs given to the REPL, kept across sessions in a file.
+///
+/// Each entry is stored in its own line. Entries spanning many
+/// lines have each of their line breaks but the last preceded
+/// by a backslash. Backslashes ending a line of the entry
+/// itself are doubled, so that they can be told apart.
+struct History {
+    entries: Vec<String>,
+    file: Option<File>,
+}
+
+impl History {
+    /// Loads the history from `$CECEIO_HISTORY` or, if unset,
+    /// `~/.ceceio_history`. History is kept in memory only if
+    /// neither is available
+    fn load() -> Self {
+        let Some(path) = Self::path() else {
+            return Self {
+                entries: vec![],
+                file: None,
+            };
+        };
+
+        let entries = fs::read_to_string(&path)
+            .map(|contents| Self::parse(&contents))
+            .unwrap_or_default();
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .ok();
+
+        Self { entries, file }
+    }
+
+    fn path() -> Option<PathBuf> {
+        env::var_os("CECEIO_HISTORY").map(PathBuf::from).or_else(
+            || {
+                env::var_os("HOME").map(|home| {
+                    PathBuf::from(home).join(".ceceio_history")
+                })
+            },
+        )
+    }
+
+    fn parse(contents: &str) -> Vec<String> {
+        let mut entries = vec![];
+        let mut entry = String::new();
+
+        for line in contents.lines() {
+            let text = line.trim_end_matches('\\');
+            let backslashes = line.len() - text.len();
+
+            // An odd amount of them ends in a line break
+            entry.push_str(text);
+            entry.push_str(&"\\".repeat(backslashes / 2));
+            if backslashes % 2 == 1 {
+                entry.push('\n');
+            } else {
+                entries.push(std::mem::take(&mut entry));
+            }
+        }
+
+        entries
+    }
+
+    fn encode(entry: &str) -> String {
+        let lines: Vec<_> = entry
+            .split('\n')
+            .map(|line| {
+                let text = line.trim_end_matches('\\');
+                let backslashes = line.len() - text.len();
+                format!("{text}{}", "\\".repeat(2 * backslashes))
+            })
+            .collect();
+
+        lines.join("\\\n")
+    }
+
+    fn push(&mut self, entry: &str) {
+        if let Some(file) = &mut self.file {
+            let encoded = Self::encode(entry);
+
+            // Failing to save the history shouldn't stop the
+            // REPL
+            if writeln!(file, "{encoded}").is_err() {
+                self.file = None;
+            }
+        }
+
+        self.entries.push(entry.to_owned());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_balanced, History};
+
+    #[test]
+    fn checks_whether_input_is_balanced() {
+        assert!(is_balanced(""));
+        assert!(is_balanced("(+ 1 2)"));
+        assert!(is_balanced("[1 [2 3]]"));
+        assert!(is_balanced("{:a #{1 2}}"));
+        assert!(is_balanced("(+ 1 2))"));
+        assert!(is_balanced("(= \")\" \"(\")"));
+        assert!(is_balanced("\"\\\"(\""));
+
+        assert!(!is_balanced("(def f (fn [x]"));
+        assert!(!is_balanced("[1 2"));
+        assert!(!is_balanced("{:a #{1 2}"));
+        assert!(!is_balanced("(= \")\""));
+        assert!(!is_balanced("\"unterminated"));
+
+        // Comments
+        assert!(is_balanced("(+ 1 2) ; (unbalanced"));
+        assert!(is_balanced("(+ 1 #| ( |# 2)"));
+        assert!(is_balanced("(+ 1 \"; (\")"));
+        assert!(!is_balanced("(+ 1 ; 2)"));
+        assert!(!is_balanced("#| #| |# (+ 1 2)"));
+        assert!(is_balanced("#| #| |# |# (+ 1 2)"));
+
+        // Raw strings
+        assert!(is_balanced(r#"(split "a\b" r"\")"#));
+        assert!(is_balanced(r##"(str r#"(")"# "")"##));
+        assert!(is_balanced(r#"(str r"\")"#));
+        assert!(!is_balanced(r#"(str r"\)"#));
+        assert!(!is_balanced(r##"(str r#"a"")"##));
+        assert!(is_balanced("(str rest)"));
+    }
+
+    #[test]
+    fn parses_history_files() {
+        assert_eq!(
+            History::parse(
+                "(+ 1 2)\n(def f\\\n  (fn [] 2))\n:ok\n"
+            ),
+            ["(+ 1 2)", "(def f\n  (fn [] 2))", ":ok"]
+        );
+
+        // Lines of an entry may end in backslashes themselves
+        let entries =
+            ["(str r\"a\\\nb\")", "r\"\\\\\"", "#| \\\n\\ |#"];
+        let encoded: Vec<_> = entries
+            .iter()
+            .map(|entry| History::encode(entry))
+            .collect();
+        assert_eq!(
+            History::parse(&(encoded.join("\n") + "\n")),
+            entries
+        );
+    }
+}
@@ -1,61 +1,64 @@
-#![allow(unused)]
+use std::rc::Rc;
 
-use crate::{Atom, Error, Expression, Result, SmallString};
+use crate::{Expression, SmallString};
 
-pub struct Scope<'a> {
-    identifiers: &'a [SmallString],
-    expressions: &'a [Expression],
+/// A lexical scope: the bindings introduced by a lambda
+/// application or by a `let` expression, linked to the scope
+/// enclosing them
+#[derive(Debug, PartialEq, Default)]
+pub struct Scope {
+    identifiers: Vec<SmallString>,
+    expressions: Vec<Expression>,
+    parent: Option<Rc<Scope>>,
 }
 
-impl Scope<'_> {
-    pub fn resolve_symbol<'other>(
-        &self,
-        identifier_to_resolve: &'other SmallString,
-    ) -> Result<&Expression> {
-        let idx = self
-            .identifiers
-            .iter()
-            .position(|identifier| {
-                identifier == identifier_to_resolve
-            })
-            .ok_or_else(|| {
-                Error::UnknownSymbol(
-                    identifier_to_resolve.clone(),
-                )
-            })?;
-
-        Ok(&self.expressions[idx])
-    }
-
-    pub fn resolve_all_in_place(
-        &self,
-        exprs: &mut [Expression],
-    ) {
-        for expression in exprs.iter_mut() {
-            if let Expression::Atom(Atom::Identifier(
-                identifier,
-            )) = expression
-            {
-                if let Ok(resolved_expression) =
-                    self.resolve_symbol(&*identifier)
-                {
-                    *expression = resolved_expression.clone();
-                }
-            }
-        }
-    }
-}
-
-impl<'a> Scope<'a> {
+impl Scope {
     pub fn new(
-        identifiers: &'a [SmallString],
-        expressions: &'a [Expression],
+        identifiers: Vec<SmallString>,
+        expressions: Vec<Expression>,
+        parent: Option<Rc<Scope>>,
     ) -> Self {
         debug_assert_eq!(identifiers.len(), expressions.len());
 
         Self {
             identifiers,
             expressions,
+            parent,
+        }
+    }
+
+    /// The value of the `idx`-th binding of this scope alone,
+    /// as used by closures created by the bytecode VM, whose
+    /// captured bindings are resolved at compile time
+    #[cfg(feature = "bytecode")]
+    pub(crate) fn get(&self, idx: usize) -> &Expression {
+        &self.expressions[idx]
+    }
+
+    /// Looks up `identifier_to_resolve` in this scope and then
+    /// in each of its enclosing scopes
+    pub fn resolve_symbol(
+        &self,
+        identifier_to_resolve: &str,
+    ) -> Option<&Expression> {
+        let mut scope = self;
+
+        loop {
+            // Search backwards so that, within a single scope,
+            // later bindings shadow earlier ones
+            let idx = scope.identifiers.iter().rposition(
+                |identifier| {
+                    identifier.as_str() == identifier_to_resolve
+                },
+            );
+
+            match (idx, &scope.parent) {
+                (Some(idx), _) => {
+                    return Some(&scope.expressions[idx])
+                }
+                (None, Some(parent)) => scope = parent,
+                (None, None) => return None,
+            }
         }
     }
 }
@@ -0,0 +1,307 @@
+use std::{fmt, mem, rc::Rc};
+
+use crate::{
+    expression::elements::FnIdentifier, Expression, SmallString,
+};
+
+/// The region of source code an expression was parsed from.
+///
+/// Right after parsing, `start` and `end` hold the amount of
+/// input that remained before and after the expression, since
+/// the parsers only ever see the rest of their input. They're
+/// turned into byte offsets, with the matching line and
+/// column, once the whole source is known (see
+/// [`Expression::resolve_spans`]).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Span {
+    /// Byte offset of the start of the expression
+    pub start: usize,
+    /// Byte offset right past the end of the expression
+    pub end: usize,
+    /// 1-based line of `start`, or 0 if not yet resolved
+    pub line: usize,
+    /// 1-based column, in characters, of `start`
+    pub column: usize,
+    /// Identifies the source this span belongs to
+    pub source: usize,
+}
+
+// Spans don't take part in comparisons so that expressions
+// compare equal regardless of where they were written
+impl PartialEq for Span {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for Span {}
+
+impl Span {
+    /// Builds an unresolved span out of the length of the input
+    /// before and after parsing an expression
+    pub(crate) fn from_remaining(
+        before: &str,
+        after: &str,
+    ) -> Self {
+        Self {
+            start: before.len(),
+            end: after.len(),
+            ..Self::default()
+        }
+    }
+
+    /// The resolved, empty, span of the position of `source`
+    /// where `remaining` begins
+    pub(crate) fn at(
+        source: &str,
+        remaining: &str,
+        source_id: usize,
+    ) -> Self {
+        let mut span =
+            Self::from_remaining(remaining, remaining);
+        span.resolve(source, &line_starts(source), source_id);
+        span
+    }
+
+    pub fn is_resolved(&self) -> bool {
+        self.line != 0
+    }
+
+    fn resolve(
+        &mut self,
+        source: &str,
+        line_starts: &[usize],
+        source_id: usize,
+    ) {
+        let start = source.len() - self.start;
+        let end = source.len() - self.end;
+
+        // `line_starts` always begins with 0, so this won't
+        // underflow
+        let line =
+            line_starts.partition_point(|&idx| idx <= start);
+        let line_start = line_starts[line - 1];
+
+        *self = Self {
+            start,
+            end,
+            line,
+            column: source[line_start..start].chars().count()
+                + 1,
+            source: source_id,
+        };
+    }
+}
+
+/// Byte offsets at which each line of `source` starts
+fn line_starts(source: &str) -> Vec<usize> {
+    std::iter::once(0)
+        .chain(
+            source.match_indices('\n').map(|(idx, _)| idx + 1),
+        )
+        .collect()
+}
+
+/// Where in a named source an error happened
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub source: SmallString,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}",
+            self.source, self.line, self.column
+        )
+    }
+}
+
+impl Expression {
+    /// Turns the spans of an expression freshly parsed from
+    /// `source` into byte offsets and line/column positions
+    pub fn resolve_spans(
+        &mut self,
+        source: &str,
+        source_id: usize,
+    ) {
+        let line_starts = line_starts(source);
+
+        self.for_each_span(&mut |span| {
+            if !span.is_resolved() {
+                span.resolve(source, &line_starts, source_id)
+            }
+        })
+    }
+
+    /// Calls `func` on the span of this expression and of every
+    /// expression nested in it
+    pub fn for_each_span(
+        &mut self,
+        func: &mut impl FnMut(&mut Span),
+    ) {
+        match self {
+            Expression::Atom(_) => {}
+            Expression::Application(app) => {
+                func(&mut app.span);
+                if let FnIdentifier::Expression(callee) =
+                    &mut app.name
+                {
+                    Rc::make_mut(callee).for_each_span(func);
+                }
+                for argument in &mut app.arguments {
+                    argument.for_each_span(func);
+                }
+            }
+            Expression::If(if_expr) => {
+                func(&mut if_expr.span);
+                if_expr.condition.for_each_span(func);
+                if_expr.do_this.for_each_span(func);
+            }
+            Expression::IfElse(if_else) => {
+                func(&mut if_else.span);
+                if_else.condition.for_each_span(func);
+                if_else.if_true.for_each_span(func);
+                if_else.if_false.for_each_span(func);
+            }
+            Expression::Binding(binding)
+            | Expression::DefMacro(binding) => {
+                func(&mut binding.span);
+                binding.expression.for_each_span(func);
+            }
+            Expression::Quote(form)
+            | Expression::Quasiquote(form)
+            | Expression::Unquote(form)
+            | Expression::UnquoteSplicing(form) => {
+                Rc::make_mut(form).for_each_span(func);
+            }
+            Expression::Let(let_expr) => {
+                func(&mut let_expr.span);
+                for (_, expression) in &mut let_expr.bindings {
+                    expression.for_each_span(func);
+                }
+                let_expr.body.for_each_span(func);
+            }
+            Expression::Match(match_expr) => {
+                func(&mut match_expr.span);
+                match_expr.expression.for_each_span(func);
+                for clause in &mut match_expr.clauses {
+                    if let Some(guard) = &mut clause.guard {
+                        guard.for_each_span(func);
+                    }
+                    clause.branch.for_each_span(func);
+                }
+            }
+            Expression::Lambda(lambda) => {
+                func(&mut lambda.span);
+                for (_, default) in
+                    &mut lambda.parameters.optional
+                {
+                    default.for_each_span(func);
+                }
+                for parameter in
+                    lambda.template.iter_mut().flatten()
+                {
+                    parameter.for_each_span(func);
+                }
+                Rc::make_mut(&mut lambda.body)
+                    .for_each_span(func);
+            }
+            Expression::Do(list) | Expression::List(list) => {
+                for expression in list {
+                    expression.for_each_span(func);
+                }
+            }
+            Expression::Map(map) => {
+                // Keys can't be changed in place, so entries are
+                // inserted anew, in the same order
+                let entries = mem::take(Rc::make_mut(map));
+                *Rc::make_mut(map) = entries
+                    .into_iter()
+                    .map(|(mut key, mut value)| {
+                        key.for_each_span(func);
+                        value.for_each_span(func);
+                        (key, value)
+                    })
+                    .collect();
+            }
+            Expression::Set(set) => {
+                let items = mem::take(Rc::make_mut(set));
+                *Rc::make_mut(set) = items
+                    .into_iter()
+                    .map(|mut item| {
+                        item.for_each_span(func);
+                        item
+                    })
+                    .collect();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{parse_expression, Expression};
+
+    fn application_span(
+        source: &str,
+    ) -> (usize, usize, usize, usize) {
+        let mut expr = parse_expression(source).unwrap().1;
+        expr.resolve_spans(source, 0);
+
+        match expr {
+            Expression::Application(app) => (
+                app.span.start,
+                app.span.end,
+                app.span.line,
+                app.span.column,
+            ),
+            other => {
+                panic!("expected an application, got {other}")
+            }
+        }
+    }
+
+    #[test]
+    fn resolves_spans() {
+        assert_eq!(application_span("(+ 1 2)"), (0, 7, 1, 1));
+        assert_eq!(
+            application_span("  (+ 1 2)  "),
+            (2, 9, 1, 3)
+        );
+        assert_eq!(
+            application_span("\n\n    (foo\n 2)"),
+            (6, 14, 3, 5)
+        );
+    }
+
+    #[test]
+    fn resolves_nested_spans() {
+        let source = "(def f\n  (fn [x]\n    (+ x (g x))))";
+        let mut expr = parse_expression(source).unwrap().1;
+        expr.resolve_spans(source, 0);
+
+        let mut positions = vec![];
+        expr.for_each_span(&mut |span| {
+            positions.push((span.line, span.column))
+        });
+
+        assert_eq!(positions, [(1, 1), (2, 3), (3, 5), (3, 10)]);
+    }
+
+    #[test]
+    fn counts_columns_in_characters() {
+        let source = "(+ \"ñ\" (foo))";
+        let mut expr = parse_expression(source).unwrap().1;
+        expr.resolve_spans(source, 0);
+
+        let mut spans = vec![];
+        expr.for_each_span(&mut |span| spans.push(*span));
+
+        assert_eq!(spans[1].start, 8);
+        assert_eq!(spans[1].column, 8);
+    }
+}
@@ -0,0 +1,526 @@
+//! An alternative backend that compiles expressions to
+//! bytecode, resolving bindings to stack slots ahead of time,
+//! and runs it on a stack machine.
+//!
+//! Values are shared with the tree walker, so both backends
+//! can be mixed: closures created here remain regular
+//! [`Lambda`](crate::expression::elements::Lambda)s carrying
+//! their compiled body, and lambdas lacking one are applied
+//! through the tree walker.
+
+mod compiler;
+mod function;
+
+use std::{iter, rc::Rc};
+
+pub use function::Function;
+use function::{Capture, Op};
+
+use crate::{
+    evaluatable,
+    expression::{
+        elements::{Atom, Lambda},
+        Map,
+    },
+    macros,
+    scope::Scope,
+    Env, Error, Expression, Result, Span, Typed,
+};
+
+/// Compiles and runs a top-level expression
+pub fn eval(
+    expression: &Expression,
+    env: &mut Env,
+) -> Result<Expression> {
+    let function = compiler::compile(expression);
+
+    Vm::new(env).run(Rc::new(function), None, vec![], None)
+}
+
+/// Calls a compiled lambda with the given, already evaluated,
+/// arguments
+pub fn call(
+    function: Rc<Function>,
+    captured: Option<Rc<Scope>>,
+    arguments: Vec<Expression>,
+    env: &mut Env,
+) -> Result<Expression> {
+    function.ensure_arity(arguments.len())?;
+
+    Vm::new(env).run(function, captured, arguments, None)
+}
+
+/// The state of a call to a compiled function
+struct Frame {
+    function: Rc<Function>,
+    captured: Option<Rc<Scope>>,
+    /// Index of the next instruction to run
+    ip: usize,
+    /// Stack index of the first argument. The callee sits right
+    /// below it
+    base: usize,
+    /// Span of the application that made this call, which is to
+    /// blame for errors not located within the function itself
+    span: Option<Span>,
+}
+
+struct Vm<'env> {
+    env: &'env mut Env,
+    stack: Vec<Expression>,
+    frames: Vec<Frame>,
+}
+
+impl<'env> Vm<'env> {
+    fn new(env: &'env mut Env) -> Self {
+        Self {
+            env,
+            stack: vec![],
+            frames: vec![],
+        }
+    }
+
+    fn run(
+        mut self,
+        function: Rc<Function>,
+        captured: Option<Rc<Scope>>,
+        arguments: Vec<Expression>,
+        span: Option<Span>,
+    ) -> Result<Expression> {
+        // Stands in for the callee, so that all frames look
+        // alike
+        self.stack.push(Expression::default());
+        self.stack.extend(arguments);
+        let ip = function.enter(&mut self.stack, 1);
+        self.frames.push(Frame {
+            function,
+            captured,
+            ip,
+            base: 1,
+            span,
+        });
+
+        loop {
+            let (op, op_span) = self.fetch();
+
+            match self.step(op, op_span) {
+                Ok(Some(value)) => return Ok(value),
+                Ok(None) => {}
+                Err(error) => {
+                    let span = op_span.or(self.frame().span);
+                    let result = Err(error);
+                    self.env.blame(&result, span);
+                    return result;
+                }
+            }
+        }
+    }
+
+    fn frame(&self) -> &Frame {
+        // Safe unwrap: `run` returns once the last frame is gone
+        self.frames.last().unwrap()
+    }
+
+    fn frame_mut(&mut self) -> &mut Frame {
+        // Safe unwrap: `run` returns once the last frame is gone
+        self.frames.last_mut().unwrap()
+    }
+
+    /// Reads the next instruction of the current frame, along
+    /// with its span
+    fn fetch(&mut self) -> (Op, Option<Span>) {
+        let frame = self.frame_mut();
+        let ip = frame.ip;
+        frame.ip += 1;
+
+        (frame.function.code[ip], frame.function.spans[ip])
+    }
+
+    fn pop(&mut self) -> Expression {
+        // Safe unwrap: the compiler keeps track of the stack
+        // depth, so we never pop more than what was pushed
+        self.stack.pop().unwrap()
+    }
+
+    /// Runs a single instruction, returning the result of the
+    /// outermost call once it returns
+    fn step(
+        &mut self,
+        op: Op,
+        span: Option<Span>,
+    ) -> Result<Option<Expression>> {
+        match op {
+            Op::Constant(idx) => {
+                let constant = self.frame().function.constants
+                    [idx as usize]
+                    .clone();
+                self.stack.push(constant);
+            }
+            Op::GetLocal(slot) => {
+                let value = self.stack
+                    [self.frame().base + slot as usize]
+                    .clone();
+                self.stack.push(value);
+            }
+            Op::GetCaptured(idx) => {
+                // Safe unwrap: the compiler only emits this
+                // for closures that capture something
+                let captured = self.frame().captured.as_ref();
+                let value =
+                    captured.unwrap().get(idx as usize).clone();
+                self.stack.push(value);
+            }
+            Op::GetGlobal(idx) => {
+                let identifier =
+                    &self.frame().function.globals[idx as usize];
+                let value = self.env.get_global(identifier)?;
+                self.stack.push(value);
+            }
+            Op::DefineGlobal(idx) => {
+                let identifier = self.frame().function.globals
+                    [idx as usize]
+                    .clone();
+                // Safe unwrap: there's always a value to bind
+                let value = self.stack.last().unwrap().clone();
+                self.env.insert(identifier, value);
+            }
+            Op::DefineGlobals(idx) => {
+                let pattern = &self.frame().function.patterns
+                    [idx as usize];
+                // Safe unwrap: there's always a value to bind
+                let value = self.stack.last().unwrap().clone();
+
+                // Nothing gets bound unless the whole pattern
+                // matches
+                let mut bindings = vec![];
+                pattern.destructure(
+                    value,
+                    &mut |identifier, value| {
+                        bindings
+                            .push((identifier.clone(), value))
+                    },
+                )?;
+                for (identifier, value) in bindings {
+                    self.env.insert(identifier, value);
+                }
+            }
+            Op::Destructure(idx) => {
+                let function = self.frame().function.clone();
+                let value = self.pop();
+                function.patterns[idx as usize]
+                    .destructure(value, &mut |_, value| {
+                        self.stack.push(value)
+                    })?;
+            }
+            Op::Match(idx, target) => {
+                let function = self.frame().function.clone();
+                let value = self.pop();
+                let pattern = &function.patterns[idx as usize];
+                if pattern.matches(&value) {
+                    pattern.destructure(
+                        value,
+                        &mut |_, value| self.stack.push(value),
+                    )?;
+                } else {
+                    self.frame_mut().ip = target as _;
+                }
+            }
+            Op::DefineMacro(idx) => {
+                let identifier = self.frame().function.globals
+                    [idx as usize]
+                    .clone();
+                let lambda = self.pop().as_lambda()?;
+                self.env.insert_macro(identifier, lambda);
+                self.stack.push(Expression::default());
+            }
+            Op::Quasiquote(idx, amount) => {
+                let template = self.frame().function.constants
+                    [idx as usize]
+                    .clone();
+                let len = self.stack.len() - amount as usize;
+                let mut values =
+                    self.stack.split_off(len).into_iter();
+                // Safe unwrap: the compiler pushed a value for
+                // each unquoted form
+                let code = macros::fill(&template, &mut |_| {
+                    Ok(values.next().unwrap())
+                })?;
+                self.stack.push(code);
+            }
+            Op::Slide(amount) => {
+                let value = self.pop();
+                let len = self.stack.len() - amount as usize;
+                self.stack.truncate(len);
+                self.stack.push(value);
+            }
+            Op::Pop(amount) => {
+                let len = self.stack.len() - amount as usize;
+                self.stack.truncate(len);
+            }
+            Op::Jump(target) => {
+                self.frame_mut().ip = target as _
+            }
+            Op::JumpUnlessTrue(target) => {
+                if !is_true(&self.pop()) {
+                    self.frame_mut().ip = target as _;
+                }
+            }
+            Op::JumpIfTrue(target) => {
+                if is_true(&self.pop()) {
+                    self.frame_mut().ip = target as _;
+                }
+            }
+            Op::CheckBool => {
+                // Safe unwrap: there's always a value to check
+                let value = self.stack.last().unwrap();
+                if !matches!(
+                    value,
+                    Expression::Atom(Atom::Boolean(_))
+                ) {
+                    return Err(Error::TypeMismatch {
+                        expected: "boolean",
+                        received: value.rough_type(),
+                    });
+                }
+            }
+            Op::MakeList(amount) => {
+                let len = self.stack.len() - amount as usize;
+                let list = self.stack.split_off(len);
+                self.stack.push(Expression::List(list));
+            }
+            Op::MakeMap(amount) => {
+                let len = self.stack.len() - 2 * amount as usize;
+                // Keys and values were pushed in turns
+                let mut values =
+                    self.stack.split_off(len).into_iter();
+                let map: Map = iter::from_fn(|| {
+                    Some((values.next()?, values.next()?))
+                })
+                .collect();
+                self.stack.push(Expression::Map(Rc::new(map)));
+            }
+            Op::MakeSet(amount) => {
+                let len = self.stack.len() - amount as usize;
+                let set = self.stack.drain(len..).collect();
+                self.stack.push(Expression::Set(Rc::new(set)));
+            }
+            Op::MakeClosure(idx) => {
+                let closure = self.make_closure(idx as usize);
+                self.stack.push(closure);
+            }
+            Op::Call(amount) => {
+                return self.call(amount as usize, span, false)
+            }
+            Op::TailCall(amount) => {
+                return self.call(amount as usize, span, true)
+            }
+            Op::CallBuiltIn(built_in, amount) => {
+                let len = self.stack.len() - amount as usize;
+                let arguments = self.stack.split_off(len);
+                let value =
+                    built_in.call(arguments, self.env)?;
+                self.stack.push(value);
+            }
+            Op::Return => {
+                let value = self.pop();
+                return Ok(self.return_value(value));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn make_closure(&self, idx: usize) -> Expression {
+        let frame = self.frame();
+        let prototype = &frame.function.prototypes[idx];
+
+        // Closures capturing nothing share the empty scope of
+        // those defined at the top level
+        let captured = if prototype.captures.is_empty() {
+            self.env.top_level_scope()
+        } else {
+            let (identifiers, values) = prototype
+                .captures
+                .iter()
+                .map(|(identifier, capture)| {
+                    let value = match *capture {
+                        Capture::Local(slot) => {
+                            &self.stack
+                                [frame.base + slot as usize]
+                        }
+                        // Safe unwrap: the enclosing closure
+                        // captures what we capture from it
+                        Capture::Captured(idx) => frame
+                            .captured
+                            .as_ref()
+                            .unwrap()
+                            .get(idx as usize),
+                    };
+                    (identifier.clone(), value.clone())
+                })
+                .unzip();
+
+            Rc::new(Scope::new(identifiers, values, None))
+        };
+
+        Expression::Lambda(Box::new(Lambda {
+            parameters: prototype.parameters.clone(),
+            body: prototype.body.clone(),
+            captured: Some(captured),
+            span: prototype.span,
+            template: None,
+            compiled: Some(prototype.function.clone()),
+        }))
+    }
+
+    /// Calls a value other than a compiled lambda, at
+    /// `callee_idx` of the stack, with the values above it
+    fn call_value(
+        &mut self,
+        callee_idx: usize,
+    ) -> Result<Expression> {
+        let arguments = self.stack.split_off(callee_idx + 1);
+        let callee = self.pop();
+        evaluatable::call(&callee, arguments, self.env)?
+            .finish(self.env)
+    }
+
+    /// Calls the function under the top `amount` values of the
+    /// stack, which are its arguments
+    fn call(
+        &mut self,
+        amount: usize,
+        span: Option<Span>,
+        tail: bool,
+    ) -> Result<Option<Expression>> {
+        let callee_idx = self.stack.len() - amount - 1;
+
+        let value = match &self.stack[callee_idx] {
+            Expression::Lambda(lambda) => match &lambda.compiled
+            {
+                Some(function) => {
+                    function.ensure_arity(amount)?;
+
+                    let function = function.clone();
+                    let captured = lambda.captured.clone();
+                    let ip = function
+                        .enter(&mut self.stack, callee_idx + 1);
+                    let frame = Frame {
+                        function,
+                        captured,
+                        ip,
+                        base: callee_idx + 1,
+                        span,
+                    };
+
+                    if tail {
+                        // Move the callee and its arguments
+                        // over the current call's
+                        let base = self.frame().base;
+                        self.stack.drain(base - 1..callee_idx);
+                        *self.frame_mut() =
+                            Frame { base, ..frame };
+                    } else {
+                        self.frames.push(frame);
+                    }
+
+                    return Ok(None);
+                }
+                None => self.call_value(callee_idx)?,
+            },
+            _ => self.call_value(callee_idx)?,
+        };
+
+        if tail {
+            Ok(self.return_value(value))
+        } else {
+            self.stack.push(value);
+            Ok(None)
+        }
+    }
+
+    /// Pops the current frame, handing `value` to its caller,
+    /// or returning it if there's none
+    fn return_value(
+        &mut self,
+        value: Expression,
+    ) -> Option<Expression> {
+        // Safe unwrap: `run` returns once the last frame is gone
+        let frame = self.frames.pop().unwrap();
+        self.stack.truncate(frame.base - 1);
+
+        if self.frames.is_empty() {
+            Some(value)
+        } else {
+            self.stack.push(value);
+            None
+        }
+    }
+}
+
+/// Conditions hold only when they evaluate to `true`, as in
+/// the tree walker
+fn is_true(value: &Expression) -> bool {
+    matches!(value, Expression::Atom(Atom::Boolean(true)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::{Backend, Interpreter};
+
+    /// How long `program` takes to run through `backend`, once
+    /// its definitions have been evaluated
+    fn time(
+        backend: Backend,
+        setup: &str,
+        program: &str,
+    ) -> Duration {
+        let mut interp = Interpreter::with_backend(backend);
+        interp.eval_program(setup).unwrap();
+
+        let start = Instant::now();
+        interp.eval_program(program).unwrap();
+        start.elapsed()
+    }
+
+    #[test]
+    #[ignore = "used only for quick benchmarks"]
+    fn outruns_the_tree_walker() {
+        let workloads = [
+            (
+                "(def fib (fn [n]
+                    (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2))))))",
+                "(fib 22)",
+            ),
+            (
+                "(def sum-to (fn [n (acc 0)]
+                    (if (= n 0) acc (sum-to (- n 1) (+ acc n)))))",
+                "(sum-to 300000)",
+            ),
+            (
+                "(def even? (fn [x] (= (% x 2) 0)))
+                 (def count-even (fn [n (acc 0)]
+                    (if (= n 0)
+                        acc
+                        (count-even
+                            (- n 1)
+                            (if (even? n) (+ acc 1) acc)))))",
+                "(count-even 200000)",
+            ),
+        ];
+
+        for (setup, program) in workloads {
+            let tree_walker =
+                time(Backend::TreeWalker, setup, program);
+            let bytecode =
+                time(Backend::Bytecode, setup, program);
+            println!(
+                "{program}: {tree_walker:?} walking the tree, \
+                 {bytecode:?} as bytecode"
+            );
+
+            assert!(bytecode < tree_walker);
+        }
+    }
+}
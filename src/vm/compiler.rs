@@ -0,0 +1,848 @@
+use std::rc::Rc;
+
+use super::function::{Capture, Function, Op, Prototype};
+use crate::{
+    expression::elements::{
+        Application, Atom, FnIdentifier, Lambda, Match, Pattern,
+    },
+    macros, BuiltIn, Expression, SmallString, Span,
+};
+
+/// Compiles a top-level expression into a function taking no
+/// arguments
+pub fn compile(expression: &Expression) -> Function {
+    let mut compiler = Compiler {
+        builders: vec![Builder::default()],
+    };
+
+    compiler.compile(expression, true);
+    compiler.emit(Op::Return);
+
+    // Safe unwrap: there's always the top-level builder
+    compiler.builders.pop().unwrap().function
+}
+
+/// Where an identifier is bound, as found at compile time
+enum Access {
+    Local(u32),
+    Captured(u32),
+    Global,
+}
+
+/// A function being compiled
+#[derive(Default)]
+struct Builder {
+    function: Function,
+    /// Arguments and `let` bindings in scope, along with their
+    /// stack slot. Later bindings shadow earlier ones
+    locals: Vec<(SmallString, u32)>,
+    /// Bindings of enclosing functions this one refers to
+    captures: Vec<(SmallString, Capture)>,
+    /// Amount of values the code emitted so far leaves on the
+    /// stack of the current call
+    depth: u32,
+    /// Span of the innermost application being compiled
+    span: Option<Span>,
+}
+
+struct Compiler {
+    /// The function being compiled, preceded by those enclosing
+    /// it
+    builders: Vec<Builder>,
+}
+
+impl Compiler {
+    fn builder(&mut self) -> &mut Builder {
+        // Safe unwrap: there's always the top-level builder
+        self.builders.last_mut().unwrap()
+    }
+
+    /// Index of the next instruction to be emitted
+    fn position(&mut self) -> u32 {
+        self.builder().function.code.len() as u32
+    }
+
+    fn emit(&mut self, op: Op) {
+        let builder = self.builder();
+
+        builder.depth = match op {
+            Op::Constant(_)
+            | Op::GetLocal(_)
+            | Op::GetCaptured(_)
+            | Op::GetGlobal(_)
+            | Op::MakeClosure(_) => builder.depth + 1,
+            Op::Slide(amount) | Op::Pop(amount) => {
+                builder.depth - amount
+            }
+            Op::JumpUnlessTrue(_) | Op::JumpIfTrue(_) => {
+                builder.depth - 1
+            }
+            // Replace the arguments, and the callee if any, by
+            // the result
+            Op::MakeList(amount)
+            | Op::MakeSet(amount)
+            | Op::CallBuiltIn(_, amount) => {
+                builder.depth - amount + 1
+            }
+            Op::MakeMap(amount) => {
+                builder.depth - 2 * amount + 1
+            }
+            Op::Quasiquote(_, amount) => {
+                builder.depth - amount + 1
+            }
+            // When matching fails, the jump target has the depth
+            // set by hand
+            Op::Destructure(idx) | Op::Match(idx, _) => {
+                let pattern =
+                    &builder.function.patterns[idx as usize];
+                builder.depth - 1
+                    + pattern.identifiers().len() as u32
+            }
+            Op::Call(amount) | Op::TailCall(amount) => {
+                builder.depth - amount
+            }
+            Op::DefineGlobal(_)
+            | Op::DefineGlobals(_)
+            | Op::DefineMacro(_)
+            | Op::Jump(_)
+            | Op::CheckBool
+            | Op::Return => builder.depth,
+        };
+
+        builder.function.code.push(op);
+        builder.function.spans.push(builder.span);
+    }
+
+    /// Emits a jump to be patched once its target is known
+    fn emit_jump(
+        &mut self,
+        op: impl FnOnce(u32) -> Op,
+    ) -> usize {
+        self.emit(op(u32::MAX));
+        self.builder().function.code.len() - 1
+    }
+
+    /// Makes the jump at `idx` land on the next instruction
+    fn patch_jump(&mut self, idx: usize) {
+        let target = self.position();
+
+        match &mut self.builder().function.code[idx] {
+            Op::Jump(to)
+            | Op::JumpUnlessTrue(to)
+            | Op::JumpIfTrue(to)
+            | Op::Match(_, to) => *to = target,
+            other => unreachable!("{other:?} is not a jump"),
+        }
+    }
+
+    fn constant(&mut self, expression: Expression) {
+        let idx = self.add_constant(expression);
+        self.emit(Op::Constant(idx));
+    }
+
+    /// Stores a constant without pushing it
+    fn add_constant(&mut self, expression: Expression) -> u32 {
+        let constants = &mut self.builder().function.constants;
+        constants.push(expression);
+        constants.len() as u32 - 1
+    }
+
+    fn add_pattern(&mut self, pattern: &Pattern) -> u32 {
+        let patterns = &mut self.builder().function.patterns;
+        patterns.push(pattern.clone());
+        patterns.len() as u32 - 1
+    }
+
+    fn global(&mut self, identifier: &SmallString) -> u32 {
+        let globals = &mut self.builder().function.globals;
+
+        match globals.iter().position(|name| name == identifier)
+        {
+            Some(idx) => idx as u32,
+            None => {
+                globals.push(identifier.clone());
+                globals.len() as u32 - 1
+            }
+        }
+    }
+
+    /// Finds where `identifier` is bound as seen from the
+    /// function at `level`, capturing it from the enclosing
+    /// functions if needed
+    fn resolve(
+        &mut self,
+        level: usize,
+        identifier: &SmallString,
+    ) -> Access {
+        let builder = &self.builders[level];
+
+        if let Some((_, slot)) = builder
+            .locals
+            .iter()
+            .rev()
+            .find(|(name, _)| name == identifier)
+        {
+            return Access::Local(*slot);
+        }
+
+        if let Some(idx) = builder
+            .captures
+            .iter()
+            .position(|(name, _)| name == identifier)
+        {
+            return Access::Captured(idx as u32);
+        }
+
+        if level == 0 {
+            return Access::Global;
+        }
+
+        let capture = match self.resolve(level - 1, identifier) {
+            Access::Local(slot) => Capture::Local(slot),
+            Access::Captured(idx) => Capture::Captured(idx),
+            Access::Global => return Access::Global,
+        };
+
+        let captures = &mut self.builders[level].captures;
+        captures.push((identifier.clone(), capture));
+        Access::Captured(captures.len() as u32 - 1)
+    }
+
+    fn compile_identifier(&mut self, identifier: &SmallString) {
+        let level = self.builders.len() - 1;
+
+        let op = match self.resolve(level, identifier) {
+            Access::Local(slot) => Op::GetLocal(slot),
+            Access::Captured(idx) => Op::GetCaptured(idx),
+            Access::Global => {
+                Op::GetGlobal(self.global(identifier))
+            }
+        };
+
+        self.emit(op);
+    }
+
+    /// Compiles `expression`, whose value is left on top of the
+    /// stack. `tail` tells whether nothing's left to do in the
+    /// current function after evaluating it
+    fn compile(&mut self, expression: &Expression, tail: bool) {
+        // As in the tree walker, special forms are to blame for
+        // the errors within them not located any more precisely
+        let span = match expression {
+            Expression::If(if_expr) => Some(if_expr.span),
+            Expression::IfElse(if_else) => Some(if_else.span),
+            Expression::Binding(binding) => Some(binding.span),
+            Expression::Let(let_expr) => Some(let_expr.span),
+            Expression::Match(match_expr) => {
+                Some(match_expr.span)
+            }
+            _ => None,
+        };
+        let enclosing_span = self.builder().span;
+        if span.is_some() {
+            self.builder().span = span;
+        }
+
+        match expression {
+            Expression::Atom(Atom::Identifier(identifier)) => {
+                self.compile_identifier(identifier)
+            }
+            Expression::Atom(atom) => {
+                self.constant(Expression::Atom(atom.clone()))
+            }
+            Expression::Application(application) => {
+                self.compile_application(application, tail)
+            }
+            Expression::If(if_expr) => self.compile_if(
+                &if_expr.condition,
+                &if_expr.do_this,
+                None,
+                tail,
+            ),
+            Expression::IfElse(if_else) => self.compile_if(
+                &if_else.condition,
+                &if_else.if_true,
+                Some(&if_else.if_false),
+                tail,
+            ),
+            Expression::Binding(binding) => {
+                self.compile(&binding.expression, false);
+                let op = match &binding.pattern {
+                    Pattern::Identifier(identifier) => {
+                        Op::DefineGlobal(self.global(identifier))
+                    }
+                    pattern => Op::DefineGlobals(
+                        self.add_pattern(pattern),
+                    ),
+                };
+                self.emit(op);
+            }
+            Expression::DefMacro(def_macro) => {
+                let Pattern::Identifier(identifier) =
+                    &def_macro.pattern
+                else {
+                    unreachable!(
+                        "unquotes outside of quasiquotes are \
+                         rejected while expanding macros"
+                    )
+                };
+                self.compile(&def_macro.expression, false);
+                let idx = self.global(identifier);
+                self.emit(Op::DefineMacro(idx));
+            }
+            Expression::Quote(data) => {
+                self.constant((**data).clone())
+            }
+            Expression::Quasiquote(template) => {
+                self.compile_quasiquote(template)
+            }
+            Expression::Unquote(_)
+            | Expression::UnquoteSplicing(_) => {
+                unreachable!(
+                    "unquotes outside of quasiquotes are rejected \
+                     while expanding macros"
+                )
+            }
+            Expression::Let(let_expr) => {
+                let locals = self.builder().locals.len();
+                let depth = self.builder().depth;
+
+                for (pattern, expression) in &let_expr.bindings {
+                    self.compile(expression, false);
+                    if !matches!(pattern, Pattern::Identifier(_))
+                    {
+                        let idx = self.add_pattern(pattern);
+                        self.emit(Op::Destructure(idx));
+                    }
+
+                    // The values we've just pushed are the
+                    // bindings, in the order of their
+                    // identifiers
+                    let identifiers = pattern.identifiers();
+                    let builder = self.builder();
+                    let first =
+                        builder.depth - identifiers.len() as u32;
+                    builder.locals.extend(
+                        identifiers
+                            .into_iter()
+                            .cloned()
+                            .zip(first..),
+                    );
+                }
+
+                self.compile(&let_expr.body, tail);
+                self.builder().locals.truncate(locals);
+
+                let amount = self.builder().depth - depth - 1;
+                if amount > 0 {
+                    self.emit(Op::Slide(amount));
+                }
+            }
+            Expression::Match(match_expr) => {
+                self.compile_match(match_expr, tail)
+            }
+            Expression::Lambda(lambda) => {
+                self.compile_lambda(lambda)
+            }
+            Expression::Do(body) => {
+                let Some((last, rest)) = body.split_last()
+                else {
+                    return self.constant(Expression::default());
+                };
+
+                // The values of all but the last expression are
+                // left on the stack, then slid out from under it
+                for expression in rest {
+                    self.compile(expression, false);
+                }
+                self.compile(last, tail);
+
+                if !rest.is_empty() {
+                    self.emit(Op::Slide(rest.len() as u32));
+                }
+            }
+            Expression::List(list) => {
+                for expression in list {
+                    self.compile(expression, false);
+                }
+                self.emit(Op::MakeList(list.len() as u32));
+            }
+            Expression::Map(map) => {
+                for (key, value) in map.iter() {
+                    self.compile(key, false);
+                    self.compile(value, false);
+                }
+                self.emit(Op::MakeMap(map.len() as u32));
+            }
+            Expression::Set(set) => {
+                for item in set.iter() {
+                    self.compile(item, false);
+                }
+                self.emit(Op::MakeSet(set.len() as u32));
+            }
+        }
+
+        self.builder().span = enclosing_span;
+    }
+
+    fn compile_if(
+        &mut self,
+        condition: &Expression,
+        if_true: &Expression,
+        if_false: Option<&Expression>,
+        tail: bool,
+    ) {
+        self.compile(condition, false);
+        let to_else = self.emit_jump(Op::JumpUnlessTrue);
+
+        self.compile(if_true, tail);
+        let to_end = self.emit_jump(Op::Jump);
+
+        // Only one of the branches gets to push its value
+        self.builder().depth -= 1;
+        self.patch_jump(to_else);
+        match if_false {
+            Some(if_false) => self.compile(if_false, tail),
+            None => self.constant(Expression::default()),
+        }
+        self.patch_jump(to_end);
+    }
+
+    /// Tries each clause in turn against the value of the
+    /// matched expression, which is kept on the stack until the
+    /// chosen branch is done with it
+    fn compile_match(&mut self, match_expr: &Match, tail: bool) {
+        self.compile(&match_expr.expression, false);
+        let value = self.builder().depth - 1;
+        let mut to_end = vec![];
+
+        for clause in &match_expr.clauses {
+            let locals = self.builder().locals.len();
+            let identifiers = clause.pattern.identifiers();
+            let amount = identifiers.len() as u32;
+
+            self.emit(Op::GetLocal(value));
+            let idx = self.add_pattern(&clause.pattern);
+            let to_next =
+                self.emit_jump(|to| Op::Match(idx, to));
+            self.builder().locals.extend(
+                identifiers
+                    .into_iter()
+                    .cloned()
+                    .zip(value + 1..),
+            );
+
+            let to_fail = clause.guard.as_ref().map(|guard| {
+                self.compile(guard, false);
+                self.emit_jump(Op::JumpUnlessTrue)
+            });
+
+            self.compile(&clause.branch, tail);
+            if amount > 0 {
+                self.emit(Op::Slide(amount));
+            }
+            to_end.push(self.emit_jump(Op::Jump));
+            self.builder().locals.truncate(locals);
+
+            // Only the chosen branch gets to push its value, and
+            // failing guards leave the bindings behind
+            if let Some(to_fail) = to_fail {
+                self.builder().depth = value + 1 + amount;
+                self.patch_jump(to_fail);
+                if amount > 0 {
+                    self.emit(Op::Pop(amount));
+                }
+            }
+            self.builder().depth = value + 1;
+            self.patch_jump(to_next);
+        }
+
+        // No clause matched
+        self.constant(Expression::default());
+        for to_end in to_end {
+            self.patch_jump(to_end);
+        }
+        self.emit(Op::Slide(1));
+    }
+
+    /// Pushes the values of the unquoted forms of `template`,
+    /// which the VM then fills it in with
+    fn compile_quasiquote(&mut self, template: &Expression) {
+        let mut amount = 0;
+        // Filling in the template goes through its unquoted
+        // forms in the order the VM will expect their
+        // values. Only failing to splice something in
+        // may fail, in which case the VM fails at the
+        // same point
+        let _ = macros::fill(template, &mut |form| {
+            self.compile(form, false);
+            amount += 1;
+            Ok(Expression::List(vec![]))
+        });
+
+        let idx = self.add_constant(template.clone());
+        self.emit(Op::Quasiquote(idx, amount));
+    }
+
+    /// Calls the callee on top of the stack with the given
+    /// arguments
+    fn compile_call(
+        &mut self,
+        arguments: &[Expression],
+        tail: bool,
+    ) {
+        for argument in arguments {
+            self.compile(argument, false);
+        }
+
+        let amount = arguments.len() as u32;
+        self.emit(if tail {
+            Op::TailCall(amount)
+        } else {
+            Op::Call(amount)
+        });
+    }
+
+    fn compile_application(
+        &mut self,
+        application: &Application,
+        tail: bool,
+    ) {
+        let enclosing_span = self.builder().span;
+        self.builder().span = Some(application.span);
+
+        let arguments = &application.arguments;
+
+        match &application.name {
+            FnIdentifier::BuiltIn(BuiltIn::Cond) => {
+                self.compile_cond(arguments, tail)
+            }
+            FnIdentifier::BuiltIn(BuiltIn::And)
+                if arguments.len() >= 2 =>
+            {
+                self.compile_short_circuit(arguments, false)
+            }
+            FnIdentifier::BuiltIn(BuiltIn::Or)
+                if arguments.len() >= 2 =>
+            {
+                self.compile_short_circuit(arguments, true)
+            }
+            FnIdentifier::BuiltIn(built_in) => {
+                for argument in arguments {
+                    self.compile(argument, false);
+                }
+                self.emit(Op::CallBuiltIn(
+                    *built_in,
+                    arguments.len() as u32,
+                ));
+            }
+            FnIdentifier::Symbol(symbol) => {
+                self.constant(Expression::Atom(Atom::Symbol(
+                    symbol.clone(),
+                )));
+                self.compile_call(arguments, tail);
+            }
+            FnIdentifier::Other(identifier) => {
+                // The callee is looked up before evaluating the
+                // arguments, as the tree walker does
+                self.compile_identifier(identifier);
+                self.compile_call(arguments, tail);
+            }
+            FnIdentifier::Expression(callee) => {
+                self.compile(callee, false);
+                self.compile_call(arguments, tail);
+            }
+        }
+
+        self.builder().span = enclosing_span;
+    }
+
+    /// Compiles `cond` into a chain of conditional jumps, with
+    /// its branches in tail position
+    fn compile_cond(
+        &mut self,
+        arguments: &[Expression],
+        tail: bool,
+    ) {
+        let mut to_end = vec![];
+        let mut pairs = arguments.chunks_exact(2);
+
+        for pair in &mut pairs {
+            self.compile(&pair[0], false);
+            let to_next = self.emit_jump(Op::JumpUnlessTrue);
+
+            self.compile(&pair[1], tail);
+            to_end.push(self.emit_jump(Op::Jump));
+
+            self.builder().depth -= 1;
+            self.patch_jump(to_next);
+        }
+
+        match pairs.remainder() {
+            [default] => self.compile(default, tail),
+            _ => self.constant(Expression::default()),
+        }
+
+        for jump in to_end {
+            self.patch_jump(jump);
+        }
+    }
+
+    /// Compiles `and`, if `stop_on` is false, or `or`, if it's
+    /// true, so that arguments after the first one equal to
+    /// `stop_on` aren't evaluated
+    fn compile_short_circuit(
+        &mut self,
+        arguments: &[Expression],
+        stop_on: bool,
+    ) {
+        let mut to_stop = vec![];
+
+        for argument in arguments {
+            self.compile(argument, false);
+            self.emit(Op::CheckBool);
+            to_stop.push(self.emit_jump(if stop_on {
+                Op::JumpIfTrue
+            } else {
+                Op::JumpUnlessTrue
+            }));
+        }
+
+        self.constant((!stop_on).into());
+        let to_end = self.emit_jump(Op::Jump);
+
+        self.builder().depth -= 1;
+        for jump in to_stop {
+            self.patch_jump(jump);
+        }
+        self.constant(stop_on.into());
+        self.patch_jump(to_end);
+    }
+
+    fn compile_lambda(&mut self, lambda: &Lambda) {
+        // Lambdas that were already evaluated are values
+        if lambda.captured.is_some() {
+            return self.constant(Expression::Lambda(Box::new(
+                lambda.clone(),
+            )));
+        }
+
+        assert!(
+            lambda.template.is_none(),
+            "unquotes outside of quasiquotes are rejected while \
+             expanding macros"
+        );
+        let parameters = &lambda.parameters;
+        let arity = parameters.required.len() as u32;
+        self.builders.push(Builder {
+            function: Function {
+                arity: arity as usize,
+                variadic: parameters.rest.is_some(),
+                ..Function::default()
+            },
+            locals: parameters
+                .required
+                .iter()
+                .cloned()
+                .zip(0..)
+                .collect(),
+            depth: arity,
+            ..Builder::default()
+        });
+
+        // Calls leaving out optional arguments start by pushing
+        // the defaults of those, each into the slot of its
+        // parameter
+        for (identifier, default) in &parameters.optional {
+            let entry = self.position();
+            self.builder().function.entries.push(entry);
+
+            self.compile(default, false);
+            let builder = self.builder();
+            let slot = builder.depth - 1;
+            builder.locals.push((identifier.clone(), slot));
+        }
+        if let Some(rest) = &parameters.rest {
+            if parameters.optional.is_empty() {
+                // There's no code to run before the body, so the
+                // VM always pushes the list of rest arguments
+                self.builder().depth += 1;
+            } else {
+                self.emit(Op::MakeList(0));
+            }
+            let builder = self.builder();
+            let slot = builder.depth - 1;
+            builder.locals.push((rest.clone(), slot));
+        }
+        let entry = self.position();
+        self.builder().function.entries.push(entry);
+
+        self.compile(&lambda.body, true);
+        self.emit(Op::Return);
+
+        // Safe unwrap: we've just pushed this builder
+        let builder = self.builders.pop().unwrap();
+
+        let prototypes = &mut self.builder().function.prototypes;
+        prototypes.push(Prototype {
+            function: Rc::new(builder.function),
+            captures: builder.captures,
+            parameters: lambda.parameters.clone(),
+            body: lambda.body.clone(),
+            span: lambda.span,
+        });
+        let idx = prototypes.len() as u32 - 1;
+
+        self.emit(Op::MakeClosure(idx));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compile, Capture, Op};
+    use crate::{parse_expression, BuiltIn, SmallString};
+
+    #[test]
+    fn resolves_bindings_ahead_of_time() {
+        let expression = parse_expression(
+            "(let [x 1] (fn [y] (let [z 2] (+ x y z w))))",
+        )
+        .unwrap()
+        .1;
+
+        let function = compile(&expression);
+        assert_eq!(
+            function.code,
+            [
+                Op::Constant(0),
+                Op::MakeClosure(0),
+                Op::Slide(1),
+                Op::Return
+            ]
+        );
+
+        let prototype = &function.prototypes[0];
+        assert_eq!(
+            prototype.captures,
+            [(SmallString::new("x"), Capture::Local(0))]
+        );
+        assert_eq!(
+            prototype.function.code,
+            [
+                Op::Constant(0),
+                Op::GetCaptured(0),
+                Op::GetLocal(0),
+                Op::GetLocal(1),
+                Op::GetGlobal(0),
+                Op::CallBuiltIn(BuiltIn::Plus, 4),
+                Op::Slide(1),
+                Op::Return
+            ]
+        );
+    }
+
+    #[test]
+    fn compiles_tail_calls() {
+        let expression = parse_expression(
+            "(fn [n] (if (= n 0) (f n) (g (h n))))",
+        )
+        .unwrap()
+        .1;
+
+        let function = compile(&expression);
+        let calls: Vec<_> = function.prototypes[0]
+            .function
+            .code
+            .iter()
+            .filter(|op| {
+                matches!(op, Op::Call(_) | Op::TailCall(_))
+            })
+            .copied()
+            .collect();
+
+        assert_eq!(
+            calls,
+            [Op::TailCall(1), Op::Call(1), Op::TailCall(1)]
+        );
+    }
+
+    #[test]
+    fn compiles_do_blocks() {
+        let expression =
+            parse_expression("(fn [x] (f x) (g x))").unwrap().1;
+
+        let function = compile(&expression);
+        assert_eq!(
+            function.prototypes[0].function.code,
+            [
+                Op::GetGlobal(0),
+                Op::GetLocal(0),
+                Op::Call(1),
+                Op::GetGlobal(1),
+                Op::GetLocal(0),
+                Op::TailCall(1),
+                Op::Slide(1),
+                Op::Return
+            ]
+        );
+    }
+
+    #[test]
+    fn compiles_optional_parameters() {
+        let expression =
+            parse_expression("(fn [x (y 1) & more] more)")
+                .unwrap()
+                .1;
+
+        let function =
+            &compile(&expression).prototypes[0].function;
+        // Leaving `y` out starts by pushing its default, then an
+        // empty list for `more`
+        assert_eq!(
+            function.code,
+            [
+                Op::Constant(0),
+                Op::MakeList(0),
+                Op::GetLocal(2),
+                Op::Return
+            ]
+        );
+        assert_eq!(function.entries, [0, 2]);
+        assert!(function.variadic);
+    }
+
+    #[test]
+    fn compiles_match_expressions() {
+        let expression = parse_expression(
+            "(fn [x] (match x [y] :when y y _ 0))",
+        )
+        .unwrap()
+        .1;
+
+        let function =
+            &compile(&expression).prototypes[0].function;
+        // The matched value stays in slot 1 while each clause
+        // matches a copy of it
+        assert_eq!(
+            function.code,
+            [
+                Op::GetLocal(0),
+                Op::GetLocal(1),
+                Op::Match(0, 9),
+                Op::GetLocal(2),
+                Op::JumpUnlessTrue(8),
+                Op::GetLocal(2),
+                Op::Slide(1),
+                Op::Jump(14),
+                Op::Pop(1),
+                Op::GetLocal(1),
+                Op::Match(1, 13),
+                Op::Constant(0),
+                Op::Jump(14),
+                Op::Constant(1),
+                Op::Slide(1),
+                Op::Return
+            ]
+        );
+    }
+}
@@ -0,0 +1,175 @@
+use std::rc::Rc;
+
+use crate::{
+    check::ensure_lambda_arity,
+    expression::elements::{Parameters, Pattern},
+    BuiltIn, Expression, Result, SmallString, Span,
+};
+
+/// A single VM instruction. Operands index into the tables of
+/// the [`Function`] being run, or into the stack of its call
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    /// Pushes a constant
+    Constant(u32),
+    /// Pushes an argument or `let` binding of the current call
+    GetLocal(u32),
+    /// Pushes a binding captured by the closure being run
+    GetCaptured(u32),
+    /// Pushes the value of the global binding with the given
+    /// name
+    GetGlobal(u32),
+    /// Binds the value on top of the stack to a global, leaving
+    /// it on the stack
+    DefineGlobal(u32),
+    /// Removes the given amount of values from under the top of
+    /// the stack, i.e. the bindings of a `let` once its body
+    /// has been evaluated
+    Slide(u32),
+    /// Pops the given amount of values, i.e. the bindings of a
+    /// `match` clause whose guard didn't hold
+    Pop(u32),
+    Jump(u32),
+    /// Pops the top of the stack, jumping if it's anything but
+    /// `true`
+    JumpUnlessTrue(u32),
+    /// Pops the top of the stack, jumping if it's `true`
+    JumpIfTrue(u32),
+    /// Fails unless the top of the stack is a boolean, as `and`
+    /// and `or` require
+    CheckBool,
+    /// Pops the given amount of values into a list
+    MakeList(u32),
+    /// Pops the given amount of key-value pairs into a map
+    MakeMap(u32),
+    /// Pops the given amount of values into a set
+    MakeSet(u32),
+    /// Fills in the quasiquote template at the given constant
+    /// index with the given amount of values, those of its
+    /// unquoted forms
+    Quasiquote(u32, u32),
+    /// Binds each identifier of the pattern at the given index
+    /// to a global, destructuring the value on top of the
+    /// stack, which is left there
+    DefineGlobals(u32),
+    /// Pops a value, pushing those the pattern at the given
+    /// index destructures it into
+    Destructure(u32),
+    /// Same as [`Op::Destructure`], but jumping without pushing
+    /// anything if the value doesn't match the pattern
+    Match(u32, u32),
+    /// Binds the lambda on top of the stack to a macro with the
+    /// given name, replacing it with `nil`
+    DefineMacro(u32),
+    /// Creates a closure out of the given prototype
+    MakeClosure(u32),
+    /// Calls the function found under the given amount of
+    /// arguments
+    Call(u32),
+    /// Same as [`Op::Call`], but reusing the current call's
+    /// stack frame, since nothing's left to do after it
+    TailCall(u32),
+    /// Applies a built-in to the given amount of arguments
+    CallBuiltIn(BuiltIn, u32),
+    /// Returns the value on top of the stack to the caller
+    Return,
+}
+
+/// The bytecode of a lambda body, or of a top-level expression
+#[derive(Debug, Default)]
+pub struct Function {
+    /// Amount of required arguments
+    pub(crate) arity: usize,
+    /// Where to start running, by the amount of optional
+    /// arguments given. The code in between binds those left
+    /// out to their defaults
+    pub(crate) entries: Vec<u32>,
+    /// Whether arguments past the optional ones are collected
+    /// into a list
+    pub(crate) variadic: bool,
+    pub(crate) code: Vec<Op>,
+    /// For each instruction, the span of the innermost
+    /// application it was compiled from, used to locate errors
+    pub(crate) spans: Vec<Option<Span>>,
+    pub(crate) constants: Vec<Expression>,
+    /// Names of the globals this function refers to
+    pub(crate) globals: Vec<SmallString>,
+    /// The patterns of the `def` and `let` bindings that
+    /// destructure their value
+    pub(crate) patterns: Vec<Pattern>,
+    /// The lambdas defined within this function
+    pub(crate) prototypes: Vec<Prototype>,
+}
+
+impl Function {
+    fn optional(&self) -> usize {
+        self.entries.len().saturating_sub(1)
+    }
+
+    pub(crate) fn ensure_arity(
+        &self,
+        received: usize,
+    ) -> Result<()> {
+        ensure_lambda_arity(
+            self.arity,
+            self.optional(),
+            self.variadic,
+            received,
+        )
+    }
+
+    /// Lays out the arguments found on the stack from `base` as
+    /// the code expects, collecting those left for the rest
+    /// parameter into a list. Returns where to start running
+    pub(crate) fn enter(
+        &self,
+        stack: &mut Vec<Expression>,
+        base: usize,
+    ) -> usize {
+        let optional = self.optional();
+        let given = stack.len() - base - self.arity;
+
+        // When optional arguments are left out, it's up to the
+        // code to push an empty list for the rest parameter
+        if self.variadic && given >= optional {
+            let rest =
+                stack.split_off(base + self.arity + optional);
+            stack.push(Expression::List(rest));
+        }
+
+        self.entries
+            .get(given.min(optional))
+            .map_or(0, |&ip| ip as usize)
+    }
+}
+
+// Compiled code is derived from the body of its lambda, which
+// already takes part in comparisons
+impl PartialEq for Function {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+/// What's needed to create a closure at runtime
+#[derive(Debug)]
+pub struct Prototype {
+    pub(crate) function: Rc<Function>,
+    /// The bindings of the enclosing function this closure
+    /// refers to, along with where to find them when it's
+    /// created
+    pub(crate) captures: Vec<(SmallString, Capture)>,
+    /// Kept so that closures remain usable as regular lambdas
+    pub(crate) parameters: Parameters,
+    pub(crate) body: Rc<Expression>,
+    pub(crate) span: Span,
+}
+
+/// Where a closure finds a binding it captures
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Capture {
+    /// A local of the enclosing function's call
+    Local(u32),
+    /// A binding the enclosing closure captured itself
+    Captured(u32),
+}